@@ -10,6 +10,9 @@ pub enum Error {
     Parse(String),
     Internal(String),
     WriteConflict,
+    ReadOnly,
+    // 数据文件损坏
+    Corruption(String),
 }
 
 impl From<std::num::ParseIntError> for Error {
@@ -62,6 +65,8 @@ impl Display for Error {
             Error::Parse(err) => write!(f, "parse error {}", err),
             Error::Internal(err) => write!(f, "internal error {}", err),
             Error::WriteConflict => write!(f, "write conflict, try transaction"),
+            Error::ReadOnly => write!(f, "cannot write in read-only transaction"),
+            Error::Corruption(err) => write!(f, "data corruption {}", err),
         }
     }
 }
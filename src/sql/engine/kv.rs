@@ -1,12 +1,14 @@
+use std::ops::{Bound, RangeBounds};
+
 use serde::{Deserialize, Serialize};
 
 use crate::{
     error::{Error, Result},
     sql::{
-        schema::Table,
-        types::{Row, Value},
+        schema::{Index, Table},
+        types::{Row, Rows, Value},
     },
-    storage::{self, engine::Engine as StorageEngine},
+    storage::{self, engine::Engine as StorageEngine, keycode::serialize_key},
 };
 
 use super::{Engine, Transaction};
@@ -32,12 +34,20 @@ impl<E: StorageEngine> KVEngine<E> {
     }
 }
 
-impl<E: StorageEngine> Engine for KVEngine<E> {
+impl<E: StorageEngine + 'static> Engine for KVEngine<E> {
     type Transaction = KVTransaction<E>;
 
     fn begin(&self) -> Result<Self::Transaction> {
         Ok(Self::Transaction::new(self.kv.begin()?))
     }
+
+    fn begin_read_only(&self) -> Result<Self::Transaction> {
+        Ok(Self::Transaction::new(self.kv.begin_read_only()?))
+    }
+
+    fn begin_as_of(&self, version: u64) -> Result<Self::Transaction> {
+        Ok(Self::Transaction::new(self.kv.begin_as_of(version)?))
+    }
 }
 
 // KV Transaction 定义，实际上对存储引擎中 MvccTransaction 的封装
@@ -49,58 +59,239 @@ impl<E: StorageEngine> KVTransaction<E> {
     pub fn new(txn: storage::mvcc::MvccTransaction<E>) -> Self {
         Self { txn }
     }
+
+    // 根据主键读取一行数据
+    fn load_row(&self, table_name: &str, id: &Value) -> Result<Option<Row>> {
+        let key = Key::Row(table_name.to_string(), id.clone());
+        Ok(self
+            .txn
+            .get(key.encode()?)?
+            .map(|v| bincode::deserialize(&v))
+            .transpose()?)
+    }
+
+    // 读取索引中某个值对应的全部主键
+    fn load_index(&self, table_name: &str, column: &str, value: &Value) -> Result<Vec<Value>> {
+        let key = Key::Index(table_name.to_string(), column.to_string(), value.clone());
+        Ok(self
+            .txn
+            .get(key.encode()?)?
+            .map(|v| bincode::deserialize(&v))
+            .transpose()?
+            .unwrap_or_default())
+    }
+
+    // 保存索引中某个值对应的主键，没有主键时删除这条索引
+    fn save_index(
+        &self,
+        table_name: &str,
+        column: &str,
+        value: &Value,
+        ids: Vec<Value>,
+    ) -> Result<()> {
+        let key = Key::Index(table_name.to_string(), column.to_string(), value.clone()).encode()?;
+        match ids.is_empty() {
+            true => self.txn.delete(key),
+            false => self.txn.set(key, bincode::serialize(&ids)?),
+        }
+    }
+
+    // 校验唯一索引，索引中已经存在其他主键时返回错误
+    // 在事务中读取，可以看到当前事务自己的修改，并发的写入会产生写冲突
+    fn check_unique(&self, table: &Table, index: &Index, row: &Row, id: &Value) -> Result<()> {
+        let value = &row[table.get_col_index(&index.column)?];
+        if !index.unique || *value == Value::Null {
+            return Ok(());
+        }
+        if self
+            .load_index(&table.name, &index.column, value)?
+            .iter()
+            .any(|v| v != id)
+        {
+            return Err(Error::Internal(format!(
+                "duplicate data {:?} for unique index {} in table {}",
+                value, index.name, table.name
+            )));
+        }
+        Ok(())
+    }
+
+    fn insert_index(&self, table: &Table, index: &Index, row: &Row, id: &Value) -> Result<()> {
+        let value = &row[table.get_col_index(&index.column)?];
+        let mut ids = self.load_index(&table.name, &index.column, value)?;
+        if !ids.contains(id) {
+            ids.push(id.clone());
+        }
+        self.save_index(&table.name, &index.column, value, ids)
+    }
+
+    fn delete_index(&self, table: &Table, index: &Index, row: &Row, id: &Value) -> Result<()> {
+        let value = &row[table.get_col_index(&index.column)?];
+        let mut ids = self.load_index(&table.name, &index.column, value)?;
+        ids.retain(|v| v != id);
+        self.save_index(&table.name, &index.column, value, ids)
+    }
 }
 
-impl<E: StorageEngine> Transaction for KVTransaction<E> {
+impl<E: StorageEngine + 'static> Transaction for KVTransaction<E> {
+    fn version(&self) -> u64 {
+        self.txn.version()
+    }
+
+    fn read_only(&self) -> bool {
+        self.txn.read_only()
+    }
+
     fn commit(&self) -> Result<()> {
-        Ok(())
+        self.txn.commit()
     }
 
     fn rollback(&self) -> Result<()> {
-        Ok(())
+        self.txn.rollback()
     }
 
     fn create_row(&mut self, table_name: String, row: Row) -> Result<()> {
         let table = self.must_get_table(table_name.clone())?;
         // 校验行的有效性
-        for (i, col) in table.columns.iter().enumerate() {
-            match row[i].datatype() {
-                None if col.nullable => {}
-                None => {
-                    return Err(Error::Internal(format!(
-                        "column {} cannot be null",
-                        col.name
-                    )))
-                }
-                Some(dt) if dt != col.datatype => {
-                    return Err(Error::Internal(format!(
-                        "column {} type mismatch",
-                        col.name
-                    )))
+        table.validate_row(&row)?;
+
+        // 判断主键是否已经存在
+        let pk = table.get_primary_key(&row)?;
+        let id = Key::Row(table_name.clone(), pk.clone()).encode()?;
+        if self.txn.get(id.clone())?.is_some() {
+            return Err(Error::Internal(format!(
+                "duplicate data for primary key {:?} in table {}",
+                pk, table_name
+            )));
+        }
+
+        // 先校验全部的唯一索引，再写入数据
+        for index in &table.indexes {
+            self.check_unique(&table, index, &row, &pk)?;
+        }
+
+        // 存放数据
+        let value = bincode::serialize(&row)?;
+        self.txn.set(id, value)?;
+
+        // 维护索引
+        for index in &table.indexes {
+            self.insert_index(&table, index, &row, &pk)?;
+        }
+
+        Ok(())
+    }
+
+    fn update_row(&mut self, table: &Table, id: &Value, row: Row) -> Result<()> {
+        // 主键发生了变化，需要删除原来的数据，再插入新的数据
+        // 先检查新主键是否重复，重复时原来的数据不会被删除
+        // 先删除是为了原来的数据不会和新数据在唯一索引上冲突
+        let new_id = table.get_primary_key(&row)?;
+        if new_id != *id {
+            if self.load_row(&table.name, &new_id)?.is_some() {
+                return Err(Error::Internal(format!(
+                    "duplicate data for primary key {:?} in table {}",
+                    new_id, table.name
+                )));
+            }
+            self.delete_row(table, id)?;
+            return self.create_row(table.name.clone(), row);
+        }
+
+        // 校验行的有效性
+        table.validate_row(&row)?;
+        for index in &table.indexes {
+            self.check_unique(table, index, &row, id)?;
+        }
+
+        // 索引的值发生了变化时，更新索引
+        let old_row = self.load_row(&table.name, id)?;
+        for index in &table.indexes {
+            let col = table.get_col_index(&index.column)?;
+            if let Some(old_row) = &old_row {
+                if old_row[col] == row[col] {
+                    continue;
                 }
-                _ => {}
+                self.delete_index(table, index, old_row, id)?;
             }
+            self.insert_index(table, index, &row, id)?;
         }
 
-        // 存放数据
-        // 暂时以第一列作为主键，一行数据的唯一标识，todo
-        let id = Key::Row(table_name.clone(), row[0].clone());
+        let key = Key::Row(table.name.clone(), new_id);
         let value = bincode::serialize(&row)?;
-        self.txn.set(bincode::serialize(&id)?, value)?;
+        self.txn.set(key.encode()?, value)?;
 
         Ok(())
     }
 
-    fn scan_table(&mut self, table_name: String) -> Result<Vec<Row>> {
+    fn delete_row(&mut self, table: &Table, id: &Value) -> Result<()> {
+        // 删除这行数据对应的索引
+        if let Some(row) = self.load_row(&table.name, id)? {
+            for index in &table.indexes {
+                self.delete_index(table, index, &row, id)?;
+            }
+        }
+        let key = Key::Row(table.name.clone(), id.clone());
+        self.txn.delete(key.encode()?)
+    }
+
+    fn scan_table(&mut self, table_name: String) -> Result<Rows> {
         let prefix = KeyPrefix::Row(table_name.clone());
-        let results = self.txn.scan_prefix(bincode::serialize(&prefix)?)?;
+        let results = self.txn.scan_prefix(serialize_key(&prefix)?)?;
+        Ok(Box::new(results.map(|result| {
+            let row: Row = bincode::deserialize(&result?.value)?;
+            Ok(row)
+        })))
+    }
 
+    fn get_row(&mut self, table: &Table, id: &Value) -> Result<Option<Row>> {
+        self.load_row(&table.name, id)
+    }
+
+    fn get_index(&mut self, table: &Table, column: &str, value: &Value) -> Result<Vec<Value>> {
+        self.load_index(&table.name, column, value)
+    }
+
+    fn scan_range(
+        &mut self,
+        table: &Table,
+        column: &str,
+        range: (Bound<Value>, Bound<Value>),
+    ) -> Result<Rows> {
+        // 主键列直接扫描数据
+        if table.get_primary_key_column()?.name == column {
+            let range = encode_range(
+                range,
+                |v| Key::Row(table.name.clone(), v),
+                KeyPrefix::Row(table.name.clone()),
+            )?;
+            let results = self.txn.scan(range)?;
+            return Ok(Box::new(results.map(|result| {
+                let row: Row = bincode::deserialize(&result?.value)?;
+                Ok(row)
+            })));
+        }
+
+        // 扫描索引，再根据主键读取数据
+        let range = encode_range(
+            range,
+            |v| Key::Index(table.name.clone(), column.to_string(), v),
+            KeyPrefix::Index(table.name.clone(), column.to_string()),
+        )?;
         let mut rows = Vec::new();
-        for result in results {
-            let row: Row = bincode::deserialize(&result.value)?;
-            rows.push(row);
+        for result in self.txn.scan(range)? {
+            let ids: Vec<Value> = bincode::deserialize(&result?.value)?;
+            for id in ids {
+                let row = self
+                    .load_row(&table.name, &id)?
+                    .ok_or(Error::Internal(format!(
+                        "row {:?} not found in table {}",
+                        id, table.name
+                    )))?;
+                rows.push(Ok(row));
+            }
         }
-        Ok(rows)
+        Ok(Box::new(rows.into_iter()))
     }
 
     fn create_table(&mut self, table: Table) -> Result<()> {
@@ -113,16 +304,30 @@ impl<E: StorageEngine> Transaction for KVTransaction<E> {
         }
 
         // 判断表的有效性
-        if table.columns.is_empty() {
-            return Err(Error::Internal(format!(
-                "table {} has no columns",
-                table.name
-            )));
+        table.validate()?;
+
+        let key = Key::Table(table.name.clone());
+        let value = bincode::serialize(&table)?;
+        self.txn.set(serialize_key(&key)?, value)?;
+
+        Ok(())
+    }
+
+    fn create_index(&mut self, table_name: String, index: Index) -> Result<()> {
+        let mut table = self.must_get_table(table_name.clone())?;
+        table.add_index(index.clone())?;
+
+        // 为表中已有的数据建立索引，唯一索引需要校验已有的数据
+        let rows = self.scan_table(table_name)?.collect::<Result<Vec<_>>>()?;
+        for row in rows {
+            let id = table.get_primary_key(&row)?;
+            self.check_unique(&table, &index, &row, &id)?;
+            self.insert_index(&table, &index, &row, &id)?;
         }
 
         let key = Key::Table(table.name.clone());
         let value = bincode::serialize(&table)?;
-        self.txn.set(bincode::serialize(&key)?, value)?;
+        self.txn.set(serialize_key(&key)?, value)?;
 
         Ok(())
     }
@@ -131,35 +336,96 @@ impl<E: StorageEngine> Transaction for KVTransaction<E> {
         let key = Key::Table(table_name);
         Ok(self
             .txn
-            .get(bincode::serialize(&key)?)?
+            .get(serialize_key(&key)?)?
             .map(|v| bincode::deserialize(&v))
             .transpose()?)
     }
 }
 
+// 使用 keycode 编码，编码后的顺序和原始值的顺序一致
 #[derive(Debug, Serialize, Deserialize)]
 enum Key {
     Table(String),
     Row(String, Value),
+    // 表名、列名、列的值，对应的是这个值所在行的主键
+    Index(String, String, Value),
 }
+
+impl Key {
+    // 主键和索引列的值 -0.0 和 0.0 相等，编码之前统一成 0.0
+    fn encode(self) -> Result<Vec<u8>> {
+        let key = match self {
+            Key::Row(table, value) => Key::Row(table, value.canonical()),
+            Key::Index(table, column, value) => Key::Index(table, column, value.canonical()),
+            key => key,
+        };
+        serialize_key(&key)
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 enum KeyPrefix {
     Table,
     Row(String),
+    Index(String, String),
+}
+
+// 把列值的范围转换为编码后的 key 的范围，没有边界时使用前缀作为边界
+fn encode_range(
+    range: (Bound<Value>, Bound<Value>),
+    to_key: impl Fn(Value) -> Key,
+    prefix: KeyPrefix,
+) -> Result<impl RangeBounds<Vec<u8>>> {
+    let prefix = serialize_key(&prefix)?;
+    let start = match range.0 {
+        Bound::Included(v) => Bound::Included(to_key(v).encode()?),
+        Bound::Excluded(v) => Bound::Excluded(to_key(v).encode()?),
+        Bound::Unbounded => Bound::Included(prefix.clone()),
+    };
+    let end = match range.1 {
+        Bound::Included(v) => Bound::Included(to_key(v).encode()?),
+        Bound::Excluded(v) => Bound::Excluded(to_key(v).encode()?),
+        Bound::Unbounded => {
+            // 前缀以字符串的结尾 0 0 结束，最后一个字节加一不会溢出
+            let mut end = prefix;
+            if let Some(last) = end.last_mut() {
+                *last += 1;
+            }
+            Bound::Excluded(end)
+        }
+    };
+    Ok((start, end))
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::{error::Result, sql::engine::Engine, storage::memory::MemoryEngine};
+    use crate::{
+        error::{Error, Result},
+        sql::{
+            engine::{Engine, Transaction},
+            exexutor::ResultSet,
+            parser::Parser,
+            plan::Plan,
+            types::Value,
+        },
+        storage::memory::MemoryEngine,
+    };
 
     use super::KVEngine;
 
+    // 在同一个事务中执行 sql
+    fn execute<T: Transaction + 'static>(txn: &mut T, sql: &str) -> Result<ResultSet> {
+        Plan::build(Parser::new(sql).parse()?, txn)?.executor(txn)
+    }
+
     #[test]
     fn test_create_table() -> Result<()> {
         let kvengine = KVEngine::new(MemoryEngine::new());
         let mut s = kvengine.session()?;
 
-        s.execute("create table t1 (a int, b text default 'vv', c integer default 100);")?;
+        s.execute(
+            "create table t1 (a int primary key, b text default 'vv', c integer default 100);",
+        )?;
         s.execute("insert into t1 values(1, 'a', 1);")?;
         s.execute("insert into t1 values(2, 'b');")?;
         s.execute("insert into t1(c, a) values(200, 3);")?;
@@ -168,4 +434,1052 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_session_commit() -> Result<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new());
+        let mut s1 = kvengine.session()?;
+        s1.execute("create table t1 (a int primary key, b text);")?;
+        s1.execute("insert into t1 values(1, 'a'), (2, 'b');")?;
+        s1.execute("update t1 set b = 'bb' where a = 2;")?;
+
+        // 已提交的数据对新的会话可见
+        let mut s2 = kvengine.session()?;
+        s2.execute("delete from t1 where a = 1;")?;
+        s2.execute("insert into t1 values(3, 'c');")?;
+
+        let mut s3 = kvengine.session()?;
+        let res = s3.execute("select * from t1;")?;
+        assert_eq!(
+            res,
+            ResultSet::Scan {
+                columns: vec!["a".to_string(), "b".to_string()],
+                rows: vec![
+                    vec![Value::Integer(2), Value::String("bb".to_string())],
+                    vec![Value::Integer(3), Value::String("c".to_string())],
+                ],
+            }
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_session_rollback() -> Result<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new());
+        let mut s = kvengine.session()?;
+        s.execute("create table t1 (a int primary key, b text not null);")?;
+        s.execute("insert into t1 values(1, 'a');")?;
+
+        // 第二行主键重复，整条语句失败，第一行也不会被插入
+        assert!(s
+            .execute("insert into t1 values(2, 'b'), (1, 'x');")
+            .is_err());
+        // 第二行违反非空约束，已经更新的行也会被回滚
+        assert!(s
+            .execute("insert into t1 values(3, 'c'), (4, null);")
+            .is_err());
+        // 第二行更新后的主键和第一行重复，第一行写入的新主键需要回滚
+        s.execute("insert into t1 values(5, 'e');")?;
+        assert!(s.execute("update t1 set a = 9;").is_err());
+        // 建表失败
+        assert!(s.execute("create table t2 (a int);").is_err());
+
+        let mut s2 = kvengine.session()?;
+        let res = s2.execute("select * from t1;")?;
+        assert_eq!(
+            res,
+            ResultSet::Scan {
+                columns: vec!["a".to_string(), "b".to_string()],
+                rows: vec![
+                    vec![Value::Integer(1), Value::String("a".to_string())],
+                    vec![Value::Integer(5), Value::String("e".to_string())],
+                ],
+            }
+        );
+        assert!(s2.execute("select * from t2;").is_err());
+
+        // 失败的语句不会影响之后的写入
+        s2.execute("insert into t1 values(2, 'b');")?;
+        let res = s.execute("select * from t1 where a = 2;")?;
+        assert_eq!(
+            res,
+            ResultSet::Scan {
+                columns: vec!["a".to_string(), "b".to_string()],
+                rows: vec![vec![Value::Integer(2), Value::String("b".to_string())]],
+            }
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_select_where() -> Result<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new());
+        let mut txn = kvengine.begin()?;
+
+        execute(
+            &mut txn,
+            "create table t1 (a int primary key, b text, c float);",
+        )?;
+        execute(&mut txn, "insert into t1 values(1, 'a', 1.5);")?;
+        execute(&mut txn, "insert into t1 values(2, 'b', null);")?;
+        execute(&mut txn, "insert into t1 values(3, 'c', 3.5);")?;
+
+        let rows = |result: ResultSet| -> Vec<Value> {
+            match result {
+                ResultSet::Scan { rows, .. } => rows.into_iter().map(|r| r[0].clone()).collect(),
+                _ => unreachable!(),
+            }
+        };
+        let ids = |v: Vec<i64>| v.into_iter().map(Value::Integer).collect::<Vec<_>>();
+
+        let res = execute(&mut txn, "select * from t1 where a >= 2;")?;
+        assert_eq!(rows(res), ids(vec![2, 3]));
+
+        let res = execute(&mut txn, "select * from t1 where a = 1 or b = 'c';")?;
+        assert_eq!(rows(res), ids(vec![1, 3]));
+
+        let res = execute(&mut txn, "select * from t1 where c > 1;")?;
+        assert_eq!(rows(res), ids(vec![1, 3]));
+
+        let res = execute(&mut txn, "select * from t1 where not c > 1;")?;
+        assert_eq!(rows(res), ids(vec![]));
+
+        let res = execute(&mut txn, "select * from t1 where c is null;")?;
+        assert_eq!(rows(res), ids(vec![2]));
+
+        let res = execute(&mut txn, "select * from t1 where c is not null and a != 1;")?;
+        assert_eq!(rows(res), ids(vec![3]));
+
+        let res = execute(
+            &mut txn,
+            "select * from t1 where (a < 2 or a > 2) and b <= 'b';",
+        )?;
+        assert_eq!(rows(res), ids(vec![1]));
+
+        assert!(execute(&mut txn, "select * from t1 where d = 1;").is_err());
+        assert!(execute(&mut txn, "select * from t1 where a = 'x';").is_err());
+        assert!(execute(&mut txn, "select * from t1 where a;").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_select_projection() -> Result<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new());
+        let mut txn = kvengine.begin()?;
+
+        execute(
+            &mut txn,
+            "create table t1 (a int primary key, b int, c float, d text);",
+        )?;
+        execute(&mut txn, "insert into t1 values(1, 10, 1.5, 'foo');")?;
+        execute(&mut txn, "insert into t1 values(2, null, 2.5, 'Bar');")?;
+
+        let res = execute(
+            &mut txn,
+            "select a, b + 1 as c, upper(d), c * 2 - a, -a from t1 where a > 0;",
+        )?;
+        assert_eq!(
+            res,
+            ResultSet::Scan {
+                columns: vec![
+                    "a".to_string(),
+                    "c".to_string(),
+                    "upper(d)".to_string(),
+                    "(c * 2) - a".to_string(),
+                    "-a".to_string(),
+                ],
+                rows: vec![
+                    vec![
+                        Value::Integer(1),
+                        Value::Integer(11),
+                        Value::String("FOO".to_string()),
+                        Value::Float(2.0),
+                        Value::Integer(-1),
+                    ],
+                    vec![
+                        Value::Integer(2),
+                        Value::Null,
+                        Value::String("BAR".to_string()),
+                        Value::Float(3.0),
+                        Value::Integer(-2),
+                    ],
+                ],
+            }
+        );
+
+        let res = execute(&mut txn, "select * from t1 where a = 1;")?;
+        assert_eq!(
+            res,
+            ResultSet::Scan {
+                columns: vec![
+                    "a".to_string(),
+                    "b".to_string(),
+                    "c".to_string(),
+                    "d".to_string(),
+                ],
+                rows: vec![vec![
+                    Value::Integer(1),
+                    Value::Integer(10),
+                    Value::Float(1.5),
+                    Value::String("foo".to_string()),
+                ]],
+            }
+        );
+
+        assert!(execute(&mut txn, "select e from t1;").is_err());
+        assert!(execute(&mut txn, "select a / 0 from t1;").is_err());
+        assert!(execute(&mut txn, "select d + 1 from t1;").is_err());
+        assert!(execute(&mut txn, "select foo(a) from t1;").is_err());
+        assert!(execute(&mut txn, "select foo(null) from t1;").is_err());
+        assert!(execute(&mut txn, "select upper(null, d) from t1;").is_err());
+        assert!(execute(&mut txn, "select abs(a - 9223372036854775807 - 2) from t1;").is_err());
+        let res = execute(&mut txn, "select upper(null), abs(-a) from t1 where a = 1;")?;
+        assert_eq!(
+            res,
+            ResultSet::Scan {
+                columns: vec!["upper(NULL)".to_string(), "abs(-a)".to_string()],
+                rows: vec![vec![Value::Null, Value::Integer(1)]],
+            }
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_update() -> Result<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new());
+        let mut txn = kvengine.begin()?;
+
+        execute(
+            &mut txn,
+            "create table t1 (a int primary key, b text not null, c float default 1.0);",
+        )?;
+        execute(&mut txn, "insert into t1 values(1, 'a', 1.5);")?;
+        execute(&mut txn, "insert into t1 values(2, 'b', 2.5);")?;
+        execute(&mut txn, "insert into t1 values(3, 'c', 3.5);")?;
+
+        let res = execute(&mut txn, "update t1 set b = 'bb', c = c + 1 where a = 2;")?;
+        assert_eq!(res, ResultSet::Update { count: 1 });
+
+        let res = execute(&mut txn, "update t1 set c = null where a >= 3;")?;
+        assert_eq!(res, ResultSet::Update { count: 1 });
+
+        let res = execute(&mut txn, "update t1 set c = 10.0 where a > 100;")?;
+        assert_eq!(res, ResultSet::Update { count: 0 });
+
+        // 修改主键
+        let res = execute(&mut txn, "update t1 set a = 10 where a = 1;")?;
+        assert_eq!(res, ResultSet::Update { count: 1 });
+
+        let res = execute(&mut txn, "select * from t1;")?;
+        assert_eq!(
+            res,
+            ResultSet::Scan {
+                columns: vec!["a".to_string(), "b".to_string(), "c".to_string()],
+                rows: vec![
+                    vec![
+                        Value::Integer(2),
+                        Value::String("bb".to_string()),
+                        Value::Float(3.5),
+                    ],
+                    vec![
+                        Value::Integer(3),
+                        Value::String("c".to_string()),
+                        Value::Null
+                    ],
+                    vec![
+                        Value::Integer(10),
+                        Value::String("a".to_string()),
+                        Value::Float(1.5),
+                    ],
+                ],
+            }
+        );
+
+        // 类型和是否为空的校验
+        assert!(execute(&mut txn, "update t1 set b = null;").is_err());
+        assert!(execute(&mut txn, "update t1 set c = 'x';").is_err());
+        assert!(execute(&mut txn, "update t1 set d = 1;").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_delete() -> Result<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new());
+        let mut txn = kvengine.begin()?;
+
+        execute(&mut txn, "create table t1 (a int primary key, b text);")?;
+        execute(
+            &mut txn,
+            "insert into t1 values(1, 'a'), (2, 'b'), (3, 'c');",
+        )?;
+
+        let res = execute(&mut txn, "delete from t1 where a = 2 or b = 'c';")?;
+        assert_eq!(res, ResultSet::Delete { count: 2 });
+
+        let res = execute(&mut txn, "delete from t1 where a > 100;")?;
+        assert_eq!(res, ResultSet::Delete { count: 0 });
+
+        let res = execute(&mut txn, "select * from t1;")?;
+        assert_eq!(
+            res,
+            ResultSet::Scan {
+                columns: vec!["a".to_string(), "b".to_string()],
+                rows: vec![vec![Value::Integer(1), Value::String("a".to_string())]],
+            }
+        );
+
+        // 删除之后可以重新插入
+        execute(&mut txn, "insert into t1 values(2, 'bb');")?;
+        let res = execute(&mut txn, "delete from t1;")?;
+        assert_eq!(res, ResultSet::Delete { count: 2 });
+
+        let res = execute(&mut txn, "select * from t1;")?;
+        assert_eq!(
+            res,
+            ResultSet::Scan {
+                columns: vec!["a".to_string(), "b".to_string()],
+                rows: vec![],
+            }
+        );
+
+        assert!(execute(&mut txn, "delete from t2;").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_primary_key() -> Result<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new());
+        let mut txn = kvengine.begin()?;
+
+        // 主键的校验
+        assert!(execute(&mut txn, "create table t1 (a int, b text);").is_err());
+        assert!(execute(
+            &mut txn,
+            "create table t1 (a int primary key, b int primary key);"
+        )
+        .is_err());
+        assert!(execute(
+            &mut txn,
+            "create table t1 (a int primary key null, b text);"
+        )
+        .is_err());
+
+        // 主键可以不是第一列
+        execute(&mut txn, "create table t1 (a int, b text primary key);")?;
+        execute(&mut txn, "insert into t1 values(1, 'x'), (1, 'y');")?;
+        assert!(execute(&mut txn, "insert into t1 values(2, 'x');").is_err());
+        assert!(execute(&mut txn, "insert into t1 values(2, null);").is_err());
+
+        // 更新主键时同样不能重复
+        assert!(execute(&mut txn, "update t1 set b = 'y' where b = 'x';").is_err());
+        execute(&mut txn, "update t1 set b = 'z' where b = 'x';")?;
+
+        let res = execute(&mut txn, "select * from t1;")?;
+        assert_eq!(
+            res,
+            ResultSet::Scan {
+                columns: vec!["a".to_string(), "b".to_string()],
+                rows: vec![
+                    vec![Value::Integer(1), Value::String("y".to_string())],
+                    vec![Value::Integer(1), Value::String("z".to_string())],
+                ],
+            }
+        );
+
+        // 浮点数主键中 -0.0 和 0.0 是同一个值
+        execute(&mut txn, "create table t2 (a float primary key, b int);")?;
+        execute(&mut txn, "insert into t2 values(-0.0, 1);")?;
+        assert!(execute(&mut txn, "insert into t2 values(0.0, 2);").is_err());
+        execute(&mut txn, "insert into t2 values(1.5, 3);")?;
+        assert!(execute(&mut txn, "update t2 set a = 0.0 where b = 3;").is_err());
+        execute(&mut txn, "update t2 set a = 0.0 where b = 1;")?;
+        let res = execute(&mut txn, "select b from t2 where a = -0.0;")?;
+        assert_eq!(
+            res,
+            ResultSet::Scan {
+                columns: vec!["b".to_string()],
+                rows: vec![vec![Value::Integer(1)]],
+            }
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_session_transaction() -> Result<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new());
+        let mut s1 = kvengine.session()?;
+        let mut s2 = kvengine.session()?;
+        s1.execute("create table t1 (a int primary key, b text);")?;
+
+        // 未提交的事务对其他会话不可见
+        let version = match s1.execute("begin;")? {
+            ResultSet::Begin { version, .. } => version,
+            _ => unreachable!(),
+        };
+        s1.execute("insert into t1 values(1, 'a');")?;
+        s1.execute("insert into t1 values(2, 'b');")?;
+        let rows = |res: ResultSet| match res {
+            ResultSet::Scan { rows, .. } => rows.len(),
+            _ => unreachable!(),
+        };
+        assert_eq!(rows(s1.execute("select * from t1;")?), 2);
+        assert_eq!(rows(s2.execute("select * from t1;")?), 0);
+        assert!(s1.execute("begin;").is_err());
+        assert_eq!(s1.execute("commit;")?, ResultSet::Commit { version });
+        assert_eq!(rows(s2.execute("select * from t1;")?), 2);
+        assert!(s1.execute("commit;").is_err());
+        assert!(s1.execute("rollback;").is_err());
+
+        // 回滚之后所有的修改都不可见
+        let version = match s1.execute("begin;")? {
+            ResultSet::Begin { version, .. } => version,
+            _ => unreachable!(),
+        };
+        s1.execute("delete from t1 where a = 1;")?;
+        s1.execute("insert into t1 values(3, 'c');")?;
+        assert_eq!(s1.execute("rollback;")?, ResultSet::Rollback { version });
+        assert_eq!(rows(s1.execute("select * from t1;")?), 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_session_write_conflict() -> Result<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new());
+        let mut s1 = kvengine.session()?;
+        let mut s2 = kvengine.session()?;
+        s1.execute("create table t1 (a int primary key, b text);")?;
+        s1.execute("insert into t1 values(1, 'a');")?;
+
+        s1.execute("begin;")?;
+        s2.execute("begin;")?;
+        s1.execute("update t1 set b = 'x' where a = 1;")?;
+        s1.execute("insert into t1 values(2, 'b');")?;
+        s2.execute("insert into t1 values(3, 'c');")?;
+
+        // 写冲突之后事务被回滚，会话回到没有事务的状态
+        assert_eq!(
+            s2.execute("update t1 set b = 'y' where a = 1;"),
+            Err(Error::WriteConflict)
+        );
+        assert!(s2.execute("commit;").is_err());
+        s1.execute("commit;")?;
+
+        let res = s2.execute("select * from t1;")?;
+        assert_eq!(
+            res,
+            ResultSet::Scan {
+                columns: vec!["a".to_string(), "b".to_string()],
+                rows: vec![
+                    vec![Value::Integer(1), Value::String("x".to_string())],
+                    vec![Value::Integer(2), Value::String("b".to_string())],
+                ],
+            }
+        );
+
+        // 会话关闭时，未提交的事务被回滚
+        s2.execute("begin;")?;
+        s2.execute("delete from t1;")?;
+        drop(s2);
+        s1.execute("update t1 set b = 'z';")?;
+        assert_eq!(
+            rows_of(s1.execute("select b from t1;")?),
+            vec![
+                vec![Value::String("z".to_string())],
+                vec![Value::String("z".to_string())]
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_session_statement_failure() -> Result<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new());
+        let mut s = kvengine.session()?;
+        s.execute("create table t1 (a int primary key, b text);")?;
+        s.execute("create unique index idx_b on t1 (b);")?;
+        s.execute("insert into t1 values(1, 'a');")?;
+        let all = vec![vec![Value::Integer(1), Value::String("a".to_string())]];
+
+        // 插入多行时中途失败，前面写入的数据不能被提交
+        s.execute("begin;")?;
+        assert!(s
+            .execute("insert into t1 values(2, 'b'), (1, 'x');")
+            .is_err());
+        assert!(s.execute("select * from t1;").is_err());
+        assert!(s.execute("commit;").is_err());
+        assert_eq!(rows_of(s.execute("select * from t1;")?), all);
+
+        // 修改主键时唯一索引检查失败，回滚之后可以开启新的事务
+        s.execute("insert into t1 values(2, 'b');")?;
+        s.execute("begin;")?;
+        assert!(s
+            .execute("update t1 set a = 3, b = 'a' where a = 2;")
+            .is_err());
+        assert!(s.execute("insert into t1 values(4, 'd');").is_err());
+        s.execute("rollback;")?;
+        s.execute("begin;")?;
+        s.execute("delete from t1 where a = 2;")?;
+        s.execute("commit;")?;
+        assert_eq!(rows_of(s.execute("select * from t1;")?), all);
+        Ok(())
+    }
+
+    fn rows_of(res: ResultSet) -> Vec<Vec<Value>> {
+        match res {
+            ResultSet::Scan { rows, .. } => rows,
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_session_read_only() -> Result<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new());
+        let mut s1 = kvengine.session()?;
+        let mut s2 = kvengine.session()?;
+        s1.execute("create table t1 (a int primary key, b text);")?;
+        s1.execute("insert into t1 values(1, 'a');")?;
+
+        let version = match s1.execute("begin;")? {
+            ResultSet::Begin { version, read_only } => {
+                assert!(!read_only);
+                version
+            }
+            _ => unreachable!(),
+        };
+        s1.execute("update t1 set b = 'aa';")?;
+        s1.execute("insert into t1 values(2, 'b');")?;
+        s1.execute("commit;")?;
+        s1.execute("delete from t1 where a = 1;")?;
+
+        // 只读事务中不能写入数据
+        match s2.execute("begin read only;")? {
+            ResultSet::Begin { read_only, .. } => assert!(read_only),
+            _ => unreachable!(),
+        }
+        assert_eq!(
+            rows_of(s2.execute("select * from t1;")?),
+            vec![vec![Value::Integer(2), Value::String("b".to_string())]]
+        );
+        assert_eq!(
+            s2.execute("insert into t1 values(3, 'c');"),
+            Err(Error::ReadOnly)
+        );
+        s2.execute("commit;")?;
+
+        // 读取历史版本的数据
+        let sql = format!("begin read only as of system time {};", version);
+        s2.execute(&sql)?;
+        assert_eq!(
+            rows_of(s2.execute("select * from t1;")?),
+            vec![vec![Value::Integer(1), Value::String("a".to_string())]]
+        );
+        assert_eq!(s2.execute("delete from t1;"), Err(Error::ReadOnly));
+        s2.execute("rollback;")?;
+
+        let sql = format!("begin read only as of system time {};", version + 1);
+        s2.execute(&sql)?;
+        assert_eq!(
+            rows_of(s2.execute("select * from t1;")?),
+            vec![
+                vec![Value::Integer(1), Value::String("aa".to_string())],
+                vec![Value::Integer(2), Value::String("b".to_string())],
+            ]
+        );
+        s2.execute("commit;")?;
+
+        assert!(s2
+            .execute("begin read only as of system time 1000;")
+            .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_index() -> Result<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new());
+        let mut txn = kvengine.begin()?;
+        execute(
+            &mut txn,
+            "create table t1 (a int primary key, b text, c int);",
+        )?;
+        execute(
+            &mut txn,
+            "insert into t1 values (1, 'x', 10), (2, 'y', 10), (3, 'x', null);",
+        )?;
+
+        // 为已有的数据建立索引
+        assert_eq!(
+            execute(&mut txn, "create index idx_b on t1 (b);")?,
+            ResultSet::CreateIndex {
+                index_name: "idx_b".to_string()
+            }
+        );
+        assert!(execute(&mut txn, "create index idx_b on t1 (c);").is_err());
+        assert!(execute(&mut txn, "create index idx_d on t1 (d);").is_err());
+        assert!(execute(&mut txn, "create unique index idx_c on t1 (c);").is_err());
+        let table = txn.must_get_table("t1".to_string())?;
+        assert_eq!(table.indexes.len(), 1);
+
+        let ids = |txn: &super::KVTransaction<MemoryEngine>, col: &str, v: Value| {
+            txn.load_index("t1", col, &v)
+        };
+        let x = Value::String("x".to_string());
+        assert_eq!(
+            ids(&txn, "b", x.clone())?,
+            vec![Value::Integer(1), Value::Integer(3)]
+        );
+
+        // 插入、更新、删除时维护索引
+        execute(&mut txn, "insert into t1 values (4, 'x', 40);")?;
+        execute(&mut txn, "update t1 set b = 'z' where a = 1;")?;
+        execute(&mut txn, "delete from t1 where a = 3;")?;
+        assert_eq!(ids(&txn, "b", x.clone())?, vec![Value::Integer(4)]);
+        assert_eq!(
+            ids(&txn, "b", Value::String("z".to_string()))?,
+            vec![Value::Integer(1)]
+        );
+        // 修改主键
+        execute(&mut txn, "update t1 set a = 5 where a = 4;")?;
+        assert_eq!(ids(&txn, "b", x)?, vec![Value::Integer(5)]);
+
+        // 唯一索引，NULL 可以重复
+        execute(&mut txn, "update t1 set c = 20 where a = 2;")?;
+        execute(&mut txn, "create unique index idx_c on t1 (c);")?;
+        assert!(execute(&mut txn, "insert into t1 values (6, 'w', 20);").is_err());
+        assert!(execute(&mut txn, "update t1 set c = 40 where a = 1;").is_err());
+        execute(
+            &mut txn,
+            "insert into t1 values (6, 'w', null), (7, 'w', null);",
+        )?;
+        // 唯一索引的值不变，或者只修改主键，不会产生冲突
+        execute(&mut txn, "update t1 set b = 'v' where a = 1;")?;
+        execute(&mut txn, "update t1 set a = 8 where a = 1;")?;
+        assert_eq!(ids(&txn, "c", Value::Integer(10))?, vec![Value::Integer(8)]);
+        txn.commit()?;
+
+        // 并发的事务写入相同的唯一值，后写入的事务产生写冲突
+        let mut t1 = kvengine.begin()?;
+        let mut t2 = kvengine.begin()?;
+        execute(&mut t1, "insert into t1 values (9, 'u', 90);")?;
+        assert_eq!(
+            execute(&mut t2, "insert into t1 values (10, 'u', 90);"),
+            Err(Error::WriteConflict)
+        );
+        t1.commit()?;
+        t2.rollback()?;
+
+        let mut txn = kvengine.begin()?;
+        assert!(execute(&mut txn, "insert into t1 values (10, 'u', 90);").is_err());
+
+        // 浮点数索引中 -0.0 和 0.0 是同一个值
+        execute(&mut txn, "create table g (a int primary key, f float);")?;
+        execute(&mut txn, "create unique index gi on g (f);")?;
+        execute(&mut txn, "insert into g values (1, -0.0);")?;
+        assert!(execute(&mut txn, "insert into g values (2, 0.0);").is_err());
+        execute(&mut txn, "insert into g values (2, 1.5);")?;
+        assert!(execute(&mut txn, "update g set f = 0.0 where a = 2;").is_err());
+        assert_eq!(
+            txn.load_index("g", "f", &Value::Float(0.0))?,
+            vec![Value::Integer(1)]
+        );
+        assert_eq!(
+            execute(&mut txn, "select a from g where f = 0.0;")?,
+            ResultSet::Scan {
+                columns: vec!["a".to_string()],
+                rows: vec![vec![Value::Integer(1)]],
+            }
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_index_query() -> Result<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new());
+        let mut s = kvengine.session()?;
+        s.execute("create table t1 (a int primary key, b text, c int);")?;
+        s.execute("create index idx_b on t1 (b);")?;
+        s.execute(
+            "insert into t1 values (1, 'x', 10), (2, 'y', 20), (3, 'x', 30), (4, null, 40), (5, 'z', 50);",
+        )?;
+        let ids = |rows: Vec<Vec<Value>>| {
+            rows.into_iter()
+                .map(|row| row[0].clone())
+                .collect::<Vec<_>>()
+        };
+        let mut query = |sql: &str| -> Result<Vec<Value>> { Ok(ids(rows_of(s.execute(sql)?))) };
+        let int = |v: Vec<i64>| v.into_iter().map(Value::Integer).collect::<Vec<_>>();
+
+        assert_eq!(query("select * from t1 where a = 3;")?, int(vec![3]));
+        assert_eq!(query("select * from t1 where a = 6;")?, int(vec![]));
+        assert_eq!(query("select * from t1 where b = 'x';")?, int(vec![1, 3]));
+        assert_eq!(
+            query("select * from t1 where b = 'x' and c > 10;")?,
+            int(vec![3])
+        );
+        assert_eq!(
+            query("select * from t1 where a > 1 and a < 4;")?,
+            int(vec![2, 3])
+        );
+        assert_eq!(query("select * from t1 where a >= 4;")?, int(vec![4, 5]));
+        assert_eq!(
+            query("select * from t1 where a > 4 and a < 2;")?,
+            int(vec![])
+        );
+        // 按照索引列的顺序返回，NULL 不满足范围条件
+        assert_eq!(
+            query("select * from t1 where b >= 'x';")?,
+            int(vec![1, 3, 2, 5])
+        );
+        assert_eq!(query("select * from t1 where b < 'y';")?, int(vec![1, 3]));
+
+        // 通过主键和索引更新、删除数据
+        s.execute("update t1 set b = 'w' where a = 1;")?;
+        s.execute("delete from t1 where b = 'x';")?;
+        let mut query = |sql: &str| -> Result<Vec<Value>> { Ok(ids(rows_of(s.execute(sql)?))) };
+        assert_eq!(query("select * from t1 where b = 'w';")?, int(vec![1]));
+        assert_eq!(query("select * from t1 where b = 'x';")?, int(vec![]));
+        assert_eq!(query("select * from t1;")?, int(vec![1, 2, 4, 5]));
+
+        // -0.0 和 0.0 相等，使用主键和索引查询的结果和全表扫描一致
+        s.execute("create table f (a float primary key, b int, c float);")?;
+        s.execute("create index idx_fc on f (c);")?;
+        s.execute("insert into f values (-0.0, 1, 0.0), (0.5, 2, -0.0), (-1.5, 3, 1.5);")?;
+        assert!(s.execute("insert into f values (0.0, 4, 2.5);").is_err());
+        for column in ["a", "c"] {
+            for op in ["=", ">=", ">", "<=", "<"] {
+                for value in ["0.0", "-0.0"] {
+                    let mut query = |expr: String| -> Result<Vec<Value>> {
+                        let sql = format!("select b from f where {} {} {};", expr, op, value);
+                        let mut rows = ids(rows_of(s.execute(&sql)?));
+                        rows.sort_by(|a, b| a.total_cmp(b));
+                        Ok(rows)
+                    };
+                    assert_eq!(
+                        query(column.to_string())?,
+                        query(format!("{} + 0.0", column))?,
+                        "{} {} {}",
+                        column,
+                        op,
+                        value
+                    );
+                }
+            }
+        }
+        assert_eq!(
+            ids(rows_of(s.execute("select b from f where a = 0.0;")?)),
+            int(vec![1])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_join() -> Result<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new());
+        let mut s = kvengine.session()?;
+        s.execute("create table t1 (a int primary key, b text);")?;
+        s.execute("create table t2 (c int primary key, a int);")?;
+        s.execute("insert into t1 values (1, 'x'), (2, 'y'), (3, 'z');")?;
+        s.execute("insert into t2 values (10, 1), (11, 1), (12, 3), (13, null), (14, 4);")?;
+        let int = |v: i64| Value::Integer(v);
+
+        let res = s.execute("select t1.a, t2.c from t1 join t2 on t1.a = t2.a;")?;
+        assert_eq!(
+            res,
+            ResultSet::Scan {
+                columns: vec!["a".to_string(), "c".to_string()],
+                rows: vec![
+                    vec![int(1), int(10)],
+                    vec![int(1), int(11)],
+                    vec![int(3), int(12)],
+                ],
+            }
+        );
+
+        // 外连接中没有匹配的行补 NULL
+        let rows = rows_of(s.execute("select t1.a, c from t1 left join t2 on t2.a = t1.a;")?);
+        assert_eq!(
+            rows,
+            vec![
+                vec![int(1), int(10)],
+                vec![int(1), int(11)],
+                vec![int(2), Value::Null],
+                vec![int(3), int(12)],
+            ]
+        );
+        let rows = rows_of(s.execute("select b, c from t1 right outer join t2 on t1.a = t2.a;")?);
+        assert_eq!(
+            rows,
+            vec![
+                vec![Value::String("x".to_string()), int(10)],
+                vec![Value::String("x".to_string()), int(11)],
+                vec![Value::String("z".to_string()), int(12)],
+                vec![Value::Null, int(13)],
+                vec![Value::Null, int(14)],
+            ]
+        );
+
+        // 非等值条件以及没有条件的连接
+        let rows = rows_of(
+            s.execute("select x.a, c from t1 as x inner join t2 y on y.a > x.a where c < 14;")?,
+        );
+        assert_eq!(rows, vec![vec![int(1), int(12)], vec![int(2), int(12)]]);
+        assert_eq!(rows_of(s.execute("select * from t1, t2;")?).len(), 15);
+        assert_eq!(
+            rows_of(s.execute("select * from t1 cross join t2;")?)[0].len(),
+            4
+        );
+
+        // 同一个表使用不同的别名
+        let rows = rows_of(s.execute("select x.b, y.b from t1 x join t1 y on x.a + 1 = y.a;")?);
+        assert_eq!(
+            rows,
+            vec![
+                vec![
+                    Value::String("x".to_string()),
+                    Value::String("y".to_string())
+                ],
+                vec![
+                    Value::String("y".to_string()),
+                    Value::String("z".to_string())
+                ],
+            ]
+        );
+
+        // 单表查询中也可以使用带表名的列
+        s.execute("update t1 set b = 'w' where t1.a = 1;")?;
+        let rows = rows_of(s.execute("select t.b from t1 t where t.a = 1;")?);
+        assert_eq!(rows, vec![vec![Value::String("w".to_string())]]);
+
+        assert!(s
+            .execute("select a from t1 join t2 on t1.a = t2.a;")
+            .is_err());
+        assert!(s.execute("select * from t1 join t2 on c = a;").is_err());
+        assert!(s.execute("select * from t1 join t1 on true;").is_err());
+        assert!(s.execute("select t2.b from t1 join t2 on true;").is_err());
+        assert!(s.execute("select * from t1 join t2 on c;").is_err());
+
+        // 浮点数的等值连接，-0.0 和 0.0 相等
+        s.execute("create table f1 (a int primary key, x float);")?;
+        s.execute("create table f2 (b int primary key, y float);")?;
+        s.execute("insert into f1 values (1, 0.0), (2, -0.0), (3, 1.5);")?;
+        s.execute("insert into f2 values (1, -0.0), (2, 1.5), (3, 2.5);")?;
+        let expected = vec![
+            vec![int(1), int(1)],
+            vec![int(2), int(1)],
+            vec![int(3), int(2)],
+        ];
+        let rows = rows_of(s.execute("select a, b from f1 join f2 on x = y;")?);
+        assert_eq!(rows, expected);
+        let rows = rows_of(s.execute("select a, b from f1 join f2 on x = y + 0.0 * a;")?);
+        assert_eq!(rows, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_aggregate() -> Result<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new());
+        let mut s = kvengine.session()?;
+        s.execute("create table t1 (a int primary key, b text, c int, d float);")?;
+
+        // 空表上 count 返回 0，其他聚合函数返回 NULL
+        let res =
+            s.execute("select count(*), count(c), sum(c), avg(d), min(b), max(a) from t1;")?;
+        assert_eq!(
+            res,
+            ResultSet::Scan {
+                columns: vec![
+                    "count(*)".to_string(),
+                    "count(c)".to_string(),
+                    "sum(c)".to_string(),
+                    "avg(d)".to_string(),
+                    "min(b)".to_string(),
+                    "max(a)".to_string(),
+                ],
+                rows: vec![vec![
+                    Value::Integer(0),
+                    Value::Integer(0),
+                    Value::Null,
+                    Value::Null,
+                    Value::Null,
+                    Value::Null,
+                ]],
+            }
+        );
+        assert!(rows_of(s.execute("select b, count(*) from t1 group by b;")?).is_empty());
+
+        s.execute(
+            "insert into t1 values (1, 'x', 10, 1.5), (2, 'y', null, 2.5), (3, 'x', 20, null), (4, null, 30, 0.5), (5, 'y', null, null);",
+        )?;
+        let str = |s: &str| Value::String(s.to_string());
+
+        // NULL 不参与计算，整数求和的结果是整数，平均值是浮点数
+        let rows = rows_of(s.execute(
+            "select count(*), count(c), sum(c), avg(c), min(b), max(d), sum(c + d) from t1;",
+        )?);
+        assert_eq!(
+            rows,
+            vec![vec![
+                Value::Integer(5),
+                Value::Integer(3),
+                Value::Integer(60),
+                Value::Float(20.0),
+                str("x"),
+                Value::Float(2.5),
+                Value::Float(42.0),
+            ]]
+        );
+
+        // 按照分组的值排序输出，NULL 单独作为一组
+        let res = s.execute("select b, count(c) as n, sum(c) * 2, avg(a) from t1 group by b;")?;
+        assert_eq!(
+            res,
+            ResultSet::Scan {
+                columns: vec![
+                    "b".to_string(),
+                    "n".to_string(),
+                    "sum(c) * 2".to_string(),
+                    "avg(a)".to_string(),
+                ],
+                rows: vec![
+                    vec![
+                        Value::Null,
+                        Value::Integer(1),
+                        Value::Integer(60),
+                        Value::Float(4.0)
+                    ],
+                    vec![
+                        str("x"),
+                        Value::Integer(2),
+                        Value::Integer(60),
+                        Value::Float(2.0)
+                    ],
+                    vec![str("y"), Value::Integer(0), Value::Null, Value::Float(3.5)],
+                ],
+            }
+        );
+
+        let rows = rows_of(s.execute(
+            "select b, max(a) from t1 where a > 1 group by b having count(*) > 1 or b is null;",
+        )?);
+        assert_eq!(
+            rows,
+            vec![
+                vec![Value::Null, Value::Integer(4)],
+                vec![str("y"), Value::Integer(5)],
+            ]
+        );
+        let rows = rows_of(s.execute("select count(*) from t1 having sum(a) > 100;")?);
+        assert!(rows.is_empty());
+        let rows = rows_of(s.execute("select c > 15 as big, count(*) from t1 group by c > 15;")?);
+        assert_eq!(
+            rows,
+            vec![
+                vec![Value::Null, Value::Integer(2)],
+                vec![Value::Boolean(false), Value::Integer(1)],
+                vec![Value::Boolean(true), Value::Integer(2)],
+            ]
+        );
+
+        // -0.0 和 0.0 属于同一个分组
+        s.execute("create table g (a int primary key, f float);")?;
+        s.execute("insert into g values (1, -0.0), (2, 0.0), (3, 1.5);")?;
+        let rows = rows_of(s.execute("select f, count(*) from g group by f;")?);
+        assert_eq!(
+            rows,
+            vec![
+                vec![Value::Float(0.0), Value::Integer(2)],
+                vec![Value::Float(1.5), Value::Integer(1)],
+            ]
+        );
+
+        assert!(s.execute("select sum(b) from t1;").is_err());
+        assert!(s.execute("select b, count(*) from t1;").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_order_by() -> Result<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new());
+        let mut s = kvengine.session()?;
+        s.execute("create table t1 (a int primary key, b text, c int, d float);")?;
+        s.execute(
+            "insert into t1 values (1, 'x', 30, 1.5), (2, 'y', null, -2.5), (3, 'x', 10, null), (4, null, 20, 0.5), (5, 'y', 10, 3.0);",
+        )?;
+        let ids = |rows: Vec<Vec<Value>>| {
+            rows.into_iter()
+                .map(|row| row[0].clone())
+                .collect::<Vec<_>>()
+        };
+        let mut query = |sql: &str| -> Result<Vec<Value>> { Ok(ids(rows_of(s.execute(sql)?))) };
+        let int = |v: Vec<i64>| v.into_iter().map(Value::Integer).collect::<Vec<_>>();
+
+        // 升序时 NULL 在最后，降序时 NULL 在最前，相同的值保持原来的顺序
+        assert_eq!(
+            query("select a from t1 order by c;")?,
+            int(vec![3, 5, 4, 1, 2])
+        );
+        assert_eq!(
+            query("select a from t1 order by c desc;")?,
+            int(vec![2, 1, 4, 3, 5])
+        );
+        assert_eq!(
+            query("select * from t1 order by c nulls first, a desc;")?,
+            int(vec![2, 5, 3, 4, 1])
+        );
+        assert_eq!(
+            query("select a from t1 order by b desc, d;")?,
+            int(vec![4, 2, 5, 1, 3])
+        );
+        assert_eq!(
+            query("select a from t1 order by c * -1 asc nulls first;")?,
+            int(vec![2, 1, 4, 3, 5])
+        );
+
+        // 使用别名以及没有出现在 select 中的列排序
+        let res = s.execute("select a, d * 2 as dd from t1 order by dd desc limit 2;")?;
+        assert_eq!(
+            res,
+            ResultSet::Scan {
+                columns: vec!["a".to_string(), "dd".to_string()],
+                rows: vec![
+                    vec![Value::Integer(3), Value::Null],
+                    vec![Value::Integer(5), Value::Float(6.0)],
+                ],
+            }
+        );
+        let rows = rows_of(s.execute("select b from t1 order by a desc limit 2 offset 1;")?);
+        assert_eq!(
+            rows,
+            vec![vec![Value::Null], vec![Value::String("x".to_string())]]
+        );
+
+        let rows =
+            rows_of(s.execute("select b, count(*) as n from t1 group by b order by n desc, b;")?);
+        assert_eq!(
+            rows,
+            vec![
+                vec![Value::String("x".to_string()), Value::Integer(2)],
+                vec![Value::String("y".to_string()), Value::Integer(2)],
+                vec![Value::Null, Value::Integer(1)],
+            ]
+        );
+
+        let mut query = |sql: &str| -> Result<Vec<Value>> { Ok(ids(rows_of(s.execute(sql)?))) };
+        assert_eq!(query("select a from t1 limit 2;")?, int(vec![1, 2]));
+        assert_eq!(query("select a from t1 offset 3;")?, int(vec![4, 5]));
+        assert_eq!(query("select a from t1 limit 0;")?, int(vec![]));
+        assert_eq!(query("select a from t1 limit 2 offset 10;")?, int(vec![]));
+        assert_eq!(
+            query("select x.a from t1 x join t1 y on x.c = y.c order by y.a desc, x.a;")?,
+            int(vec![3, 5, 4, 3, 5, 1])
+        );
+        assert!(query("select a from t1 order by e;").is_err());
+        Ok(())
+    }
 }
@@ -1,17 +1,33 @@
+use std::ops::Bound;
+
 use crate::error::{Error, Result};
 
-use super::{exexutor::ResultSet, parser::Parser, plan::Plan, schema::Table, types::Row};
+use super::{
+    exexutor::ResultSet,
+    parser::{ast, Parser},
+    plan::Plan,
+    schema::{Index, Table},
+    types::{Row, Rows, Value},
+};
 
-mod kv;
+pub mod kv;
 
 pub trait Engine: Clone {
-    type Transaction: Transaction;
+    type Transaction: Transaction + 'static;
 
     fn begin(&self) -> Result<Self::Transaction>;
 
+    // 开启只读事务
+    fn begin_read_only(&self) -> Result<Self::Transaction>;
+
+    // 开启只读事务，读取指定版本的历史数据
+    fn begin_as_of(&self, version: u64) -> Result<Self::Transaction>;
+
     fn session(&self) -> Result<Session<Self>> {
         Ok(Session {
             engine: self.clone(),
+            txn: None,
+            aborted: false,
         })
     }
 }
@@ -21,16 +37,46 @@ pub trait Engine: Clone {
  * 底层可以接入 KV 存储引擎
  */
 pub trait Transaction {
+    // 事务的版本号
+    fn version(&self) -> u64;
+
+    // 是否是只读事务
+    fn read_only(&self) -> bool;
+
     fn commit(&self) -> Result<()>;
 
     fn rollback(&self) -> Result<()>;
 
     fn create_row(&mut self, table: String, row: Row) -> Result<()>;
 
-    fn scan_table(&mut self, table_name: String) -> Result<Vec<Row>>;
+    // 更新一行数据，id 是更新前这行数据的主键
+    fn update_row(&mut self, table: &Table, id: &Value, row: Row) -> Result<()>;
+
+    // 根据主键删除一行数据
+    fn delete_row(&mut self, table: &Table, id: &Value) -> Result<()>;
+
+    // 扫描表中的数据，返回的迭代器按需读取数据行
+    fn scan_table(&mut self, table_name: String) -> Result<Rows>;
+
+    // 根据主键读取一行数据
+    fn get_row(&mut self, table: &Table, id: &Value) -> Result<Option<Row>>;
+
+    // 读取索引中某个值对应的全部主键
+    fn get_index(&mut self, table: &Table, column: &str, value: &Value) -> Result<Vec<Value>>;
+
+    // 按照主键或者索引列的范围读取数据，返回的数据按照这一列排序
+    fn scan_range(
+        &mut self,
+        table: &Table,
+        column: &str,
+        range: (Bound<Value>, Bound<Value>),
+    ) -> Result<Rows>;
 
     fn create_table(&mut self, table_name: Table) -> Result<()>;
 
+    // 在表上创建索引，并为表中已有的数据建立索引
+    fn create_index(&mut self, table_name: String, index: Index) -> Result<()>;
+
     fn get_table(&mut self, table_name: String) -> Result<Option<Table>>;
 
     fn must_get_table(&mut self, table_name: String) -> Result<Table> {
@@ -41,21 +87,88 @@ pub trait Transaction {
 
 pub struct Session<E: Engine> {
     engine: E,
+    // 通过 BEGIN 显式开启的事务
+    txn: Option<E::Transaction>,
+    // 事务中有语句执行失败，失败的语句可能已经写入了部分数据
+    // 之后只能回滚，COMMIT 也会回滚事务并返回错误
+    aborted: bool,
 }
 
 impl<E: Engine> Session<E> {
     pub fn execute(&mut self, sql: &str) -> Result<ResultSet> {
         match Parser::new(sql).parse()? {
+            ast::Statement::Begin { .. } if self.txn.is_some() => {
+                Err(Error::Internal("already in transaction".to_string()))
+            }
+            ast::Statement::Commit | ast::Statement::Rollback if self.txn.is_none() => {
+                Err(Error::Internal("not in transaction".to_string()))
+            }
+            ast::Statement::Begin { read_only, as_of } => {
+                let txn = match (read_only, as_of) {
+                    (false, _) => self.engine.begin()?,
+                    (true, None) => self.engine.begin_read_only()?,
+                    (true, Some(version)) => self.engine.begin_as_of(version)?,
+                };
+                let version = txn.version();
+                self.txn = Some(txn);
+                Ok(ResultSet::Begin { version, read_only })
+            }
+            ast::Statement::Commit if self.aborted => {
+                self.aborted = false;
+                self.txn.take().unwrap().rollback()?;
+                Err(Error::Internal(
+                    "transaction aborted, rolled back instead of commit".to_string(),
+                ))
+            }
+            ast::Statement::Commit => {
+                let txn = self.txn.take().unwrap();
+                let version = txn.version();
+                txn.commit()?;
+                Ok(ResultSet::Commit { version })
+            }
+            ast::Statement::Rollback => {
+                self.aborted = false;
+                let txn = self.txn.take().unwrap();
+                let version = txn.version();
+                txn.rollback()?;
+                Ok(ResultSet::Rollback { version })
+            }
+            _ if self.aborted => Err(Error::Internal(
+                "transaction aborted, only ROLLBACK is allowed".to_string(),
+            )),
+            stmt if self.txn.is_some() => {
+                // 在显式开启的事务中执行，由 COMMIT / ROLLBACK 结束事务
+                let txn = self.txn.as_mut().unwrap();
+                match Plan::build(stmt, txn).and_then(|plan| plan.executor(txn)) {
+                    Err(Error::WriteConflict) => {
+                        // 写冲突，事务无法继续，直接回滚
+                        self.txn.take().unwrap().rollback()?;
+                        Err(Error::WriteConflict)
+                    }
+                    Err(err) => {
+                        // 失败的语句可能已经写入了部分数据，读写事务不能再提交
+                        self.aborted = !txn.read_only();
+                        Err(err)
+                    }
+                    result => result,
+                }
+            }
             stmt => {
-                let mut txn = self.engine.begin()?;
-                // 构建执行计划
-                match Plan::build(stmt).executor(&mut txn) {
+                // 查询语句使用只读事务，不需要分配新的版本号
+                let read_only = matches!(stmt, ast::Statement::Select { .. });
+                let mut txn = match read_only {
+                    true => self.engine.begin_read_only()?,
+                    false => self.engine.begin()?,
+                };
+                // 构建执行计划时需要读取表的信息，因此在事务中进行
+                match Plan::build(stmt, &mut txn).and_then(|plan| plan.executor(&mut txn)) {
                     Ok(result) => {
                         txn.commit()?;
                         Ok(result)
                     }
                     Err(err) => {
-                        txn.rollback();
+                        // 执行失败，回滚这个语句所做的全部修改
+                        txn.rollback()?;
                         Err(err)
                     }
                 }
@@ -63,3 +176,12 @@ impl<E: Engine> Session<E> {
         }
     }
 }
+
+impl<E: Engine> Drop for Session<E> {
+    // 会话结束时，回滚还没有提交的事务
+    fn drop(&mut self) {
+        if let Some(txn) = self.txn.take() {
+            let _ = txn.rollback();
+        }
+    }
+}
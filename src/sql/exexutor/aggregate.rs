@@ -0,0 +1,249 @@
+use std::{cmp::Ordering, collections::BTreeMap};
+
+use crate::{
+    error::{Error, Result},
+    sql::{
+        engine::Transaction,
+        parser::ast::Expression,
+        plan::AggregateFunction,
+        types::{Row, Rows, Value},
+    },
+    storage::keycode::serialize_key,
+};
+
+use super::{query::collect, Executor, ResultSet};
+
+pub struct Aggregate<T: Transaction> {
+    source: Box<dyn Executor<T>>,
+    group_by: Vec<Expression>,
+    aggregates: Vec<(AggregateFunction, Option<Expression>)>,
+}
+
+impl<T: Transaction> Aggregate<T> {
+    pub fn new(
+        source: Box<dyn Executor<T>>,
+        group_by: Vec<Expression>,
+        aggregates: Vec<(AggregateFunction, Option<Expression>)>,
+    ) -> Box<Self> {
+        Box::new(Self {
+            source,
+            group_by,
+            aggregates,
+        })
+    }
+}
+
+impl<T: Transaction + 'static> Executor<T> for Aggregate<T> {
+    fn executor(self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
+        collect(self, txn)
+    }
+
+    fn rows(self: Box<Self>, txn: &mut T) -> Result<(Vec<String>, Rows)> {
+        let Self {
+            source,
+            group_by,
+            aggregates,
+        } = *self;
+        let (columns, rows) = source.rows(txn)?;
+        let accumulators = || {
+            aggregates
+                .iter()
+                .map(|(function, _)| Accumulator::new(*function))
+                .collect::<Vec<_>>()
+        };
+
+        // 分组的 key 是编码之后的分组值，结果按照分组值的顺序输出
+        // -0.0 和 0.0 属于同一个分组，编码之前统一成 0.0
+        let mut groups: BTreeMap<Vec<u8>, (Row, Vec<Accumulator>)> = BTreeMap::new();
+        for row in rows {
+            let row = row?;
+            let values = group_by
+                .iter()
+                .map(|expr| expr.evaluate(&columns, &row))
+                .collect::<Result<Vec<_>>>()?;
+            let key = values
+                .iter()
+                .cloned()
+                .map(Value::canonical)
+                .collect::<Vec<_>>();
+            let (_, accumulators) = groups
+                .entry(serialize_key(&key)?)
+                .or_insert_with(|| (values, accumulators()));
+            for (acc, (_, arg)) in accumulators.iter_mut().zip(aggregates.iter()) {
+                // count(*) 统计所有的行，用一个非 NULL 的值表示
+                let value = match arg {
+                    Some(expr) => expr.evaluate(&columns, &row)?,
+                    None => Value::Boolean(true),
+                };
+                acc.add(value)?;
+            }
+        }
+        // 没有分组时，即使没有数据也返回一行结果
+        if groups.is_empty() && group_by.is_empty() {
+            groups.insert(Vec::new(), (Vec::new(), accumulators()));
+        }
+
+        let names = group_by
+            .iter()
+            .map(|expr| expr.to_string())
+            .chain(aggregates.iter().map(|(f, arg)| f.column_name(arg)))
+            .collect();
+        let rows = groups
+            .into_values()
+            .map(|(mut row, accumulators)| {
+                row.extend(accumulators.into_iter().map(Accumulator::result));
+                Ok(row)
+            })
+            .collect::<Vec<_>>();
+        Ok((names, Box::new(rows.into_iter())))
+    }
+}
+
+// 聚合函数的中间状态，NULL 值不参与计算
+// 没有非 NULL 的值时，count 的结果是 0，其他函数的结果是 NULL
+struct Accumulator {
+    function: AggregateFunction,
+    count: i64,
+    value: Value,
+}
+
+impl Accumulator {
+    fn new(function: AggregateFunction) -> Self {
+        Self {
+            function,
+            count: 0,
+            value: Value::Null,
+        }
+    }
+
+    fn add(&mut self, value: Value) -> Result<()> {
+        if value == Value::Null {
+            return Ok(());
+        }
+        self.count += 1;
+        let current = std::mem::replace(&mut self.value, Value::Null);
+        self.value = match (self.function, current, value) {
+            (AggregateFunction::Count, _, _) => Value::Null,
+            // 整数求和的结果是整数，有浮点数参与时结果为浮点数
+            (
+                AggregateFunction::Sum | AggregateFunction::Avg,
+                Value::Null,
+                v @ (Value::Integer(_) | Value::Float(_)),
+            ) => v,
+            (
+                AggregateFunction::Sum | AggregateFunction::Avg,
+                Value::Integer(a),
+                Value::Integer(b),
+            ) => Value::Integer(
+                a.checked_add(b)
+                    .ok_or(Error::Internal("integer overflow".to_string()))?,
+            ),
+            (
+                AggregateFunction::Sum | AggregateFunction::Avg,
+                Value::Integer(a),
+                Value::Float(b),
+            ) => Value::Float(a as f64 + b),
+            (
+                AggregateFunction::Sum | AggregateFunction::Avg,
+                Value::Float(a),
+                Value::Integer(b),
+            ) => Value::Float(a + b as f64),
+            (AggregateFunction::Sum | AggregateFunction::Avg, Value::Float(a), Value::Float(b)) => {
+                Value::Float(a + b)
+            }
+            (AggregateFunction::Min | AggregateFunction::Max, Value::Null, v) => v,
+            (function @ (AggregateFunction::Min | AggregateFunction::Max), current, v) => {
+                let ordering = current.compare(&v).ok_or(Error::Internal(format!(
+                    "cannot compare {:?} and {:?}",
+                    current, v
+                )))?;
+                match (function, ordering) {
+                    (AggregateFunction::Min, Ordering::Greater)
+                    | (AggregateFunction::Max, Ordering::Less) => v,
+                    _ => current,
+                }
+            }
+            (function, _, v) => {
+                return Err(Error::Internal(format!(
+                    "cannot apply {} to {:?}",
+                    function, v
+                )))
+            }
+        };
+        Ok(())
+    }
+
+    fn result(self) -> Value {
+        match (self.function, self.value) {
+            (AggregateFunction::Count, _) => Value::Integer(self.count),
+            (AggregateFunction::Avg, Value::Integer(sum)) => {
+                Value::Float(sum as f64 / self.count as f64)
+            }
+            (AggregateFunction::Avg, Value::Float(sum)) => Value::Float(sum / self.count as f64),
+            (_, value) => value,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Accumulator;
+    use crate::{
+        error::Result,
+        sql::{plan::AggregateFunction, types::Value},
+    };
+
+    fn aggregate(function: AggregateFunction, values: Vec<Value>) -> Result<Value> {
+        let mut acc = Accumulator::new(function);
+        for value in values {
+            acc.add(value)?;
+        }
+        Ok(acc.result())
+    }
+
+    #[test]
+    fn test_accumulator() -> Result<()> {
+        // 整数和浮点数混合时，sum 和 avg 的结果是浮点数
+        let mixed = vec![
+            Value::Integer(1),
+            Value::Null,
+            Value::Float(2.5),
+            Value::Integer(3),
+        ];
+        assert_eq!(
+            aggregate(AggregateFunction::Sum, mixed.clone())?,
+            Value::Float(6.5)
+        );
+        assert_eq!(
+            aggregate(AggregateFunction::Avg, mixed.clone())?,
+            Value::Float(6.5 / 3.0)
+        );
+        assert_eq!(
+            aggregate(AggregateFunction::Count, mixed.clone())?,
+            Value::Integer(3)
+        );
+        // 最小值和最大值保持原来的类型
+        assert_eq!(
+            aggregate(AggregateFunction::Min, mixed.clone())?,
+            Value::Integer(1)
+        );
+        assert_eq!(aggregate(AggregateFunction::Max, mixed)?, Value::Integer(3));
+
+        assert_eq!(
+            aggregate(AggregateFunction::Sum, vec![Value::Null])?,
+            Value::Null
+        );
+        assert!(aggregate(
+            AggregateFunction::Sum,
+            vec![Value::Integer(i64::MAX), Value::Integer(1)]
+        )
+        .is_err());
+        assert!(aggregate(
+            AggregateFunction::Max,
+            vec![Value::Integer(1), Value::String("a".to_string())]
+        )
+        .is_err());
+        assert!(aggregate(AggregateFunction::Avg, vec![Value::Boolean(true)]).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,248 @@
+use std::collections::{HashMap, VecDeque};
+
+use crate::{
+    error::{Error, Result},
+    sql::{
+        engine::Transaction,
+        parser::ast::{resolve_column, Expression, JoinType},
+        types::{Row, Rows, Value},
+    },
+    storage::keycode::serialize_key,
+};
+
+use super::{query::collect, Executor, ResultSet};
+
+// 找到左边一行数据在右边匹配的全部行
+type Matcher = Box<dyn FnMut(&Row, &[Row]) -> Result<Vec<usize>>>;
+
+pub struct NestedLoopJoin<T: Transaction> {
+    left: Box<dyn Executor<T>>,
+    right: Box<dyn Executor<T>>,
+    predicate: Option<Expression>,
+    join_type: JoinType,
+}
+
+impl<T: Transaction> NestedLoopJoin<T> {
+    pub fn new(
+        left: Box<dyn Executor<T>>,
+        right: Box<dyn Executor<T>>,
+        predicate: Option<Expression>,
+        join_type: JoinType,
+    ) -> Box<Self> {
+        Box::new(Self {
+            left,
+            right,
+            predicate,
+            join_type,
+        })
+    }
+}
+
+impl<T: Transaction + 'static> Executor<T> for NestedLoopJoin<T> {
+    fn executor(self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
+        collect(self, txn)
+    }
+
+    fn rows(self: Box<Self>, txn: &mut T) -> Result<(Vec<String>, Rows)> {
+        let (left_columns, left_rows) = self.left.rows(txn)?;
+        let (right_columns, right_rows) = self.right.rows(txn)?;
+        let right = right_rows.collect::<Result<Vec<_>>>()?;
+        let columns = [left_columns.clone(), right_columns.clone()].concat();
+
+        // 拼接之后计算连接条件，没有连接条件时全部匹配
+        let predicate = self.predicate;
+        let names = columns.clone();
+        let matcher: Matcher = Box::new(move |left_row, right| {
+            let mut matched = Vec::new();
+            for (i, right_row) in right.iter().enumerate() {
+                let ok = match &predicate {
+                    Some(predicate) => {
+                        let row = [left_row.as_slice(), right_row.as_slice()].concat();
+                        match predicate.evaluate(&names, &row)? {
+                            Value::Boolean(b) => b,
+                            Value::Null => false,
+                            v => {
+                                return Err(Error::Internal(format!(
+                                    "join predicate returned {:?}, expected boolean",
+                                    v
+                                )))
+                            }
+                        }
+                    }
+                    None => true,
+                };
+                if ok {
+                    matched.push(i);
+                }
+            }
+            Ok(matched)
+        });
+
+        let rows = JoinIterator::new(
+            left_rows,
+            left_columns.len(),
+            right,
+            right_columns.len(),
+            self.join_type,
+            matcher,
+        );
+        Ok((columns, Box::new(rows)))
+    }
+}
+
+pub struct HashJoin<T: Transaction> {
+    left: Box<dyn Executor<T>>,
+    left_field: String,
+    right: Box<dyn Executor<T>>,
+    right_field: String,
+    join_type: JoinType,
+}
+
+impl<T: Transaction> HashJoin<T> {
+    pub fn new(
+        left: Box<dyn Executor<T>>,
+        left_field: String,
+        right: Box<dyn Executor<T>>,
+        right_field: String,
+        join_type: JoinType,
+    ) -> Box<Self> {
+        Box::new(Self {
+            left,
+            left_field,
+            right,
+            right_field,
+            join_type,
+        })
+    }
+}
+
+impl<T: Transaction + 'static> Executor<T> for HashJoin<T> {
+    fn executor(self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
+        collect(self, txn)
+    }
+
+    fn rows(self: Box<Self>, txn: &mut T) -> Result<(Vec<String>, Rows)> {
+        let (left_columns, left_rows) = self.left.rows(txn)?;
+        let (right_columns, right_rows) = self.right.rows(txn)?;
+        let right = right_rows.collect::<Result<Vec<_>>>()?;
+        let left_pos = resolve_column(&left_columns, &self.left_field)?;
+        let right_pos = resolve_column(&right_columns, &self.right_field)?;
+
+        // 使用右边的数据建立哈希表，key 是编码之后的列值，NULL 不和任何值匹配
+        let mut table: HashMap<Vec<u8>, Vec<usize>> = HashMap::new();
+        for (i, row) in right.iter().enumerate() {
+            if row[right_pos] != Value::Null {
+                table.entry(hash_key(&row[right_pos])?).or_default().push(i);
+            }
+        }
+        let matcher: Matcher = Box::new(move |left_row, _| {
+            if left_row[left_pos] == Value::Null {
+                return Ok(Vec::new());
+            }
+            let key = hash_key(&left_row[left_pos])?;
+            Ok(table.get(&key).cloned().unwrap_or_default())
+        });
+
+        let columns = [left_columns.clone(), right_columns.clone()].concat();
+        let rows = JoinIterator::new(
+            left_rows,
+            left_columns.len(),
+            right,
+            right_columns.len(),
+            self.join_type,
+            matcher,
+        );
+        Ok((columns, Box::new(rows)))
+    }
+}
+
+// 哈希表的 key，-0.0 和 0.0 相等，编码之前统一成 0.0
+fn hash_key(value: &Value) -> Result<Vec<u8>> {
+    serialize_key(&value.clone().canonical())
+}
+
+// 逐行读取左边的数据，和右边匹配的行拼接在一起
+// LEFT JOIN 中左边没有匹配的行，右边补 NULL
+// RIGHT JOIN 在左边读取完之后，输出右边没有匹配过的行，左边补 NULL
+struct JoinIterator {
+    left: Rows,
+    left_width: usize,
+    right: Vec<Row>,
+    right_width: usize,
+    join_type: JoinType,
+    matcher: Matcher,
+    // 右边的每一行是否匹配过
+    matched: Vec<bool>,
+    pending: VecDeque<Row>,
+    // RIGHT JOIN 输出右边没有匹配的行时的位置
+    cursor: usize,
+}
+
+impl JoinIterator {
+    fn new(
+        left: Rows,
+        left_width: usize,
+        right: Vec<Row>,
+        right_width: usize,
+        join_type: JoinType,
+        matcher: Matcher,
+    ) -> Self {
+        Self {
+            left,
+            left_width,
+            matched: vec![false; right.len()],
+            right,
+            right_width,
+            join_type,
+            matcher,
+            pending: VecDeque::new(),
+            cursor: 0,
+        }
+    }
+
+    fn try_next(&mut self) -> Result<Option<Row>> {
+        loop {
+            if let Some(row) = self.pending.pop_front() {
+                return Ok(Some(row));
+            }
+            let left_row = match self.left.next().transpose()? {
+                Some(row) => row,
+                None => break,
+            };
+            let matched = (self.matcher)(&left_row, &self.right)?;
+            if matched.is_empty() && self.join_type == JoinType::Left {
+                let mut row = left_row.clone();
+                row.extend(std::iter::repeat_n(Value::Null, self.right_width));
+                self.pending.push_back(row);
+            }
+            for i in matched {
+                self.matched[i] = true;
+                let mut row = left_row.clone();
+                row.extend(self.right[i].iter().cloned());
+                self.pending.push_back(row);
+            }
+        }
+
+        if self.join_type != JoinType::Right {
+            return Ok(None);
+        }
+        while self.cursor < self.right.len() {
+            let i = self.cursor;
+            self.cursor += 1;
+            if !self.matched[i] {
+                let mut row = vec![Value::Null; self.left_width];
+                row.extend(self.right[i].iter().cloned());
+                return Ok(Some(row));
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl Iterator for JoinIterator {
+    type Item = Result<Row>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.try_next().transpose()
+    }
+}
@@ -1,35 +1,132 @@
-use mutation::Insert;
-use query::Scan;
-use schema::CreateTable;
+use aggregate::Aggregate;
+use join::{HashJoin, NestedLoopJoin};
+use mutation::{Delete, Insert, Update};
+use order::Order;
+use query::{Filter, IndexLookup, IndexRangeScan, KeyLookup, Limit, Offset, Projection, Scan};
+use schema::{CreateIndex, CreateTable};
 
-use crate::error::Result;
+use crate::error::{Error, Result};
 
-use super::{engine::Transaction, plan::Node, types::Row};
+use super::{
+    engine::Transaction,
+    plan::Node,
+    types::{Row, Rows},
+};
 
+mod aggregate;
+mod join;
 mod mutation;
+mod order;
 mod query;
 mod schema;
 
 pub trait Executor<T: Transaction> {
     fn executor(self: Box<Self>, txn: &mut T) -> Result<ResultSet>;
+
+    // 返回列名和按需读取的数据行，查询节点通过它从下层节点逐行读取数据
+    fn rows(self: Box<Self>, txn: &mut T) -> Result<(Vec<String>, Rows)> {
+        match self.executor(txn)? {
+            ResultSet::Scan { columns, rows } => Ok((columns, Box::new(rows.into_iter().map(Ok)))),
+            _ => Err(Error::Internal("unexpected result set".to_string())),
+        }
+    }
 }
 
-impl<T: Transaction> dyn Executor<T> {
+impl<T: Transaction + 'static> dyn Executor<T> {
     pub fn build(node: Node) -> Box<dyn Executor<T>> {
         match node {
             Node::CreateTable { schema } => CreateTable::new(schema),
+            Node::CreateIndex { table_name, index } => CreateIndex::new(table_name, index),
             Node::Insert {
                 table_name,
                 columns,
                 values,
             } => Insert::new(table_name, columns, values),
-            Node::Scan { table_name } => Scan::new(table_name),
+            Node::Update {
+                table_name,
+                source,
+                columns,
+            } => Update::new(table_name, Self::build(*source), columns),
+            Node::Delete { table_name, source } => Delete::new(table_name, Self::build(*source)),
+            Node::Scan { table_name, alias } => Scan::new(table_name, alias),
+            Node::NestedLoopJoin {
+                left,
+                right,
+                predicate,
+                join_type,
+            } => NestedLoopJoin::new(
+                Self::build(*left),
+                Self::build(*right),
+                predicate,
+                join_type,
+            ),
+            Node::HashJoin {
+                left,
+                left_field,
+                right,
+                right_field,
+                join_type,
+            } => HashJoin::new(
+                Self::build(*left),
+                left_field,
+                Self::build(*right),
+                right_field,
+                join_type,
+            ),
+            Node::KeyLookup { table_name, value } => KeyLookup::new(table_name, value),
+            Node::IndexLookup {
+                table_name,
+                column,
+                value,
+            } => IndexLookup::new(table_name, column, value),
+            Node::IndexRangeScan {
+                table_name,
+                column,
+                range,
+            } => IndexRangeScan::new(table_name, column, range),
+            Node::Filter { source, predicate } => Filter::new(Self::build(*source), predicate),
+            Node::Aggregate {
+                source,
+                group_by,
+                aggregates,
+            } => Aggregate::new(Self::build(*source), group_by, aggregates),
+            Node::Order { source, order_by } => Order::new(Self::build(*source), order_by),
+            Node::Offset { source, offset } => Offset::new(Self::build(*source), offset),
+            Node::Limit { source, limit } => Limit::new(Self::build(*source), limit),
+            Node::Projection { source, exprs } => Projection::new(Self::build(*source), exprs),
         }
     }
 }
 
+#[derive(Debug, PartialEq)]
 pub enum ResultSet {
-    CreateTable { table_name: String },
-    Insert { count: usize },
-    Scan { columns: Vec<String>, rows: Vec<Row> },
+    CreateTable {
+        table_name: String,
+    },
+    CreateIndex {
+        index_name: String,
+    },
+    Insert {
+        count: usize,
+    },
+    Update {
+        count: usize,
+    },
+    Delete {
+        count: usize,
+    },
+    Begin {
+        version: u64,
+        read_only: bool,
+    },
+    Commit {
+        version: u64,
+    },
+    Rollback {
+        version: u64,
+    },
+    Scan {
+        columns: Vec<String>,
+        rows: Vec<Row>,
+    },
 }
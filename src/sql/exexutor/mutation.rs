@@ -1,16 +1,11 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
 use crate::{
     error::{Error, Result},
-    sql::{
-        engine::Transaction,
-        parser::ast::Expression,
-        schema::Table,
-        types::{Row, Value},
-    },
+    sql::{engine::Transaction, parser::ast::Expression, schema::Table, types::Row},
 };
 
-use super::Executor;
+use super::{Executor, ResultSet};
 
 pub struct Insert {
     table_name: String,
@@ -89,8 +84,8 @@ impl<T: Transaction> Executor<T> for Insert {
             // 将表达式转换成 value
             let row = exprs
                 .into_iter()
-                .map(|e| Value::from_expression(e))
-                .collect::<Vec<_>>();
+                .map(|e| e.evaluate(&[], &Vec::new()))
+                .collect::<Result<Vec<_>>>()?;
             // 如果没有指定插入的列
 
             let insert_row = if self.columes.is_empty() {
@@ -108,3 +103,77 @@ impl<T: Transaction> Executor<T> for Insert {
         Ok(super::ResultSet::Insert { count })
     }
 }
+
+pub struct Update<T: Transaction> {
+    table_name: String,
+    source: Box<dyn Executor<T>>,
+    columns: BTreeMap<String, Expression>,
+}
+
+impl<T: Transaction> Update<T> {
+    pub fn new(
+        table_name: String,
+        source: Box<dyn Executor<T>>,
+        columns: BTreeMap<String, Expression>,
+    ) -> Box<Self> {
+        Box::new(Self {
+            table_name,
+            source,
+            columns,
+        })
+    }
+}
+
+impl<T: Transaction> Executor<T> for Update<T> {
+    fn executor(self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
+        let table = txn.must_get_table(self.table_name.clone())?;
+        // 先扫描出需要更新的行
+        match self.source.executor(txn)? {
+            ResultSet::Scan { columns, rows } => {
+                let mut count = 0;
+                for row in rows {
+                    let id = table.get_primary_key(&row)?;
+                    let mut new_row = row.clone();
+                    for (col, expr) in self.columns.iter() {
+                        // 表达式基于更新前的行进行计算
+                        new_row[table.get_col_index(col)?] = expr.evaluate(&columns, &row)?;
+                    }
+                    txn.update_row(&table, &id, new_row)?;
+                    count += 1;
+                }
+                Ok(ResultSet::Update { count })
+            }
+            _ => Err(Error::Internal("unexpected result set".to_string())),
+        }
+    }
+}
+
+pub struct Delete<T: Transaction> {
+    table_name: String,
+    source: Box<dyn Executor<T>>,
+}
+
+impl<T: Transaction> Delete<T> {
+    pub fn new(table_name: String, source: Box<dyn Executor<T>>) -> Box<Self> {
+        Box::new(Self { table_name, source })
+    }
+}
+
+impl<T: Transaction> Executor<T> for Delete<T> {
+    fn executor(self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
+        let table = txn.must_get_table(self.table_name.clone())?;
+        // 先扫描出需要删除的行
+        match self.source.executor(txn)? {
+            ResultSet::Scan { rows, .. } => {
+                let mut count = 0;
+                for row in rows {
+                    let id = table.get_primary_key(&row)?;
+                    txn.delete_row(&table, &id)?;
+                    count += 1;
+                }
+                Ok(ResultSet::Delete { count })
+            }
+            _ => Err(Error::Internal("unexpected result set".to_string())),
+        }
+    }
+}
@@ -0,0 +1,308 @@
+use std::{
+    cmp::Ordering,
+    fs::File,
+    io::{BufReader, BufWriter, Seek, SeekFrom, Write},
+};
+
+use crate::{
+    error::Result,
+    sql::{
+        engine::Transaction,
+        parser::ast::{OrderBy, OrderDirection},
+        types::{Row, Rows, Value},
+    },
+};
+
+use super::{query::collect, Executor, ResultSet};
+
+// 排序使用的内存上限，超过之后将排好序的数据写入临时文件
+const SORT_MEMORY_BUDGET: u64 = 8 * 1024 * 1024;
+// 一次最多归并的临时文件数量，限制同时打开的文件数
+const SORT_MERGE_FAN_IN: usize = 64;
+
+// 排序的数据，排序表达式的值以及对应的行
+type Entry = (Vec<Value>, Row);
+
+type Entries = Box<dyn Iterator<Item = Result<Entry>>>;
+
+pub struct Order<T: Transaction> {
+    source: Box<dyn Executor<T>>,
+    order_by: Vec<OrderBy>,
+}
+
+impl<T: Transaction> Order<T> {
+    pub fn new(source: Box<dyn Executor<T>>, order_by: Vec<OrderBy>) -> Box<Self> {
+        Box::new(Self { source, order_by })
+    }
+}
+
+impl<T: Transaction + 'static> Executor<T> for Order<T> {
+    fn executor(self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
+        collect(self, txn)
+    }
+
+    fn rows(self: Box<Self>, txn: &mut T) -> Result<(Vec<String>, Rows)> {
+        let (columns, rows) = self.source.rows(txn)?;
+        let mut sorter = Sorter::new(
+            self.order_by
+                .iter()
+                .map(|order| (order.direction, order.nulls_first))
+                .collect(),
+            SORT_MEMORY_BUDGET,
+            SORT_MERGE_FAN_IN,
+        );
+        for row in rows {
+            let row = row?;
+            let keys = self
+                .order_by
+                .iter()
+                .map(|order| order.expr.evaluate(&columns, &row))
+                .collect::<Result<Vec<_>>>()?;
+            sorter.push(keys, row)?;
+        }
+        let rows = sorter.finish()?.map(|entry| entry.map(|(_, row)| row));
+        Ok((columns, Box::new(rows)))
+    }
+}
+
+// 外部排序，内存中的数据超过上限时，排序之后写入一个临时文件
+// 读取时将所有临时文件以及内存中的数据进行多路归并
+// 同一层的临时文件达到 fan_in 个时，先归并成上一层的一个文件，限制同时打开的文件数
+// 排序是稳定的，排序值相同的行保持输入的顺序
+struct Sorter {
+    // 每个排序值的方向，以及 NULL 是否排在最前面
+    order: Vec<(OrderDirection, bool)>,
+    budget: u64,
+    fan_in: usize,
+    buffer: Vec<Entry>,
+    // buffer 中数据编码之后的大小
+    size: u64,
+    // 写入临时文件的数据，按照写入的顺序排列，越早写入的层数越高
+    runs: Vec<Run>,
+}
+
+// 一个排好序的临时文件，文件中的行数，以及归并的层数
+struct Run {
+    file: File,
+    count: usize,
+    level: usize,
+}
+
+impl Run {
+    // 将排好序的数据写入临时文件，文件在关闭之后自动删除
+    fn write(entries: impl Iterator<Item = Result<Entry>>, level: usize) -> Result<Self> {
+        let mut file = tempfile::tempfile()?;
+        let mut writer = BufWriter::new(&mut file);
+        let mut count = 0;
+        for entry in entries {
+            bincode::serialize_into(&mut writer, &entry?)?;
+            count += 1;
+        }
+        writer.flush()?;
+        drop(writer);
+        file.seek(SeekFrom::Start(0))?;
+        Ok(Self { file, count, level })
+    }
+
+    fn read(self) -> Entries {
+        let mut reader = BufReader::new(self.file);
+        Box::new((0..self.count).map(move |_| Ok(bincode::deserialize_from(&mut reader)?)))
+    }
+}
+
+impl Sorter {
+    fn new(order: Vec<(OrderDirection, bool)>, budget: u64, fan_in: usize) -> Self {
+        Self {
+            order,
+            budget,
+            fan_in: fan_in.max(2),
+            buffer: Vec::new(),
+            size: 0,
+            runs: Vec::new(),
+        }
+    }
+
+    fn push(&mut self, keys: Vec<Value>, row: Row) -> Result<()> {
+        let entry = (keys, row);
+        self.size += bincode::serialized_size(&entry)?;
+        self.buffer.push(entry);
+        if self.size > self.budget {
+            self.spill()?;
+        }
+        Ok(())
+    }
+
+    // 将内存中的数据排序之后写入临时文件
+    fn spill(&mut self) -> Result<()> {
+        self.sort_buffer();
+        let run = Run::write(self.buffer.drain(..).map(Ok), 0)?;
+        self.runs.push(run);
+        self.size = 0;
+
+        // 最后 fan_in 个文件在同一层时，归并为上一层的一个文件
+        while self.runs.len() >= self.fan_in
+            && self.runs[self.runs.len() - self.fan_in].level
+                == self.runs[self.runs.len() - 1].level
+        {
+            self.merge_runs()?;
+        }
+        Ok(())
+    }
+
+    // 将最后 fan_in 个临时文件归并为一个文件，相邻的文件归并之后仍然保持写入的顺序
+    fn merge_runs(&mut self) -> Result<()> {
+        let runs = self.runs.split_off(self.runs.len() - self.fan_in);
+        let level = runs[0].level + 1;
+        let entries = runs.into_iter().map(Run::read).collect();
+        let merged = MergeIterator::new(self.order.clone(), entries)?;
+        self.runs.push(Run::write(merged, level)?);
+        Ok(())
+    }
+
+    fn sort_buffer(&mut self) {
+        let order = &self.order;
+        self.buffer.sort_by(|a, b| compare(order, &a.0, &b.0));
+    }
+
+    // 返回排好序的数据
+    fn finish(mut self) -> Result<Entries> {
+        self.sort_buffer();
+        if self.runs.is_empty() {
+            return Ok(Box::new(self.buffer.into_iter().map(Ok)));
+        }
+        // 文件数量加上内存中的数据不超过 fan_in 时再进行最后一次归并
+        while self.runs.len() >= self.fan_in {
+            self.merge_runs()?;
+        }
+
+        // 先写入文件的数据在前面，相同的值按照 runs 的顺序输出，保证排序是稳定的
+        let mut runs: Vec<Entries> = self.runs.into_iter().map(Run::read).collect();
+        runs.push(Box::new(self.buffer.into_iter().map(Ok)));
+        Ok(Box::new(MergeIterator::new(self.order, runs)?))
+    }
+}
+
+// 比较两行的排序值
+fn compare(order: &[(OrderDirection, bool)], a: &[Value], b: &[Value]) -> Ordering {
+    for ((direction, nulls_first), (a, b)) in order.iter().zip(a.iter().zip(b.iter())) {
+        let ordering = match (a, b) {
+            (Value::Null, Value::Null) => Ordering::Equal,
+            (Value::Null, _) if *nulls_first => Ordering::Less,
+            (Value::Null, _) => Ordering::Greater,
+            (_, Value::Null) if *nulls_first => Ordering::Greater,
+            (_, Value::Null) => Ordering::Less,
+            (a, b) => match direction {
+                OrderDirection::Asc => a.total_cmp(b),
+                OrderDirection::Desc => b.total_cmp(a),
+            },
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+    Ordering::Equal
+}
+
+// 多路归并，每次从所有有序的数据中取出最小的一个
+struct MergeIterator {
+    order: Vec<(OrderDirection, bool)>,
+    runs: Vec<Entries>,
+    // 每一路当前的第一个数据
+    heads: Vec<Option<Entry>>,
+}
+
+impl MergeIterator {
+    fn new(order: Vec<(OrderDirection, bool)>, mut runs: Vec<Entries>) -> Result<Self> {
+        let heads = runs
+            .iter_mut()
+            .map(|run| run.next().transpose())
+            .collect::<Result<_>>()?;
+        Ok(Self { order, runs, heads })
+    }
+
+    fn try_next(&mut self) -> Result<Option<Entry>> {
+        let mut min: Option<usize> = None;
+        for (i, head) in self.heads.iter().enumerate() {
+            let Some((keys, _)) = head else { continue };
+            // 相同的值取前面的一路
+            let smaller = match min.and_then(|m| self.heads[m].as_ref()) {
+                Some((min_keys, _)) => compare(&self.order, keys, min_keys) == Ordering::Less,
+                None => true,
+            };
+            if smaller {
+                min = Some(i);
+            }
+        }
+        let Some(i) = min else { return Ok(None) };
+        let next = self.runs[i].next().transpose()?;
+        Ok(std::mem::replace(&mut self.heads[i], next))
+    }
+}
+
+impl Iterator for MergeIterator {
+    type Item = Result<Entry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.try_next().transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Sorter;
+    use crate::{
+        error::Result,
+        sql::{parser::ast::OrderDirection, types::Value},
+    };
+
+    fn sort(budget: u64, fan_in: usize) -> Result<(Vec<Vec<Value>>, usize)> {
+        let order = vec![(OrderDirection::Desc, false), (OrderDirection::Asc, true)];
+        let mut sorter = Sorter::new(order, budget, fan_in);
+        // 伪随机的数据，包含 NULL 以及大量重复的值
+        let mut seed: i64 = 7;
+        for i in 0..2000 {
+            seed = (seed * 1103515245 + 12345) % 2147483648;
+            let a = match seed % 11 {
+                0 => Value::Null,
+                n => Value::Integer(n),
+            };
+            let b = match seed % 7 {
+                0 => Value::Null,
+                n => Value::String(format!("s{}", n)),
+            };
+            sorter.push(vec![a.clone(), b.clone()], vec![Value::Integer(i), a, b])?;
+        }
+        let runs = sorter.runs.len();
+        let rows = sorter
+            .finish()?
+            .map(|entry| entry.map(|(_, row)| row))
+            .collect::<Result<Vec<_>>>()?;
+        Ok((rows, runs))
+    }
+
+    #[test]
+    fn test_sort_spill() -> Result<()> {
+        let (expected, runs) = sort(u64::MAX, 64)?;
+        assert_eq!(runs, 0);
+        let (rows, runs) = sort(1024, 64)?;
+        assert!(runs > 10);
+        assert_eq!(rows, expected);
+        // 临时文件数量超过 fan_in 时分多次归并，结果不变
+        let (merged, merged_runs) = sort(1024, 3)?;
+        assert!(merged_runs < 10);
+        assert_eq!(merged, expected);
+
+        // 第一列降序且 NULL 在最后，第二列升序且 NULL 在最前，相同的值保持输入的顺序
+        assert_eq!(rows.len(), 2000);
+        assert_eq!(rows[0][1], Value::Integer(10));
+        assert_eq!(rows[0][2], Value::Null);
+        assert_eq!(rows[1999][1], Value::Null);
+        for pair in rows.windows(2) {
+            let (a, b) = (&pair[0], &pair[1]);
+            if a[1] == b[1] && a[2] == b[2] {
+                assert!(a[0].total_cmp(&b[0]).is_lt());
+            }
+        }
+        Ok(())
+    }
+}
@@ -1,24 +1,301 @@
-use crate::{error::Result, sql::engine::Transaction};
+use std::ops::Bound;
+
+use crate::{
+    error::{Error, Result},
+    sql::{
+        engine::Transaction,
+        parser::ast::Expression,
+        schema::Table,
+        types::{Rows, Value},
+    },
+};
 
 use super::{Executor, ResultSet};
 
+// 读取查询节点全部的数据行，结果中的列名去掉表名
+pub(super) fn collect<T: Transaction>(
+    executor: Box<dyn Executor<T>>,
+    txn: &mut T,
+) -> Result<ResultSet> {
+    let (columns, rows) = executor.rows(txn)?;
+    Ok(ResultSet::Scan {
+        columns: columns.into_iter().map(unqualify).collect(),
+        rows: rows.collect::<Result<_>>()?,
+    })
+}
+
+// a.x -> x，其他的表达式保持不变
+fn unqualify(name: String) -> String {
+    let is_ident = |s: &str| {
+        s.starts_with(|c: char| c.is_alphabetic())
+            && s.chars().all(|c| c.is_alphanumeric() || c == '_')
+    };
+    match name.split_once('.') {
+        Some((table, column)) if is_ident(table) && is_ident(column) => column.to_string(),
+        _ => name,
+    }
+}
+
+// 表中的列名，带上表名或者别名
+fn column_names(label: &str, table: &Table) -> Vec<String> {
+    table
+        .columns
+        .iter()
+        .map(|c| format!("{}.{}", label, c.name))
+        .collect()
+}
+
 pub struct Scan {
     table_name: String,
+    alias: Option<String>,
 }
 
 impl Scan {
-    pub fn new(table_name: String) -> Box<Self> {
-        Box::new(Self { table_name })
+    pub fn new(table_name: String, alias: Option<String>) -> Box<Self> {
+        Box::new(Self { table_name, alias })
     }
 }
 
-impl<T: Transaction> Executor<T> for Scan {
+impl<T: Transaction + 'static> Executor<T> for Scan {
     fn executor(self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
+        collect(self, txn)
+    }
+
+    fn rows(self: Box<Self>, txn: &mut T) -> Result<(Vec<String>, Rows)> {
         let table = txn.must_get_table(self.table_name.clone())?;
         let rows = txn.scan_table(self.table_name.clone())?;
-        Ok(ResultSet::Scan {
-            columns: table.columns.into_iter().map(|c| c.name.clone()).collect(),
-            rows,
+        let label = self.alias.unwrap_or(self.table_name);
+        Ok((column_names(&label, &table), rows))
+    }
+}
+
+pub struct KeyLookup {
+    table_name: String,
+    value: Value,
+}
+
+impl KeyLookup {
+    pub fn new(table_name: String, value: Value) -> Box<Self> {
+        Box::new(Self { table_name, value })
+    }
+}
+
+impl<T: Transaction + 'static> Executor<T> for KeyLookup {
+    fn executor(self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
+        collect(self, txn)
+    }
+
+    fn rows(self: Box<Self>, txn: &mut T) -> Result<(Vec<String>, Rows)> {
+        let table = txn.must_get_table(self.table_name)?;
+        let row = txn.get_row(&table, &self.value)?;
+        Ok((
+            column_names(&table.name, &table),
+            Box::new(row.into_iter().map(Ok)),
+        ))
+    }
+}
+
+pub struct IndexLookup {
+    table_name: String,
+    column: String,
+    value: Value,
+}
+
+impl IndexLookup {
+    pub fn new(table_name: String, column: String, value: Value) -> Box<Self> {
+        Box::new(Self {
+            table_name,
+            column,
+            value,
         })
     }
 }
+
+impl<T: Transaction + 'static> Executor<T> for IndexLookup {
+    fn executor(self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
+        collect(self, txn)
+    }
+
+    fn rows(self: Box<Self>, txn: &mut T) -> Result<(Vec<String>, Rows)> {
+        let table = txn.must_get_table(self.table_name)?;
+        // 先从索引中读取主键，再根据主键读取数据
+        let mut rows = Vec::new();
+        for id in txn.get_index(&table, &self.column, &self.value)? {
+            let row = txn.get_row(&table, &id)?.ok_or(Error::Internal(format!(
+                "row {:?} not found in table {}",
+                id, table.name
+            )))?;
+            rows.push(Ok(row));
+        }
+        Ok((
+            column_names(&table.name, &table),
+            Box::new(rows.into_iter()),
+        ))
+    }
+}
+
+pub struct IndexRangeScan {
+    table_name: String,
+    column: String,
+    range: (Bound<Value>, Bound<Value>),
+}
+
+impl IndexRangeScan {
+    pub fn new(
+        table_name: String,
+        column: String,
+        range: (Bound<Value>, Bound<Value>),
+    ) -> Box<Self> {
+        Box::new(Self {
+            table_name,
+            column,
+            range,
+        })
+    }
+}
+
+impl<T: Transaction + 'static> Executor<T> for IndexRangeScan {
+    fn executor(self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
+        collect(self, txn)
+    }
+
+    fn rows(self: Box<Self>, txn: &mut T) -> Result<(Vec<String>, Rows)> {
+        let table = txn.must_get_table(self.table_name)?;
+        let rows = txn.scan_range(&table, &self.column, self.range)?;
+        Ok((column_names(&table.name, &table), rows))
+    }
+}
+
+pub struct Filter<T: Transaction> {
+    source: Box<dyn Executor<T>>,
+    predicate: Expression,
+}
+
+impl<T: Transaction> Filter<T> {
+    pub fn new(source: Box<dyn Executor<T>>, predicate: Expression) -> Box<Self> {
+        Box::new(Self { source, predicate })
+    }
+}
+
+impl<T: Transaction + 'static> Executor<T> for Filter<T> {
+    fn executor(self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
+        collect(self, txn)
+    }
+
+    fn rows(self: Box<Self>, txn: &mut T) -> Result<(Vec<String>, Rows)> {
+        let (columns, rows) = self.source.rows(txn)?;
+        let predicate = self.predicate;
+        let names = columns.clone();
+        let rows = rows.filter_map(move |row| {
+            let row = match row {
+                Ok(row) => row,
+                Err(err) => return Some(Err(err)),
+            };
+            // 只保留条件为 true 的行，NULL 和 false 都会被过滤掉
+            match predicate.evaluate(&names, &row) {
+                Ok(Value::Boolean(true)) => Some(Ok(row)),
+                Ok(Value::Boolean(false)) | Ok(Value::Null) => None,
+                Ok(v) => Some(Err(Error::Internal(format!(
+                    "filter predicate returned {:?}, expected boolean",
+                    v
+                )))),
+                Err(err) => Some(Err(err)),
+            }
+        });
+        Ok((columns, Box::new(rows)))
+    }
+}
+
+pub struct Projection<T: Transaction> {
+    source: Box<dyn Executor<T>>,
+    exprs: Vec<(Expression, Option<String>)>,
+}
+
+impl<T: Transaction> Projection<T> {
+    pub fn new(
+        source: Box<dyn Executor<T>>,
+        exprs: Vec<(Expression, Option<String>)>,
+    ) -> Box<Self> {
+        Box::new(Self { source, exprs })
+    }
+}
+
+impl<T: Transaction + 'static> Executor<T> for Projection<T> {
+    fn executor(self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
+        collect(self, txn)
+    }
+
+    fn rows(self: Box<Self>, txn: &mut T) -> Result<(Vec<String>, Rows)> {
+        let (columns, rows) = self.source.rows(txn)?;
+        // 结果列名，有别名使用别名，否则使用表达式本身
+        let new_columns = self
+            .exprs
+            .iter()
+            .map(|(expr, alias)| alias.clone().unwrap_or_else(|| expr.to_string()))
+            .collect();
+
+        let exprs = self.exprs;
+        let rows = rows.map(move |row| {
+            let row = row?;
+            exprs
+                .iter()
+                .map(|(expr, _)| expr.evaluate(&columns, &row))
+                .collect::<Result<Vec<_>>>()
+        });
+        Ok((new_columns, Box::new(rows)))
+    }
+}
+
+pub struct Offset<T: Transaction> {
+    source: Box<dyn Executor<T>>,
+    offset: usize,
+}
+
+impl<T: Transaction> Offset<T> {
+    pub fn new(source: Box<dyn Executor<T>>, offset: usize) -> Box<Self> {
+        Box::new(Self { source, offset })
+    }
+}
+
+impl<T: Transaction + 'static> Executor<T> for Offset<T> {
+    fn executor(self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
+        collect(self, txn)
+    }
+
+    fn rows(self: Box<Self>, txn: &mut T) -> Result<(Vec<String>, Rows)> {
+        let (columns, rows) = self.source.rows(txn)?;
+        // 跳过的行中出现的错误仍然需要返回
+        let mut remaining = self.offset;
+        let rows = rows.filter(move |row| match row {
+            Ok(_) if remaining > 0 => {
+                remaining -= 1;
+                false
+            }
+            _ => true,
+        });
+        Ok((columns, Box::new(rows)))
+    }
+}
+
+pub struct Limit<T: Transaction> {
+    source: Box<dyn Executor<T>>,
+    limit: usize,
+}
+
+impl<T: Transaction> Limit<T> {
+    pub fn new(source: Box<dyn Executor<T>>, limit: usize) -> Box<Self> {
+        Box::new(Self { source, limit })
+    }
+}
+
+impl<T: Transaction + 'static> Executor<T> for Limit<T> {
+    fn executor(self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
+        collect(self, txn)
+    }
+
+    fn rows(self: Box<Self>, txn: &mut T) -> Result<(Vec<String>, Rows)> {
+        let (columns, rows) = self.source.rows(txn)?;
+        // 读取到 limit 行之后不再读取下层节点的数据
+        Ok((columns, Box::new(rows.take(self.limit))))
+    }
+}
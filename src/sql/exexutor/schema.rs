@@ -1,6 +1,9 @@
 use crate::{
     error::Result,
-    sql::{engine::Transaction, schema::Table},
+    sql::{
+        engine::Transaction,
+        schema::{Index, Table},
+    },
 };
 
 use super::{Executor, ResultSet};
@@ -22,3 +25,22 @@ impl<T: Transaction> Executor<T> for CreateTable {
         Ok(ResultSet::CreateTable { table_name })
     }
 }
+
+pub struct CreateIndex {
+    table_name: String,
+    index: Index,
+}
+
+impl CreateIndex {
+    pub fn new(table_name: String, index: Index) -> Box<CreateIndex> {
+        Box::new(CreateIndex { table_name, index })
+    }
+}
+
+impl<T: Transaction> Executor<T> for CreateIndex {
+    fn executor(self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
+        let index_name = self.index.name.clone();
+        txn.create_index(self.table_name, self.index)?;
+        Ok(ResultSet::CreateIndex { index_name })
+    }
+}
@@ -1,6 +1,6 @@
+pub mod engine;
 pub mod exexutor;
 pub mod parser;
 pub mod plan;
 pub mod schema;
 pub mod types;
-pub mod engine;
\ No newline at end of file
@@ -1,4 +1,9 @@
-use crate::sql::types::DataType;
+use std::{collections::BTreeMap, fmt::Display};
+
+use crate::{
+    error::{Error, Result},
+    sql::types::{DataType, Row, Value},
+};
 
 // Abstract Syntax Tree 抽象语法树定义
 #[derive(Debug, PartialEq)]
@@ -7,14 +12,88 @@ pub enum Statement {
         name: String,
         columns: Vec<Column>,
     },
+    // create [unique] index name on table(column)
+    CreateIndex {
+        name: String,
+        table_name: String,
+        column: String,
+        unique: bool,
+    },
     Insert {
         table_name: String,
         columns: Option<Vec<String>>,
         values: Vec<Vec<Expression>>,
     },
     Select {
+        // 查询的列，以及列的别名，为空表示 select *
+        select: Vec<(Expression, Option<String>)>,
+        from: FromItem,
+        where_clause: Option<Expression>,
+        // 分组的表达式，为空表示不分组
+        group_by: Vec<Expression>,
+        // 分组之后的过滤条件
+        having: Option<Expression>,
+        order_by: Vec<OrderBy>,
+        limit: Option<Box<Expression>>,
+        offset: Option<Box<Expression>>,
+    },
+    Update {
+        table_name: String,
+        // 需要更新的列，以及更新后的值
+        columns: BTreeMap<String, Expression>,
+        where_clause: Option<Expression>,
+    },
+    Delete {
         table_name: String,
+        where_clause: Option<Expression>,
     },
+    Begin {
+        // 是否是只读事务
+        read_only: bool,
+        // 读取指定版本的历史数据
+        as_of: Option<u64>,
+    },
+    Commit,
+    Rollback,
+}
+
+// From 子句，可以是一个表，也可以是多个表的连接
+#[derive(Debug, PartialEq)]
+pub enum FromItem {
+    Table {
+        name: String,
+        alias: Option<String>,
+    },
+    Join {
+        left: Box<FromItem>,
+        right: Box<FromItem>,
+        join_type: JoinType,
+        // 连接条件，cross join 没有连接条件
+        predicate: Option<Expression>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum JoinType {
+    Cross,
+    Inner,
+    Left,
+    Right,
+}
+
+// order by 中的一个排序表达式
+#[derive(Debug, Clone, PartialEq)]
+pub struct OrderBy {
+    pub expr: Expression,
+    pub direction: OrderDirection,
+    // NULL 是否排在最前面，默认 NULL 比其他的值都大，升序时排在最后
+    pub nulls_first: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OrderDirection {
+    Asc,
+    Desc,
 }
 
 // 列定义
@@ -24,12 +103,45 @@ pub struct Column {
     pub datatype: DataType,
     pub nullable: Option<bool>,
     pub default: Option<Expression>,
+    pub primary_key: bool,
 }
 
-// 表达式定义，目前只有常量
-#[derive(Debug, PartialEq)]
+// 根据列名找到列的位置，查询中的列名都带有表名，例如 a.x
+// 不带表名的列名匹配任意表中的同名列，多个表中都有这一列时返回错误
+pub fn resolve_column(columns: &[String], name: &str) -> Result<usize> {
+    let mut found = columns
+        .iter()
+        .enumerate()
+        .filter(|(_, c)| *c == name)
+        .map(|(i, _)| i)
+        .collect::<Vec<_>>();
+    if found.is_empty() && !name.contains('.') {
+        let suffix = format!(".{}", name);
+        found = columns
+            .iter()
+            .enumerate()
+            .filter(|(_, c)| c.ends_with(&suffix))
+            .map(|(i, _)| i)
+            .collect();
+    }
+    match found[..] {
+        [i] => Ok(i),
+        [] => Err(Error::Internal(format!("column {} not found", name))),
+        _ => Err(Error::Internal(format!("column {} is ambiguous", name))),
+    }
+}
+
+// 表达式定义
+#[derive(Debug, Clone, PartialEq)]
 pub enum Expression {
+    // 常量
     Consts(Consts),
+    // 列名，可以带上表名或者别名，例如 a.x
+    Field(String),
+    // 运算
+    Operation(Operation),
+    // 函数调用，例如 upper(a)
+    Function(String, Vec<Expression>),
 }
 
 impl From<Consts> for Expression {
@@ -38,7 +150,13 @@ impl From<Consts> for Expression {
     }
 }
 
-#[derive(Debug, PartialEq)]
+impl From<Operation> for Expression {
+    fn from(value: Operation) -> Self {
+        Self::Operation(value)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum Consts {
     Null,
     Boolean(bool),
@@ -46,3 +164,312 @@ pub enum Consts {
     Float(f64),
     String(String),
 }
+
+// 运算符定义
+#[derive(Debug, Clone, PartialEq)]
+pub enum Operation {
+    // 逻辑运算
+    And(Box<Expression>, Box<Expression>),
+    Or(Box<Expression>, Box<Expression>),
+    Not(Box<Expression>),
+
+    // 比较运算
+    Equal(Box<Expression>, Box<Expression>),
+    NotEqual(Box<Expression>, Box<Expression>),
+    GreaterThan(Box<Expression>, Box<Expression>),
+    GreaterThanOrEqual(Box<Expression>, Box<Expression>),
+    LessThan(Box<Expression>, Box<Expression>),
+    LessThanOrEqual(Box<Expression>, Box<Expression>),
+    IsNull(Box<Expression>),
+
+    // 算术运算
+    Add(Box<Expression>, Box<Expression>),
+    Subtract(Box<Expression>, Box<Expression>),
+    Multiply(Box<Expression>, Box<Expression>),
+    Divide(Box<Expression>, Box<Expression>),
+    Negate(Box<Expression>),
+}
+
+impl Expression {
+    // 根据列名和一行数据，计算表达式的值
+    // 常量表达式可以传入空的 columns 和 row
+    pub fn evaluate(&self, columns: &[String], row: &Row) -> Result<Value> {
+        Ok(match self {
+            Expression::Consts(c) => Value::from_consts(c),
+            Expression::Field(name) => row[resolve_column(columns, name)?].clone(),
+            Expression::Operation(op) => match op {
+                // 逻辑运算遵循 SQL 的三值逻辑，NULL 表示未知
+                Operation::And(l, r) => {
+                    match (l.evaluate(columns, row)?, r.evaluate(columns, row)?) {
+                        (Value::Boolean(false), _) | (_, Value::Boolean(false)) => {
+                            Value::Boolean(false)
+                        }
+                        (Value::Boolean(true), Value::Boolean(true)) => Value::Boolean(true),
+                        (Value::Boolean(_) | Value::Null, Value::Boolean(_) | Value::Null) => {
+                            Value::Null
+                        }
+                        (l, r) => {
+                            return Err(Error::Internal(format!(
+                                "cannot apply {:?} AND {:?}",
+                                l, r
+                            )))
+                        }
+                    }
+                }
+                Operation::Or(l, r) => {
+                    match (l.evaluate(columns, row)?, r.evaluate(columns, row)?) {
+                        (Value::Boolean(true), _) | (_, Value::Boolean(true)) => {
+                            Value::Boolean(true)
+                        }
+                        (Value::Boolean(false), Value::Boolean(false)) => Value::Boolean(false),
+                        (Value::Boolean(_) | Value::Null, Value::Boolean(_) | Value::Null) => {
+                            Value::Null
+                        }
+                        (l, r) => {
+                            return Err(Error::Internal(format!("cannot apply {:?} OR {:?}", l, r)))
+                        }
+                    }
+                }
+                Operation::Not(e) => match e.evaluate(columns, row)? {
+                    Value::Boolean(b) => Value::Boolean(!b),
+                    Value::Null => Value::Null,
+                    v => return Err(Error::Internal(format!("cannot apply NOT {:?}", v))),
+                },
+                Operation::Equal(l, r) => Self::compare(columns, row, l, r, |o| o.is_eq())?,
+                Operation::NotEqual(l, r) => Self::compare(columns, row, l, r, |o| o.is_ne())?,
+                Operation::GreaterThan(l, r) => Self::compare(columns, row, l, r, |o| o.is_gt())?,
+                Operation::GreaterThanOrEqual(l, r) => {
+                    Self::compare(columns, row, l, r, |o| o.is_ge())?
+                }
+                Operation::LessThan(l, r) => Self::compare(columns, row, l, r, |o| o.is_lt())?,
+                Operation::LessThanOrEqual(l, r) => {
+                    Self::compare(columns, row, l, r, |o| o.is_le())?
+                }
+                Operation::IsNull(e) => Value::Boolean(e.evaluate(columns, row)? == Value::Null),
+                Operation::Add(l, r) => Self::arithmetic(
+                    l.evaluate(columns, row)?,
+                    r.evaluate(columns, row)?,
+                    "+",
+                    i64::checked_add,
+                    |a, b| a + b,
+                )?,
+                Operation::Subtract(l, r) => Self::arithmetic(
+                    l.evaluate(columns, row)?,
+                    r.evaluate(columns, row)?,
+                    "-",
+                    i64::checked_sub,
+                    |a, b| a - b,
+                )?,
+                Operation::Multiply(l, r) => Self::arithmetic(
+                    l.evaluate(columns, row)?,
+                    r.evaluate(columns, row)?,
+                    "*",
+                    i64::checked_mul,
+                    |a, b| a * b,
+                )?,
+                Operation::Divide(l, r) => {
+                    let (l, r) = (l.evaluate(columns, row)?, r.evaluate(columns, row)?);
+                    if r == Value::Integer(0) {
+                        return Err(Error::Internal("cannot divide by zero".to_string()));
+                    }
+                    Self::arithmetic(l, r, "/", i64::checked_div, |a, b| a / b)?
+                }
+                Operation::Negate(e) => match e.evaluate(columns, row)? {
+                    Value::Integer(i) => Value::Integer(
+                        i.checked_neg()
+                            .ok_or(Error::Internal("integer overflow".to_string()))?,
+                    ),
+                    Value::Float(f) => Value::Float(-f),
+                    Value::Null => Value::Null,
+                    v => return Err(Error::Internal(format!("cannot negate {:?}", v))),
+                },
+            },
+            Expression::Function(name, args) => {
+                let args = args
+                    .iter()
+                    .map(|a| a.evaluate(columns, row))
+                    .collect::<Result<Vec<_>>>()?;
+                Self::call_function(name, args)?
+            }
+        })
+    }
+
+    // 表达式本身或者其中的子表达式是否满足条件
+    pub fn contains(&self, f: &impl Fn(&Expression) -> bool) -> bool {
+        if f(self) {
+            return true;
+        }
+        match self {
+            Expression::Consts(_) | Expression::Field(_) => false,
+            Expression::Operation(op) => match op {
+                Operation::And(l, r)
+                | Operation::Or(l, r)
+                | Operation::Equal(l, r)
+                | Operation::NotEqual(l, r)
+                | Operation::GreaterThan(l, r)
+                | Operation::GreaterThanOrEqual(l, r)
+                | Operation::LessThan(l, r)
+                | Operation::LessThanOrEqual(l, r)
+                | Operation::Add(l, r)
+                | Operation::Subtract(l, r)
+                | Operation::Multiply(l, r)
+                | Operation::Divide(l, r) => l.contains(f) || r.contains(f),
+                Operation::Not(e) | Operation::IsNull(e) | Operation::Negate(e) => e.contains(f),
+            },
+            Expression::Function(_, args) => args.iter().any(|a| a.contains(f)),
+        }
+    }
+
+    // 使用 f 转换每一个直接的子表达式
+    pub fn map_children(
+        self,
+        f: &mut impl FnMut(Expression) -> Result<Expression>,
+    ) -> Result<Expression> {
+        let mut map = |e: Box<Expression>| -> Result<Box<Expression>> { Ok(Box::new(f(*e)?)) };
+        Ok(match self {
+            Expression::Consts(_) | Expression::Field(_) => self,
+            Expression::Operation(op) => Expression::Operation(match op {
+                Operation::And(l, r) => Operation::And(map(l)?, map(r)?),
+                Operation::Or(l, r) => Operation::Or(map(l)?, map(r)?),
+                Operation::Not(e) => Operation::Not(map(e)?),
+                Operation::Equal(l, r) => Operation::Equal(map(l)?, map(r)?),
+                Operation::NotEqual(l, r) => Operation::NotEqual(map(l)?, map(r)?),
+                Operation::GreaterThan(l, r) => Operation::GreaterThan(map(l)?, map(r)?),
+                Operation::GreaterThanOrEqual(l, r) => {
+                    Operation::GreaterThanOrEqual(map(l)?, map(r)?)
+                }
+                Operation::LessThan(l, r) => Operation::LessThan(map(l)?, map(r)?),
+                Operation::LessThanOrEqual(l, r) => Operation::LessThanOrEqual(map(l)?, map(r)?),
+                Operation::IsNull(e) => Operation::IsNull(map(e)?),
+                Operation::Add(l, r) => Operation::Add(map(l)?, map(r)?),
+                Operation::Subtract(l, r) => Operation::Subtract(map(l)?, map(r)?),
+                Operation::Multiply(l, r) => Operation::Multiply(map(l)?, map(r)?),
+                Operation::Divide(l, r) => Operation::Divide(map(l)?, map(r)?),
+                Operation::Negate(e) => Operation::Negate(map(e)?),
+            }),
+            Expression::Function(name, args) => {
+                Expression::Function(name, args.into_iter().map(f).collect::<Result<_>>()?)
+            }
+        })
+    }
+
+    // 算术运算，任意一边为 NULL 时结果为 NULL，整数和浮点数运算结果为浮点数
+    fn arithmetic(
+        l: Value,
+        r: Value,
+        op: &str,
+        int_op: fn(i64, i64) -> Option<i64>,
+        float_op: fn(f64, f64) -> f64,
+    ) -> Result<Value> {
+        Ok(match (l, r) {
+            (Value::Integer(a), Value::Integer(b)) => {
+                Value::Integer(int_op(a, b).ok_or(Error::Internal("integer overflow".to_string()))?)
+            }
+            (Value::Integer(a), Value::Float(b)) => Value::Float(float_op(a as f64, b)),
+            (Value::Float(a), Value::Integer(b)) => Value::Float(float_op(a, b as f64)),
+            (Value::Float(a), Value::Float(b)) => Value::Float(float_op(a, b)),
+            (Value::Null, Value::Integer(_) | Value::Float(_) | Value::Null)
+            | (Value::Integer(_) | Value::Float(_), Value::Null) => Value::Null,
+            (l, r) => {
+                return Err(Error::Internal(format!(
+                    "cannot apply {:?} {} {:?}",
+                    l, op, r
+                )))
+            }
+        })
+    }
+
+    // 内置函数
+    fn call_function(name: &str, args: Vec<Value>) -> Result<Value> {
+        // 先检查函数名和参数个数，参数为 NULL 时结果为 NULL
+        if !matches!(
+            (name, args.as_slice()),
+            ("upper" | "lower" | "length" | "abs", [_])
+        ) {
+            return Err(Error::Internal(format!(
+                "unsupported function call {}({:?})",
+                name, args
+            )));
+        }
+        Ok(match (name, args.as_slice()) {
+            (_, [Value::Null]) => Value::Null,
+            ("upper", [Value::String(s)]) => Value::String(s.to_uppercase()),
+            ("lower", [Value::String(s)]) => Value::String(s.to_lowercase()),
+            ("length", [Value::String(s)]) => Value::Integer(s.chars().count() as i64),
+            ("abs", [Value::Integer(i)]) => Value::Integer(
+                i.checked_abs()
+                    .ok_or(Error::Internal("integer overflow".to_string()))?,
+            ),
+            ("abs", [Value::Float(f)]) => Value::Float(f.abs()),
+            _ => {
+                return Err(Error::Internal(format!(
+                    "unsupported function call {}({:?})",
+                    name, args
+                )))
+            }
+        })
+    }
+
+    // 比较运算，任意一边为 NULL 时结果为 NULL
+    fn compare<F: Fn(std::cmp::Ordering) -> bool>(
+        columns: &[String],
+        row: &Row,
+        l: &Expression,
+        r: &Expression,
+        f: F,
+    ) -> Result<Value> {
+        let (l, r) = (l.evaluate(columns, row)?, r.evaluate(columns, row)?);
+        if l == Value::Null || r == Value::Null {
+            return Ok(Value::Null);
+        }
+        match l.compare(&r) {
+            Some(ordering) => Ok(Value::Boolean(f(ordering))),
+            None => Err(Error::Internal(format!(
+                "cannot compare {:?} and {:?}",
+                l, r
+            ))),
+        }
+    }
+}
+
+// 表达式的文本形式，用作没有别名时的结果列名
+impl Display for Expression {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // 嵌套的运算加上括号，保证优先级不会产生歧义
+        let wrap = |e: &Expression| match e {
+            Expression::Operation(_) => format!("({})", e),
+            e => e.to_string(),
+        };
+        match self {
+            Expression::Consts(c) => match c {
+                Consts::Null => write!(f, "NULL"),
+                Consts::Boolean(b) => write!(f, "{}", if *b { "TRUE" } else { "FALSE" }),
+                Consts::Integer(i) => write!(f, "{}", i),
+                Consts::Float(v) => write!(f, "{}", v),
+                Consts::String(s) => write!(f, "'{}'", s),
+            },
+            Expression::Field(name) => write!(f, "{}", name),
+            Expression::Operation(op) => match op {
+                Operation::And(l, r) => write!(f, "{} AND {}", wrap(l), wrap(r)),
+                Operation::Or(l, r) => write!(f, "{} OR {}", wrap(l), wrap(r)),
+                Operation::Not(e) => write!(f, "NOT {}", wrap(e)),
+                Operation::Equal(l, r) => write!(f, "{} = {}", wrap(l), wrap(r)),
+                Operation::NotEqual(l, r) => write!(f, "{} != {}", wrap(l), wrap(r)),
+                Operation::GreaterThan(l, r) => write!(f, "{} > {}", wrap(l), wrap(r)),
+                Operation::GreaterThanOrEqual(l, r) => write!(f, "{} >= {}", wrap(l), wrap(r)),
+                Operation::LessThan(l, r) => write!(f, "{} < {}", wrap(l), wrap(r)),
+                Operation::LessThanOrEqual(l, r) => write!(f, "{} <= {}", wrap(l), wrap(r)),
+                Operation::IsNull(e) => write!(f, "{} IS NULL", wrap(e)),
+                Operation::Add(l, r) => write!(f, "{} + {}", wrap(l), wrap(r)),
+                Operation::Subtract(l, r) => write!(f, "{} - {}", wrap(l), wrap(r)),
+                Operation::Multiply(l, r) => write!(f, "{} * {}", wrap(l), wrap(r)),
+                Operation::Divide(l, r) => write!(f, "{} / {}", wrap(l), wrap(r)),
+                Operation::Negate(e) => write!(f, "-{}", wrap(e)),
+            },
+            Expression::Function(name, args) => {
+                let args = args.iter().map(|a| a.to_string()).collect::<Vec<_>>();
+                write!(f, "{}({})", name, args.join(", "))
+            }
+        }
+    }
+}
@@ -19,6 +19,8 @@ pub enum Token {
     CloseParen,
     // 逗号 ,
     Comma,
+    // 点 .
+    Period,
     // 分号 ;
     Semicolon,
     // 星号 *
@@ -29,6 +31,18 @@ pub enum Token {
     Minus,
     // 斜杠 /
     Slash,
+    // 等于 =
+    Equal,
+    // 不等于 != 或 <>
+    NotEqual,
+    // 大于 >
+    GreaterThan,
+    // 大于等于 >=
+    GreaterThanOrEqual,
+    // 小于 <
+    LessThan,
+    // 小于等于 <=
+    LessThanOrEqual,
 }
 
 impl Display for Token {
@@ -41,11 +55,18 @@ impl Display for Token {
             Token::OpenParen => "(",
             Token::CloseParen => ")",
             Token::Comma => ",",
+            Token::Period => ".",
             Token::Semicolon => ";",
             Token::Asterisk => "*",
             Token::Plus => "+",
             Token::Minus => "-",
             Token::Slash => "/",
+            Token::Equal => "=",
+            Token::NotEqual => "!=",
+            Token::GreaterThan => ">",
+            Token::GreaterThanOrEqual => ">=",
+            Token::LessThan => "<",
+            Token::LessThanOrEqual => "<=",
         })
     }
 }
@@ -75,6 +96,42 @@ pub enum Keyword {
     Null,
     Primary,
     Key,
+    Where,
+    And,
+    Or,
+    Is,
+    As,
+    Update,
+    Set,
+    Delete,
+    Begin,
+    Commit,
+    Rollback,
+    Read,
+    Only,
+    Of,
+    System,
+    Time,
+    Index,
+    Unique,
+    On,
+    Join,
+    Inner,
+    Left,
+    Right,
+    Cross,
+    Outer,
+    Group,
+    By,
+    Having,
+    Order,
+    Asc,
+    Desc,
+    Nulls,
+    First,
+    Last,
+    Limit,
+    Offset,
 }
 
 impl Keyword {
@@ -103,6 +160,42 @@ impl Keyword {
             "NULL" => Keyword::Null,
             "PRIMARY" => Keyword::Primary,
             "KEY" => Keyword::Key,
+            "WHERE" => Keyword::Where,
+            "AND" => Keyword::And,
+            "OR" => Keyword::Or,
+            "IS" => Keyword::Is,
+            "AS" => Keyword::As,
+            "UPDATE" => Keyword::Update,
+            "SET" => Keyword::Set,
+            "DELETE" => Keyword::Delete,
+            "BEGIN" => Keyword::Begin,
+            "COMMIT" => Keyword::Commit,
+            "ROLLBACK" => Keyword::Rollback,
+            "READ" => Keyword::Read,
+            "ONLY" => Keyword::Only,
+            "OF" => Keyword::Of,
+            "SYSTEM" => Keyword::System,
+            "TIME" => Keyword::Time,
+            "INDEX" => Keyword::Index,
+            "UNIQUE" => Keyword::Unique,
+            "ON" => Keyword::On,
+            "JOIN" => Keyword::Join,
+            "INNER" => Keyword::Inner,
+            "LEFT" => Keyword::Left,
+            "RIGHT" => Keyword::Right,
+            "CROSS" => Keyword::Cross,
+            "OUTER" => Keyword::Outer,
+            "GROUP" => Keyword::Group,
+            "BY" => Keyword::By,
+            "HAVING" => Keyword::Having,
+            "ORDER" => Keyword::Order,
+            "ASC" => Keyword::Asc,
+            "DESC" => Keyword::Desc,
+            "NULLS" => Keyword::Nulls,
+            "FIRST" => Keyword::First,
+            "LAST" => Keyword::Last,
+            "LIMIT" => Keyword::Limit,
+            "OFFSET" => Keyword::Offset,
             _ => return None,
         })
     }
@@ -132,6 +225,42 @@ impl Keyword {
             Keyword::Null => "NULL",
             Keyword::Primary => "PRIMARY",
             Keyword::Key => "KEY",
+            Keyword::Where => "WHERE",
+            Keyword::And => "AND",
+            Keyword::Or => "OR",
+            Keyword::Is => "IS",
+            Keyword::As => "AS",
+            Keyword::Update => "UPDATE",
+            Keyword::Set => "SET",
+            Keyword::Delete => "DELETE",
+            Keyword::Begin => "BEGIN",
+            Keyword::Commit => "COMMIT",
+            Keyword::Rollback => "ROLLBACK",
+            Keyword::Read => "READ",
+            Keyword::Only => "ONLY",
+            Keyword::Of => "OF",
+            Keyword::System => "SYSTEM",
+            Keyword::Time => "TIME",
+            Keyword::Index => "INDEX",
+            Keyword::Unique => "UNIQUE",
+            Keyword::On => "ON",
+            Keyword::Join => "JOIN",
+            Keyword::Inner => "INNER",
+            Keyword::Left => "LEFT",
+            Keyword::Right => "RIGHT",
+            Keyword::Cross => "CROSS",
+            Keyword::Outer => "OUTER",
+            Keyword::Group => "GROUP",
+            Keyword::By => "BY",
+            Keyword::Having => "HAVING",
+            Keyword::Order => "ORDER",
+            Keyword::Asc => "ASC",
+            Keyword::Desc => "DESC",
+            Keyword::Nulls => "NULLS",
+            Keyword::First => "FIRST",
+            Keyword::Last => "LAST",
+            Keyword::Limit => "LIMIT",
+            Keyword::Offset => "OFFSET",
         }
     }
 }
@@ -205,7 +334,7 @@ impl<'a> Lexer<'a> {
             Some('\'') => self.scan_string(), // 扫描字符串
             Some(c) if c.is_ascii_digit() => Ok(self.scan_number()), // 扫描数字
             Some(c) if c.is_alphabetic() => Ok(self.scan_ident()), // 扫描 Ident 类型
-            Some(_) => Ok(self.scan_symbol()), // 扫描符号
+            Some(_) => self.scan_symbol(),    // 扫描符号
             None => Ok(None),
         }
     }
@@ -256,18 +385,39 @@ impl<'a> Lexer<'a> {
     }
 
     // 扫描符号
-    fn scan_symbol(&mut self) -> Option<Token> {
-        self.next_if_token(|c| match c {
+    fn scan_symbol(&mut self) -> Result<Option<Token>> {
+        let token = match self.next_if_token(|c| match c {
             '*' => Some(Token::Asterisk),
             '(' => Some(Token::OpenParen),
             ')' => Some(Token::CloseParen),
             ',' => Some(Token::Comma),
+            '.' => Some(Token::Period),
             ';' => Some(Token::Semicolon),
             '+' => Some(Token::Plus),
             '-' => Some(Token::Minus),
             '/' => Some(Token::Slash),
+            '=' => Some(Token::Equal),
+            '>' => Some(Token::GreaterThan),
+            '<' => Some(Token::LessThan),
+            // ! 只能和 = 组成 !=，先占位，后面再判断
+            '!' => Some(Token::NotEqual),
             _ => None,
-        })
+        }) {
+            Some(token) => token,
+            None => return Ok(None),
+        };
+
+        // 处理两个字符组成的比较符号 >= <= <> !=
+        Ok(Some(match token {
+            Token::GreaterThan if self.next_if(|c| c == '=').is_some() => Token::GreaterThanOrEqual,
+            Token::LessThan if self.next_if(|c| c == '=').is_some() => Token::LessThanOrEqual,
+            Token::LessThan if self.next_if(|c| c == '>').is_some() => Token::NotEqual,
+            Token::NotEqual if self.next_if(|c| c == '=').is_some() => Token::NotEqual,
+            Token::NotEqual => {
+                return Err(Error::Parse("[Lexer] Expected = after !".to_string()));
+            }
+            token => token,
+        }))
     }
 }
 
@@ -415,6 +565,136 @@ mod tests {
                 Token::Semicolon,
             ]
         );
+
+        let tokens2 = Lexer::new("select a, b * 2 as c, upper(d) from tbl;")
+            .peekable()
+            .collect::<Result<Vec<_>>>()?;
+
+        assert_eq!(
+            tokens2,
+            vec![
+                Token::Keyword(Keyword::Select),
+                Token::Ident("a".to_string()),
+                Token::Comma,
+                Token::Ident("b".to_string()),
+                Token::Asterisk,
+                Token::Number("2".to_string()),
+                Token::Keyword(Keyword::As),
+                Token::Ident("c".to_string()),
+                Token::Comma,
+                Token::Ident("upper".to_string()),
+                Token::OpenParen,
+                Token::Ident("d".to_string()),
+                Token::CloseParen,
+                Token::Keyword(Keyword::From),
+                Token::Ident("tbl".to_string()),
+                Token::Semicolon,
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_lexer_select_where() -> Result<()> {
+        let tokens = Lexer::new("select * from tbl where a >= 1 and b <> 'x' or c != 2.5;")
+            .peekable()
+            .collect::<Result<Vec<_>>>()?;
+
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Keyword(Keyword::Select),
+                Token::Asterisk,
+                Token::Keyword(Keyword::From),
+                Token::Ident("tbl".to_string()),
+                Token::Keyword(Keyword::Where),
+                Token::Ident("a".to_string()),
+                Token::GreaterThanOrEqual,
+                Token::Number("1".to_string()),
+                Token::Keyword(Keyword::And),
+                Token::Ident("b".to_string()),
+                Token::NotEqual,
+                Token::String("x".to_string()),
+                Token::Keyword(Keyword::Or),
+                Token::Ident("c".to_string()),
+                Token::NotEqual,
+                Token::Number("2.5".to_string()),
+                Token::Semicolon,
+            ]
+        );
+
+        let tokens = Lexer::new("a<b<=c>d=e")
+            .peekable()
+            .collect::<Result<Vec<_>>>()?;
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Ident("a".to_string()),
+                Token::LessThan,
+                Token::Ident("b".to_string()),
+                Token::LessThanOrEqual,
+                Token::Ident("c".to_string()),
+                Token::GreaterThan,
+                Token::Ident("d".to_string()),
+                Token::Equal,
+                Token::Ident("e".to_string()),
+            ]
+        );
+
+        assert!(Lexer::new("a ! b").collect::<Result<Vec<_>>>().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_lexer_update() -> Result<()> {
+        let tokens = Lexer::new("update tbl set a = 1, b = b + 1 where c = 2;")
+            .peekable()
+            .collect::<Result<Vec<_>>>()?;
+
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Keyword(Keyword::Update),
+                Token::Ident("tbl".to_string()),
+                Token::Keyword(Keyword::Set),
+                Token::Ident("a".to_string()),
+                Token::Equal,
+                Token::Number("1".to_string()),
+                Token::Comma,
+                Token::Ident("b".to_string()),
+                Token::Equal,
+                Token::Ident("b".to_string()),
+                Token::Plus,
+                Token::Number("1".to_string()),
+                Token::Keyword(Keyword::Where),
+                Token::Ident("c".to_string()),
+                Token::Equal,
+                Token::Number("2".to_string()),
+                Token::Semicolon,
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_lexer_delete() -> Result<()> {
+        let tokens = Lexer::new("delete from tbl where a = 1;")
+            .peekable()
+            .collect::<Result<Vec<_>>>()?;
+
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Keyword(Keyword::Delete),
+                Token::Keyword(Keyword::From),
+                Token::Ident("tbl".to_string()),
+                Token::Keyword(Keyword::Where),
+                Token::Ident("a".to_string()),
+                Token::Equal,
+                Token::Number("1".to_string()),
+                Token::Semicolon,
+            ]
+        );
         Ok(())
     }
 }
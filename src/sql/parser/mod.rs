@@ -1,4 +1,4 @@
-use std::iter::Peekable;
+use std::{collections::BTreeMap, iter::Peekable};
 
 use ast::Column;
 use lexer::{Keyword, Lexer, Token};
@@ -40,6 +40,11 @@ impl<'a> Parser<'a> {
             Some(Token::Keyword(Keyword::Create)) => self.parse_ddl(),
             Some(Token::Keyword(Keyword::Select)) => self.parse_select(),
             Some(Token::Keyword(Keyword::Insert)) => self.parse_insert(),
+            Some(Token::Keyword(Keyword::Update)) => self.parse_update(),
+            Some(Token::Keyword(Keyword::Delete)) => self.parse_delete(),
+            Some(Token::Keyword(Keyword::Begin)) => self.parse_transaction(),
+            Some(Token::Keyword(Keyword::Commit)) => self.parse_transaction(),
+            Some(Token::Keyword(Keyword::Rollback)) => self.parse_transaction(),
             Some(t) => Err(Error::Parse(format!("[Parser] Unexpected token {}", t))),
             None => Err(Error::Parse(format!("[Parser] Unexpected end of input"))),
         }
@@ -50,6 +55,11 @@ impl<'a> Parser<'a> {
         match self.next()? {
             Token::Keyword(Keyword::Create) => match self.next()? {
                 Token::Keyword(Keyword::Table) => self.parse_ddl_create_table(),
+                Token::Keyword(Keyword::Index) => self.parse_ddl_create_index(false),
+                Token::Keyword(Keyword::Unique) => {
+                    self.next_expect(Token::Keyword(Keyword::Index))?;
+                    self.parse_ddl_create_index(true)
+                }
                 token => Err(Error::Parse(format!("[Parser] Unexpected token {}", token))),
             },
             token => Err(Error::Parse(format!("[Parser] Unexpected token {}", token))),
@@ -58,13 +68,194 @@ impl<'a> Parser<'a> {
 
     // 解析 Select 语句
     fn parse_select(&mut self) -> Result<ast::Statement> {
-        self.next_expect(Token::Keyword(Keyword::Select))?;
-        self.next_expect(Token::Asterisk)?;
+        let select = self.parse_select_clause()?;
         self.next_expect(Token::Keyword(Keyword::From))?;
 
-        // 表名
-        let table_name = self.next_ident()?;
-        Ok(ast::Statement::Select { table_name })
+        Ok(ast::Statement::Select {
+            select,
+            from: self.parse_from_clause()?,
+            where_clause: self.parse_where_clause()?,
+            group_by: self.parse_group_clause()?,
+            having: self.parse_having_clause()?,
+            order_by: self.parse_order_clause()?,
+            limit: match self.next_if_token(Token::Keyword(Keyword::Limit)) {
+                Some(_) => Some(Box::new(self.parse_expression()?)),
+                None => None,
+            },
+            offset: match self.next_if_token(Token::Keyword(Keyword::Offset)) {
+                Some(_) => Some(Box::new(self.parse_expression()?)),
+                None => None,
+            },
+        })
+    }
+
+    // 解析 From 子句，多个表的连接从左到右依次组合
+    // from a, b 等价于 from a cross join b
+    fn parse_from_clause(&mut self) -> Result<ast::FromItem> {
+        let mut item = self.parse_from_table()?;
+        loop {
+            let join_type = if self.next_if_token(Token::Comma).is_some() {
+                ast::JoinType::Cross
+            } else {
+                match self.parse_join_type()? {
+                    Some(join_type) => join_type,
+                    None => break,
+                }
+            };
+            let right = self.parse_from_table()?;
+            let predicate = match join_type {
+                ast::JoinType::Cross => None,
+                _ => {
+                    self.next_expect(Token::Keyword(Keyword::On))?;
+                    Some(self.parse_expression()?)
+                }
+            };
+            item = ast::FromItem::Join {
+                left: Box::new(item),
+                right: Box::new(right),
+                join_type,
+                predicate,
+            };
+        }
+        Ok(item)
+    }
+
+    // 解析表名以及别名，别名之前的 AS 可以省略
+    fn parse_from_table(&mut self) -> Result<ast::FromItem> {
+        let name = self.next_ident()?;
+        let alias = match self.next_if_token(Token::Keyword(Keyword::As)) {
+            Some(_) => Some(self.next_ident()?),
+            None => match self.peek()? {
+                Some(Token::Ident(_)) => Some(self.next_ident()?),
+                _ => None,
+            },
+        };
+        Ok(ast::FromItem::Table { name, alias })
+    }
+
+    // 解析连接类型，CROSS JOIN、[INNER] JOIN、LEFT [OUTER] JOIN、RIGHT [OUTER] JOIN
+    fn parse_join_type(&mut self) -> Result<Option<ast::JoinType>> {
+        let join_type = match self.peek()? {
+            Some(Token::Keyword(Keyword::Cross)) => ast::JoinType::Cross,
+            Some(Token::Keyword(Keyword::Join)) | Some(Token::Keyword(Keyword::Inner)) => {
+                ast::JoinType::Inner
+            }
+            Some(Token::Keyword(Keyword::Left)) => ast::JoinType::Left,
+            Some(Token::Keyword(Keyword::Right)) => ast::JoinType::Right,
+            _ => return Ok(None),
+        };
+        match self.next()? {
+            Token::Keyword(Keyword::Left) | Token::Keyword(Keyword::Right) => {
+                self.next_if_token(Token::Keyword(Keyword::Outer));
+                self.next_expect(Token::Keyword(Keyword::Join))?;
+            }
+            Token::Keyword(Keyword::Cross) | Token::Keyword(Keyword::Inner) => {
+                self.next_expect(Token::Keyword(Keyword::Join))?;
+            }
+            _ => {}
+        }
+        Ok(Some(join_type))
+    }
+
+    // 解析 Select 之后的列信息，select * 返回空列表
+    fn parse_select_clause(&mut self) -> Result<Vec<(ast::Expression, Option<String>)>> {
+        self.next_expect(Token::Keyword(Keyword::Select))?;
+
+        let mut select = Vec::new();
+        if self.next_if_token(Token::Asterisk).is_some() {
+            return Ok(select);
+        }
+
+        loop {
+            let expr = self.parse_expression()?;
+            // 查看是否有别名
+            let alias = match self.next_if_token(Token::Keyword(Keyword::As)) {
+                Some(_) => Some(self.next_ident()?),
+                None => None,
+            };
+            select.push((expr, alias));
+            // 如果没有逗号，列解析完成，跳出
+            if self.next_if_token(Token::Comma).is_none() {
+                break;
+            }
+        }
+        Ok(select)
+    }
+
+    // 解析 Where 条件
+    fn parse_where_clause(&mut self) -> Result<Option<ast::Expression>> {
+        if self.next_if_token(Token::Keyword(Keyword::Where)).is_none() {
+            return Ok(None);
+        }
+        Ok(Some(self.parse_expression()?))
+    }
+
+    // 解析 Group By 子句，可以有多个分组表达式
+    fn parse_group_clause(&mut self) -> Result<Vec<ast::Expression>> {
+        let mut group_by = Vec::new();
+        if self.next_if_token(Token::Keyword(Keyword::Group)).is_none() {
+            return Ok(group_by);
+        }
+        self.next_expect(Token::Keyword(Keyword::By))?;
+        loop {
+            group_by.push(self.parse_expression()?);
+            if self.next_if_token(Token::Comma).is_none() {
+                break;
+            }
+        }
+        Ok(group_by)
+    }
+
+    fn parse_having_clause(&mut self) -> Result<Option<ast::Expression>> {
+        if self
+            .next_if_token(Token::Keyword(Keyword::Having))
+            .is_none()
+        {
+            return Ok(None);
+        }
+        Ok(Some(self.parse_expression()?))
+    }
+
+    // 解析 Order By 子句，expr [ASC|DESC] [NULLS FIRST|LAST], ...
+    fn parse_order_clause(&mut self) -> Result<Vec<ast::OrderBy>> {
+        let mut order_by = Vec::new();
+        if self.next_if_token(Token::Keyword(Keyword::Order)).is_none() {
+            return Ok(order_by);
+        }
+        self.next_expect(Token::Keyword(Keyword::By))?;
+        loop {
+            let expr = self.parse_expression()?;
+            let direction = match self.peek()? {
+                Some(Token::Keyword(Keyword::Asc)) => {
+                    self.next()?;
+                    ast::OrderDirection::Asc
+                }
+                Some(Token::Keyword(Keyword::Desc)) => {
+                    self.next()?;
+                    ast::OrderDirection::Desc
+                }
+                _ => ast::OrderDirection::Asc,
+            };
+            let nulls_first = match self.next_if_token(Token::Keyword(Keyword::Nulls)) {
+                Some(_) => match self.next()? {
+                    Token::Keyword(Keyword::First) => true,
+                    Token::Keyword(Keyword::Last) => false,
+                    token => {
+                        return Err(Error::Parse(format!("[Parser] Unexpected token {}", token)))
+                    }
+                },
+                None => direction == ast::OrderDirection::Desc,
+            };
+            order_by.push(ast::OrderBy {
+                expr,
+                direction,
+                nulls_first,
+            });
+            if self.next_if_token(Token::Comma).is_none() {
+                break;
+            }
+        }
+        Ok(order_by)
     }
 
     // 解析 Insert 语句
@@ -123,6 +314,85 @@ impl<'a> Parser<'a> {
         })
     }
 
+    // 解析 Update 语句
+    fn parse_update(&mut self) -> Result<ast::Statement> {
+        self.next_expect(Token::Keyword(Keyword::Update))?;
+        // 表名
+        let table_name = self.next_ident()?;
+        self.next_expect(Token::Keyword(Keyword::Set))?;
+
+        // 解析需要更新的列，update tbl set a = 1, b = 2
+        let mut columns = BTreeMap::new();
+        loop {
+            let col = self.next_ident()?;
+            self.next_expect(Token::Equal)?;
+            let value = self.parse_expression()?;
+            if columns.contains_key(&col) {
+                return Err(Error::Parse(format!(
+                    "[Parser] Duplicate column {} for update",
+                    col
+                )));
+            }
+            columns.insert(col, value);
+            // 如果没有逗号，列解析完成，跳出
+            if self.next_if_token(Token::Comma).is_none() {
+                break;
+            }
+        }
+
+        Ok(ast::Statement::Update {
+            table_name,
+            columns,
+            where_clause: self.parse_where_clause()?,
+        })
+    }
+
+    // 解析 Delete 语句
+    fn parse_delete(&mut self) -> Result<ast::Statement> {
+        self.next_expect(Token::Keyword(Keyword::Delete))?;
+        self.next_expect(Token::Keyword(Keyword::From))?;
+        // 表名
+        let table_name = self.next_ident()?;
+
+        Ok(ast::Statement::Delete {
+            table_name,
+            where_clause: self.parse_where_clause()?,
+        })
+    }
+
+    // 解析事务语句 Begin、Commit、Rollback
+    fn parse_transaction(&mut self) -> Result<ast::Statement> {
+        match self.next()? {
+            Token::Keyword(Keyword::Begin) => {
+                // begin read only as of system time 10;
+                let mut read_only = false;
+                let mut as_of = None;
+                if self.next_if_token(Token::Keyword(Keyword::Read)).is_some() {
+                    self.next_expect(Token::Keyword(Keyword::Only))?;
+                    read_only = true;
+                    if self.next_if_token(Token::Keyword(Keyword::As)).is_some() {
+                        self.next_expect(Token::Keyword(Keyword::Of))?;
+                        self.next_expect(Token::Keyword(Keyword::System))?;
+                        self.next_expect(Token::Keyword(Keyword::Time))?;
+                        as_of = match self.next()? {
+                            Token::Number(n) => Some(n.parse()?),
+                            token => {
+                                return Err(Error::Parse(format!(
+                                    "[Parser] Unexpected token {}",
+                                    token
+                                )))
+                            }
+                        };
+                    }
+                }
+                Ok(ast::Statement::Begin { read_only, as_of })
+            }
+            Token::Keyword(Keyword::Commit) => Ok(ast::Statement::Commit),
+            Token::Keyword(Keyword::Rollback) => Ok(ast::Statement::Rollback),
+            token => Err(Error::Parse(format!("[Parser] Unexpected token {}", token))),
+        }
+    }
+
     // 解析 Create Table 语句
     fn parse_ddl_create_table(&mut self) -> Result<ast::Statement> {
         // 期望是 Table 名
@@ -147,6 +417,23 @@ impl<'a> Parser<'a> {
         })
     }
 
+    // 解析 Create Index 语句
+    fn parse_ddl_create_index(&mut self, unique: bool) -> Result<ast::Statement> {
+        let name = self.next_ident()?;
+        self.next_expect(Token::Keyword(Keyword::On))?;
+        let table_name = self.next_ident()?;
+        // 目前只支持单列索引
+        self.next_expect(Token::OpenParen)?;
+        let column = self.next_ident()?;
+        self.next_expect(Token::CloseParen)?;
+        Ok(ast::Statement::CreateIndex {
+            name,
+            table_name,
+            column,
+            unique,
+        })
+    }
+
     // 解析列信息
     fn parse_ddl_column(&mut self) -> Result<ast::Column> {
         let mut column = Column {
@@ -166,6 +453,7 @@ impl<'a> Parser<'a> {
             },
             nullable: None,
             default: None,
+            primary_key: false,
         };
 
         // 解析列的默认值，以及是否可以为空
@@ -177,6 +465,10 @@ impl<'a> Parser<'a> {
                     column.nullable = Some(false);
                 }
                 Keyword::Default => column.default = Some(self.parse_expression()?),
+                Keyword::Primary => {
+                    self.next_expect(Token::Keyword(Keyword::Key))?;
+                    column.primary_key = true;
+                }
                 k => return Err(Error::Parse(format!("[Parser] Unexpected keyword {}", k))),
             }
         }
@@ -184,8 +476,112 @@ impl<'a> Parser<'a> {
         Ok(column)
     }
 
-    // 解析表达式
+    // 解析表达式，优先级从低到高依次是 OR、AND、NOT、比较运算、加减、乘除、正负号
     fn parse_expression(&mut self) -> Result<ast::Expression> {
+        let mut expr = self.parse_expression_and()?;
+        while self.next_if_token(Token::Keyword(Keyword::Or)).is_some() {
+            let r = self.parse_expression_and()?;
+            expr = ast::Operation::Or(Box::new(expr), Box::new(r)).into();
+        }
+        Ok(expr)
+    }
+
+    fn parse_expression_and(&mut self) -> Result<ast::Expression> {
+        let mut expr = self.parse_expression_not()?;
+        while self.next_if_token(Token::Keyword(Keyword::And)).is_some() {
+            let r = self.parse_expression_not()?;
+            expr = ast::Operation::And(Box::new(expr), Box::new(r)).into();
+        }
+        Ok(expr)
+    }
+
+    fn parse_expression_not(&mut self) -> Result<ast::Expression> {
+        if self.next_if_token(Token::Keyword(Keyword::Not)).is_some() {
+            let expr = self.parse_expression_not()?;
+            return Ok(ast::Operation::Not(Box::new(expr)).into());
+        }
+        self.parse_expression_compare()
+    }
+
+    // 解析比较运算，例如 a > 1、b IS NULL、c IS NOT NULL
+    fn parse_expression_compare(&mut self) -> Result<ast::Expression> {
+        let mut expr = self.parse_expression_additive()?;
+        loop {
+            let build: fn(Box<ast::Expression>, Box<ast::Expression>) -> ast::Operation =
+                match self.peek()? {
+                    Some(Token::Equal) => ast::Operation::Equal,
+                    Some(Token::NotEqual) => ast::Operation::NotEqual,
+                    Some(Token::GreaterThan) => ast::Operation::GreaterThan,
+                    Some(Token::GreaterThanOrEqual) => ast::Operation::GreaterThanOrEqual,
+                    Some(Token::LessThan) => ast::Operation::LessThan,
+                    Some(Token::LessThanOrEqual) => ast::Operation::LessThanOrEqual,
+                    Some(Token::Keyword(Keyword::Is)) => {
+                        self.next()?;
+                        let not = self.next_if_token(Token::Keyword(Keyword::Not)).is_some();
+                        self.next_expect(Token::Keyword(Keyword::Null))?;
+                        expr = ast::Operation::IsNull(Box::new(expr)).into();
+                        if not {
+                            expr = ast::Operation::Not(Box::new(expr)).into();
+                        }
+                        continue;
+                    }
+                    _ => break,
+                };
+            self.next()?;
+            let r = self.parse_expression_additive()?;
+            expr = build(Box::new(expr), Box::new(r)).into();
+        }
+        Ok(expr)
+    }
+
+    // 解析加减运算
+    fn parse_expression_additive(&mut self) -> Result<ast::Expression> {
+        let mut expr = self.parse_expression_multiplicative()?;
+        loop {
+            let build: fn(Box<ast::Expression>, Box<ast::Expression>) -> ast::Operation =
+                match self.peek()? {
+                    Some(Token::Plus) => ast::Operation::Add,
+                    Some(Token::Minus) => ast::Operation::Subtract,
+                    _ => break,
+                };
+            self.next()?;
+            let r = self.parse_expression_multiplicative()?;
+            expr = build(Box::new(expr), Box::new(r)).into();
+        }
+        Ok(expr)
+    }
+
+    // 解析乘除运算
+    fn parse_expression_multiplicative(&mut self) -> Result<ast::Expression> {
+        let mut expr = self.parse_expression_unary()?;
+        loop {
+            let build: fn(Box<ast::Expression>, Box<ast::Expression>) -> ast::Operation =
+                match self.peek()? {
+                    Some(Token::Asterisk) => ast::Operation::Multiply,
+                    Some(Token::Slash) => ast::Operation::Divide,
+                    _ => break,
+                };
+            self.next()?;
+            let r = self.parse_expression_unary()?;
+            expr = build(Box::new(expr), Box::new(r)).into();
+        }
+        Ok(expr)
+    }
+
+    // 解析正负号
+    fn parse_expression_unary(&mut self) -> Result<ast::Expression> {
+        if self.next_if_token(Token::Minus).is_some() {
+            let expr = self.parse_expression_unary()?;
+            return Ok(ast::Operation::Negate(Box::new(expr)).into());
+        }
+        if self.next_if_token(Token::Plus).is_some() {
+            return self.parse_expression_unary();
+        }
+        self.parse_expression_atom()
+    }
+
+    // 解析最基本的表达式：常量、列名、函数调用、括号
+    fn parse_expression_atom(&mut self) -> Result<ast::Expression> {
         Ok(match self.next()? {
             Token::Number(n) => {
                 if n.chars().all(|c| c.is_ascii_digit()) {
@@ -200,6 +596,43 @@ impl<'a> Parser<'a> {
             Token::Keyword(Keyword::True) => ast::Consts::Boolean(true).into(),
             Token::Keyword(Keyword::False) => ast::Consts::Boolean(false).into(),
             Token::Keyword(Keyword::Null) => ast::Consts::Null.into(),
+            Token::Ident(ident) => {
+                // 标识符之后是点，说明是带表名的列名，例如 a.x
+                if self.next_if_token(Token::Period).is_some() {
+                    let column = self.next_ident()?;
+                    return Ok(ast::Expression::Field(format!("{}.{}", ident, column)));
+                }
+                // 标识符之后是括号，说明是函数调用
+                if self.next_if_token(Token::OpenParen).is_none() {
+                    return Ok(ast::Expression::Field(ident));
+                }
+                let mut args = Vec::new();
+                // count(*) 的参数解析为列名 *
+                if self.next_if_token(Token::Asterisk).is_some() {
+                    self.next_expect(Token::CloseParen)?;
+                    args.push(ast::Expression::Field("*".to_string()));
+                } else if self.next_if_token(Token::CloseParen).is_none() {
+                    loop {
+                        args.push(self.parse_expression()?);
+                        match self.next()? {
+                            Token::CloseParen => break,
+                            Token::Comma => {}
+                            token => {
+                                return Err(Error::Parse(format!(
+                                    "[Parser] Unexpected token {}",
+                                    token
+                                )))
+                            }
+                        }
+                    }
+                }
+                ast::Expression::Function(ident, args)
+            }
+            Token::OpenParen => {
+                let expr = self.parse_expression()?;
+                self.next_expect(Token::CloseParen)?;
+                expr
+            }
             t => {
                 return Err(Error::Parse(format!(
                     "[Parser] Unexpected expression token {}",
@@ -258,7 +691,12 @@ impl<'a> Parser<'a> {
 
 #[cfg(test)]
 mod tests {
-    use crate::{error::Result, sql::parser::ast};
+    use std::collections::BTreeMap;
+
+    use crate::{
+        error::Result,
+        sql::{parser::ast, types::DataType},
+    };
 
     use super::Parser;
 
@@ -296,6 +734,34 @@ mod tests {
 
         let stmt3 = Parser::new(sql3).parse();
         assert!(stmt3.is_err());
+
+        let sql4 = "create table tbl1 (a int primary key, b text not null);";
+        let stmt4 = Parser::new(sql4).parse()?;
+        assert_eq!(
+            stmt4,
+            ast::Statement::CreateTable {
+                name: "tbl1".to_string(),
+                columns: vec![
+                    ast::Column {
+                        name: "a".to_string(),
+                        datatype: DataType::Integer,
+                        nullable: None,
+                        default: None,
+                        primary_key: true,
+                    },
+                    ast::Column {
+                        name: "b".to_string(),
+                        datatype: DataType::String,
+                        nullable: Some(false),
+                        default: None,
+                        primary_key: false,
+                    },
+                ],
+            }
+        );
+
+        let sql5 = "create table tbl1 (a int primary, b text);";
+        assert!(Parser::new(sql5).parse().is_err());
         Ok(())
     }
 
@@ -350,9 +816,471 @@ mod tests {
         assert_eq!(
             stmt,
             ast::Statement::Select {
-                table_name: "tbl1".to_string()
+                select: vec![],
+                from: ast::FromItem::Table {
+                    name: "tbl1".to_string(),
+                    alias: None,
+                },
+                where_clause: None,
+                group_by: vec![],
+                having: None,
+                order_by: vec![],
+                limit: None,
+                offset: None,
+            }
+        );
+
+        let sql = "select a, b + 1 as c, upper(d), -e * 2 from tbl1;";
+        let stmt = Parser::new(sql).parse()?;
+        assert_eq!(
+            stmt,
+            ast::Statement::Select {
+                select: vec![
+                    (ast::Expression::Field("a".to_string()), None),
+                    (
+                        ast::Operation::Add(
+                            Box::new(ast::Expression::Field("b".to_string())),
+                            Box::new(ast::Consts::Integer(1).into()),
+                        )
+                        .into(),
+                        Some("c".to_string())
+                    ),
+                    (
+                        ast::Expression::Function(
+                            "upper".to_string(),
+                            vec![ast::Expression::Field("d".to_string())]
+                        ),
+                        None
+                    ),
+                    (
+                        ast::Operation::Multiply(
+                            Box::new(
+                                ast::Operation::Negate(Box::new(ast::Expression::Field(
+                                    "e".to_string()
+                                )))
+                                .into()
+                            ),
+                            Box::new(ast::Consts::Integer(2).into()),
+                        )
+                        .into(),
+                        None
+                    ),
+                ],
+                from: ast::FromItem::Table {
+                    name: "tbl1".to_string(),
+                    alias: None,
+                },
+                where_clause: None,
+                group_by: vec![],
+                having: None,
+                order_by: vec![],
+                limit: None,
+                offset: None,
+            }
+        );
+        assert!(Parser::new("select a as from tbl1;").parse().is_err());
+        assert!(Parser::new("select a, from tbl1;").parse().is_err());
+
+        let sql = "select * from tbl1 where a > 1 and not b = 'x' or c is not null;";
+        let stmt = Parser::new(sql).parse()?;
+        assert_eq!(
+            stmt,
+            ast::Statement::Select {
+                select: vec![],
+                from: ast::FromItem::Table {
+                    name: "tbl1".to_string(),
+                    alias: None,
+                },
+                where_clause: Some(
+                    ast::Operation::Or(
+                        Box::new(
+                            ast::Operation::And(
+                                Box::new(
+                                    ast::Operation::GreaterThan(
+                                        Box::new(ast::Expression::Field("a".to_string())),
+                                        Box::new(ast::Consts::Integer(1).into()),
+                                    )
+                                    .into()
+                                ),
+                                Box::new(
+                                    ast::Operation::Not(Box::new(
+                                        ast::Operation::Equal(
+                                            Box::new(ast::Expression::Field("b".to_string())),
+                                            Box::new(ast::Consts::String("x".to_string()).into()),
+                                        )
+                                        .into()
+                                    ))
+                                    .into()
+                                ),
+                            )
+                            .into()
+                        ),
+                        Box::new(
+                            ast::Operation::Not(Box::new(
+                                ast::Operation::IsNull(Box::new(ast::Expression::Field(
+                                    "c".to_string()
+                                )))
+                                .into()
+                            ))
+                            .into()
+                        ),
+                    )
+                    .into()
+                ),
+                group_by: vec![],
+                having: None,
+                order_by: vec![],
+                limit: None,
+                offset: None,
+            }
+        );
+
+        assert!(Parser::new("select * from tbl1 where;").parse().is_err());
+        assert!(Parser::new("select * from tbl1 where a is 1;")
+            .parse()
+            .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parser_update() -> Result<()> {
+        let sql = "update tbl1 set a = 1, b = b + 1.5 where c = 'x';";
+        let stmt = Parser::new(sql).parse()?;
+        assert_eq!(
+            stmt,
+            ast::Statement::Update {
+                table_name: "tbl1".to_string(),
+                columns: BTreeMap::from([
+                    ("a".to_string(), ast::Consts::Integer(1).into()),
+                    (
+                        "b".to_string(),
+                        ast::Operation::Add(
+                            Box::new(ast::Expression::Field("b".to_string())),
+                            Box::new(ast::Consts::Float(1.5).into()),
+                        )
+                        .into()
+                    ),
+                ]),
+                where_clause: Some(
+                    ast::Operation::Equal(
+                        Box::new(ast::Expression::Field("c".to_string())),
+                        Box::new(ast::Consts::String("x".to_string()).into()),
+                    )
+                    .into()
+                ),
+            }
+        );
+
+        let sql = "update tbl1 set a = null;";
+        let stmt = Parser::new(sql).parse()?;
+        assert_eq!(
+            stmt,
+            ast::Statement::Update {
+                table_name: "tbl1".to_string(),
+                columns: BTreeMap::from([("a".to_string(), ast::Consts::Null.into())]),
+                where_clause: None,
+            }
+        );
+
+        assert!(Parser::new("update tbl1 set a = 1, a = 2;")
+            .parse()
+            .is_err());
+        assert!(Parser::new("update tbl1 a = 1;").parse().is_err());
+        assert!(Parser::new("update tbl1 set;").parse().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parser_delete() -> Result<()> {
+        let sql = "delete from tbl1 where a = 1;";
+        let stmt = Parser::new(sql).parse()?;
+        assert_eq!(
+            stmt,
+            ast::Statement::Delete {
+                table_name: "tbl1".to_string(),
+                where_clause: Some(
+                    ast::Operation::Equal(
+                        Box::new(ast::Expression::Field("a".to_string())),
+                        Box::new(ast::Consts::Integer(1).into()),
+                    )
+                    .into()
+                ),
+            }
+        );
+
+        let sql = "delete from tbl1;";
+        let stmt = Parser::new(sql).parse()?;
+        assert_eq!(
+            stmt,
+            ast::Statement::Delete {
+                table_name: "tbl1".to_string(),
+                where_clause: None,
+            }
+        );
+
+        assert!(Parser::new("delete tbl1;").parse().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parser_transaction() -> Result<()> {
+        assert_eq!(
+            Parser::new("begin;").parse()?,
+            ast::Statement::Begin {
+                read_only: false,
+                as_of: None
+            }
+        );
+        assert_eq!(
+            Parser::new("begin read only;").parse()?,
+            ast::Statement::Begin {
+                read_only: true,
+                as_of: None
+            }
+        );
+        assert_eq!(
+            Parser::new("begin read only as of system time 10;").parse()?,
+            ast::Statement::Begin {
+                read_only: true,
+                as_of: Some(10)
+            }
+        );
+        assert!(Parser::new("begin read;").parse().is_err());
+        assert!(Parser::new("begin read only as of 10;").parse().is_err());
+        assert!(Parser::new("begin read only as of system time 1.5;")
+            .parse()
+            .is_err());
+        assert_eq!(Parser::new("COMMIT;").parse()?, ast::Statement::Commit);
+        assert_eq!(Parser::new("rollback;").parse()?, ast::Statement::Rollback);
+        assert!(Parser::new("begin commit;").parse().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parser_create_index() -> Result<()> {
+        assert_eq!(
+            Parser::new("create index idx_b on tbl1 (b);").parse()?,
+            ast::Statement::CreateIndex {
+                name: "idx_b".to_string(),
+                table_name: "tbl1".to_string(),
+                column: "b".to_string(),
+                unique: false,
+            }
+        );
+        assert_eq!(
+            Parser::new("CREATE UNIQUE INDEX idx_c ON tbl1(c);").parse()?,
+            ast::Statement::CreateIndex {
+                name: "idx_c".to_string(),
+                table_name: "tbl1".to_string(),
+                column: "c".to_string(),
+                unique: true,
+            }
+        );
+        assert!(Parser::new("create unique idx on tbl1(c);")
+            .parse()
+            .is_err());
+        assert!(Parser::new("create index idx on tbl1(a, b);")
+            .parse()
+            .is_err());
+        assert!(Parser::new("create index idx tbl1(a);").parse().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parser_join() -> Result<()> {
+        let table = |name: &str, alias: Option<&str>| ast::FromItem::Table {
+            name: name.to_string(),
+            alias: alias.map(|a| a.to_string()),
+        };
+        let from = |sql: &str| -> Result<ast::FromItem> {
+            match Parser::new(sql).parse()? {
+                ast::Statement::Select { from, .. } => Ok(from),
+                stmt => panic!("unexpected statement {:?}", stmt),
+            }
+        };
+
+        assert_eq!(
+            from("select * from tbl1 as t, tbl2;")?,
+            ast::FromItem::Join {
+                left: Box::new(table("tbl1", Some("t"))),
+                right: Box::new(table("tbl2", None)),
+                join_type: ast::JoinType::Cross,
+                predicate: None,
+            }
+        );
+        // 多个连接从左到右结合
+        assert_eq!(
+            from("select * from tbl1 a join tbl2 b on a.x = b.y left outer join tbl3 on b.y > 1;")?,
+            ast::FromItem::Join {
+                left: Box::new(ast::FromItem::Join {
+                    left: Box::new(table("tbl1", Some("a"))),
+                    right: Box::new(table("tbl2", Some("b"))),
+                    join_type: ast::JoinType::Inner,
+                    predicate: Some(
+                        ast::Operation::Equal(
+                            Box::new(ast::Expression::Field("a.x".to_string())),
+                            Box::new(ast::Expression::Field("b.y".to_string())),
+                        )
+                        .into()
+                    ),
+                }),
+                right: Box::new(table("tbl3", None)),
+                join_type: ast::JoinType::Left,
+                predicate: Some(
+                    ast::Operation::GreaterThan(
+                        Box::new(ast::Expression::Field("b.y".to_string())),
+                        Box::new(ast::Consts::Integer(1).into()),
+                    )
+                    .into()
+                ),
+            }
+        );
+        assert_eq!(
+            from("select * from tbl1 right join tbl2 on true cross join tbl3;")?,
+            ast::FromItem::Join {
+                left: Box::new(ast::FromItem::Join {
+                    left: Box::new(table("tbl1", None)),
+                    right: Box::new(table("tbl2", None)),
+                    join_type: ast::JoinType::Right,
+                    predicate: Some(ast::Consts::Boolean(true).into()),
+                }),
+                right: Box::new(table("tbl3", None)),
+                join_type: ast::JoinType::Cross,
+                predicate: None,
+            }
+        );
+
+        assert!(Parser::new("select * from tbl1 join tbl2;")
+            .parse()
+            .is_err());
+        assert!(Parser::new("select * from tbl1 left tbl2 on true;")
+            .parse()
+            .is_err());
+        assert!(Parser::new("select * from tbl1 cross join tbl2 on true;")
+            .parse()
+            .is_err());
+        assert!(Parser::new("select a. from tbl1;").parse().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parser_group_by() -> Result<()> {
+        let sql =
+            "select a, COUNT(*), sum(b) from tbl1 where c > 1 group by a, d having min(b) > 0;";
+        assert_eq!(
+            Parser::new(sql).parse()?,
+            ast::Statement::Select {
+                select: vec![
+                    (ast::Expression::Field("a".to_string()), None),
+                    (
+                        ast::Expression::Function(
+                            "count".to_string(),
+                            vec![ast::Expression::Field("*".to_string())]
+                        ),
+                        None
+                    ),
+                    (
+                        ast::Expression::Function(
+                            "sum".to_string(),
+                            vec![ast::Expression::Field("b".to_string())]
+                        ),
+                        None
+                    ),
+                ],
+                from: ast::FromItem::Table {
+                    name: "tbl1".to_string(),
+                    alias: None,
+                },
+                where_clause: Some(
+                    ast::Operation::GreaterThan(
+                        Box::new(ast::Expression::Field("c".to_string())),
+                        Box::new(ast::Consts::Integer(1).into()),
+                    )
+                    .into()
+                ),
+                group_by: vec![
+                    ast::Expression::Field("a".to_string()),
+                    ast::Expression::Field("d".to_string()),
+                ],
+                having: Some(
+                    ast::Operation::GreaterThan(
+                        Box::new(ast::Expression::Function(
+                            "min".to_string(),
+                            vec![ast::Expression::Field("b".to_string())]
+                        )),
+                        Box::new(ast::Consts::Integer(0).into()),
+                    )
+                    .into()
+                ),
+                order_by: vec![],
+                limit: None,
+                offset: None,
+            }
+        );
+
+        assert!(Parser::new("select count(*) from tbl1 having;")
+            .parse()
+            .is_err());
+        assert!(Parser::new("select a from tbl1 group a;").parse().is_err());
+        assert!(Parser::new("select a from tbl1 group by;").parse().is_err());
+        assert!(Parser::new("select count(*, a) from tbl1;")
+            .parse()
+            .is_err());
+        assert!(Parser::new("select a from tbl1 having a > 1 group by a;")
+            .parse()
+            .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parser_order_by() -> Result<()> {
+        let sql = "select * from tbl1 order by a, b desc, c asc nulls first, d desc nulls last limit 10 offset 1 + 1;";
+        let order = |name: &str, direction: ast::OrderDirection, nulls_first: bool| ast::OrderBy {
+            expr: ast::Expression::Field(name.to_string()),
+            direction,
+            nulls_first,
+        };
+        assert_eq!(
+            Parser::new(sql).parse()?,
+            ast::Statement::Select {
+                select: vec![],
+                from: ast::FromItem::Table {
+                    name: "tbl1".to_string(),
+                    alias: None,
+                },
+                where_clause: None,
+                group_by: vec![],
+                having: None,
+                // 默认 NULL 在升序时排在最后，降序时排在最前
+                order_by: vec![
+                    order("a", ast::OrderDirection::Asc, false),
+                    order("b", ast::OrderDirection::Desc, true),
+                    order("c", ast::OrderDirection::Asc, true),
+                    order("d", ast::OrderDirection::Desc, false),
+                ],
+                limit: Some(Box::new(ast::Consts::Integer(10).into())),
+                offset: Some(Box::new(
+                    ast::Operation::Add(
+                        Box::new(ast::Consts::Integer(1).into()),
+                        Box::new(ast::Consts::Integer(1).into()),
+                    )
+                    .into()
+                )),
             }
         );
+
+        assert!(Parser::new("select * from tbl1 offset 1;").parse().is_ok());
+        assert!(Parser::new("select * from tbl1 order a;").parse().is_err());
+        assert!(Parser::new("select * from tbl1 order by a nulls;")
+            .parse()
+            .is_err());
+        assert!(Parser::new("select * from tbl1 order by a asc desc;")
+            .parse()
+            .is_err());
+        assert!(Parser::new("select * from tbl1 limit;").parse().is_err());
+        assert!(Parser::new("select * from tbl1 offset 1 limit 1;")
+            .parse()
+            .is_err());
         Ok(())
     }
 }
@@ -1,3 +1,5 @@
+use std::{collections::BTreeMap, fmt::Display, ops::Bound};
+
 use planner::Planner;
 
 use crate::error::Result;
@@ -5,8 +7,9 @@ use crate::error::Result;
 use super::{
     engine::Transaction,
     exexutor::{Executor, ResultSet},
-    parser::ast::{self, Expression},
-    schema::Table,
+    parser::ast::{self, Expression, JoinType, OrderBy},
+    schema::{Index, Table},
+    types::Value,
 };
 
 mod planner;
@@ -18,6 +21,12 @@ pub enum Node {
         schema: Table,
     },
 
+    // 创建索引
+    CreateIndex {
+        table_name: String,
+        index: Index,
+    },
+
     // 插入数据
     Insert {
         table_name: String,
@@ -25,38 +34,182 @@ pub enum Node {
         values: Vec<Vec<Expression>>,
     },
 
-    // 扫描节点
+    // 更新数据
+    Update {
+        table_name: String,
+        source: Box<Node>,
+        columns: BTreeMap<String, Expression>,
+    },
+
+    // 删除数据
+    Delete {
+        table_name: String,
+        source: Box<Node>,
+    },
+
+    // 扫描节点，输出的列名带有表名或者别名
     Scan {
         table_name: String,
+        alias: Option<String>,
+    },
+
+    // 根据主键读取数据
+    KeyLookup {
+        table_name: String,
+        value: Value,
+    },
+
+    // 根据索引读取数据
+    IndexLookup {
+        table_name: String,
+        column: String,
+        value: Value,
+    },
+
+    // 按照主键或者索引列的范围读取数据
+    IndexRangeScan {
+        table_name: String,
+        column: String,
+        range: (Bound<Value>, Bound<Value>),
+    },
+
+    // 嵌套循环连接，对左边的每一行，遍历右边的全部数据
+    NestedLoopJoin {
+        left: Box<Node>,
+        right: Box<Node>,
+        predicate: Option<Expression>,
+        join_type: JoinType,
+    },
+
+    // 哈希连接，用于等值连接，使用右边的数据建立哈希表
+    HashJoin {
+        left: Box<Node>,
+        left_field: String,
+        right: Box<Node>,
+        right_field: String,
+        join_type: JoinType,
+    },
+
+    // 过滤节点
+    Filter {
+        source: Box<Node>,
+        predicate: Expression,
+    },
+
+    // 聚合节点，按照分组表达式分组之后计算聚合函数
+    // 输出的列依次是分组表达式和聚合函数，count(*) 的参数为 None
+    Aggregate {
+        source: Box<Node>,
+        group_by: Vec<Expression>,
+        aggregates: Vec<(AggregateFunction, Option<Expression>)>,
+    },
+
+    // 投影节点，计算 select 的列以及别名
+    Projection {
+        source: Box<Node>,
+        exprs: Vec<(Expression, Option<String>)>,
+    },
+
+    // 排序节点，数据量超过内存限制时使用临时文件排序
+    Order {
+        source: Box<Node>,
+        order_by: Vec<OrderBy>,
+    },
+
+    // 跳过前面的 offset 行
+    Offset {
+        source: Box<Node>,
+        offset: usize,
+    },
+
+    // 最多返回 limit 行
+    Limit {
+        source: Box<Node>,
+        limit: usize,
     },
 }
 
+// 聚合函数
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AggregateFunction {
+    Count,
+    Sum,
+    Avg,
+    Min,
+    Max,
+}
+
+impl AggregateFunction {
+    pub fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "count" => Self::Count,
+            "sum" => Self::Sum,
+            "avg" => Self::Avg,
+            "min" => Self::Min,
+            "max" => Self::Max,
+            _ => return None,
+        })
+    }
+
+    // 聚合结果的列名，例如 sum(a)、count(*)
+    pub fn column_name(&self, arg: &Option<Expression>) -> String {
+        match arg {
+            Some(expr) => format!("{}({})", self, expr),
+            None => format!("{}(*)", self),
+        }
+    }
+}
+
+impl Display for AggregateFunction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Count => "count",
+            Self::Sum => "sum",
+            Self::Avg => "avg",
+            Self::Min => "min",
+            Self::Max => "max",
+        })
+    }
+}
+
 #[derive(Debug, PartialEq)]
 // 执行计划定义，底层是不同类型执行节点
 pub struct Plan(pub Node);
 
 impl Plan {
-    pub fn build(stmt: ast::Statement) -> Self {
-        Planner::new().build(stmt)
+    // 生成执行计划时需要读取表的信息，选择使用主键或者索引读取数据
+    pub fn build<T: Transaction>(stmt: ast::Statement, txn: &mut T) -> Result<Self> {
+        Planner::new(txn).build(stmt)
     }
-    pub fn executor<T: Transaction>(self, txn: &mut T) -> Result<ResultSet> {
+    pub fn executor<T: Transaction + 'static>(self, txn: &mut T) -> Result<ResultSet> {
         <dyn Executor<T>>::build(self.0).executor(txn)
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::{collections::BTreeMap, ops::Bound};
+
     use crate::{
         error::Result,
         sql::{
+            engine::{kv::KVEngine, Engine},
             parser::{
                 ast::{self, Expression},
                 Parser,
             },
-            plan::{Node, Plan},
+            plan::{AggregateFunction, Node, Plan},
+            types::Value,
         },
+        storage::memory::MemoryEngine,
     };
 
+    // 在空的数据库中生成执行计划，表不存在时使用全表扫描
+    fn build(stmt: ast::Statement) -> Result<Plan> {
+        let mut txn = KVEngine::new(MemoryEngine::new()).begin()?;
+        Plan::build(stmt, &mut txn)
+    }
+
     #[test]
     fn test_plan_create_table() -> Result<()> {
         let sql1 = "
@@ -68,7 +221,7 @@ mod tests {
         );
         ";
         let stmt1 = Parser::new(sql1).parse()?;
-        let p1 = Plan::build(stmt1);
+        let p1 = build(stmt1)?;
 
         let sql2 = "
         create            table tbl1 (
@@ -79,7 +232,7 @@ mod tests {
         );
         ";
         let stmt2 = Parser::new(sql2).parse()?;
-        let p2 = Plan::build(stmt2);
+        let p2 = build(stmt2)?;
         assert_eq!(p1, p2);
 
         Ok(())
@@ -89,7 +242,7 @@ mod tests {
     fn test_plan_insert() -> Result<()> {
         let sql1 = "insert into tbl1 values (1, 2, 3, 'a', true);";
         let stmt1 = Parser::new(sql1).parse()?;
-        let p1 = Plan::build(stmt1);
+        let p1 = build(stmt1)?;
         assert_eq!(
             p1,
             Plan(Node::Insert {
@@ -107,7 +260,7 @@ mod tests {
 
         let sql2 = "insert into tbl2 (c1, c2, c3) values (3, 'a', true),(4, 'b', false);";
         let stmt2 = Parser::new(sql2).parse()?;
-        let p2 = Plan::build(stmt2);
+        let p2 = build(stmt2)?;
         assert_eq!(
             p2,
             Plan(Node::Insert {
@@ -135,14 +288,391 @@ mod tests {
     fn test_plan_select() -> Result<()> {
         let sql = "select * from tbl1;";
         let stmt = Parser::new(sql).parse()?;
-        let p = Plan::build(stmt);
+        let p = build(stmt)?;
         assert_eq!(
             p,
             Plan(Node::Scan {
                 table_name: "tbl1".to_string(),
+                alias: None,
+            })
+        );
+
+        let sql = "select * from tbl1 where a = 1;";
+        let stmt = Parser::new(sql).parse()?;
+        let p = build(stmt)?;
+        assert_eq!(
+            p,
+            Plan(Node::Filter {
+                source: Box::new(Node::Scan {
+                    table_name: "tbl1".to_string(),
+                    alias: None,
+                }),
+                predicate: ast::Operation::Equal(
+                    Box::new(Expression::Field("a".to_string())),
+                    Box::new(ast::Consts::Integer(1).into()),
+                )
+                .into(),
             })
         );
 
+        let sql = "select a, b as c from tbl1 where a = 1;";
+        let stmt = Parser::new(sql).parse()?;
+        let p = build(stmt)?;
+        assert_eq!(
+            p,
+            Plan(Node::Projection {
+                source: Box::new(Node::Filter {
+                    source: Box::new(Node::Scan {
+                        table_name: "tbl1".to_string(),
+                        alias: None,
+                    }),
+                    predicate: ast::Operation::Equal(
+                        Box::new(Expression::Field("a".to_string())),
+                        Box::new(ast::Consts::Integer(1).into()),
+                    )
+                    .into(),
+                }),
+                exprs: vec![
+                    (Expression::Field("a".to_string()), None),
+                    (Expression::Field("b".to_string()), Some("c".to_string())),
+                ],
+            })
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_plan_update() -> Result<()> {
+        let sql = "update tbl1 set a = 1 where b = 2;";
+        let stmt = Parser::new(sql).parse()?;
+        let p = build(stmt)?;
+        assert_eq!(
+            p,
+            Plan(Node::Update {
+                table_name: "tbl1".to_string(),
+                source: Box::new(Node::Filter {
+                    source: Box::new(Node::Scan {
+                        table_name: "tbl1".to_string(),
+                        alias: None,
+                    }),
+                    predicate: ast::Operation::Equal(
+                        Box::new(Expression::Field("b".to_string())),
+                        Box::new(ast::Consts::Integer(2).into()),
+                    )
+                    .into(),
+                }),
+                columns: BTreeMap::from([("a".to_string(), ast::Consts::Integer(1).into())]),
+            })
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_plan_delete() -> Result<()> {
+        let sql = "delete from tbl1;";
+        let stmt = Parser::new(sql).parse()?;
+        let p = build(stmt)?;
+        assert_eq!(
+            p,
+            Plan(Node::Delete {
+                table_name: "tbl1".to_string(),
+                source: Box::new(Node::Scan {
+                    table_name: "tbl1".to_string(),
+                    alias: None,
+                }),
+            })
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_plan_index() -> Result<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new());
+        let mut txn = kvengine.begin()?;
+        for sql in [
+            "create table tbl1 (a int primary key, b text, c int, d float);",
+            "create index idx_b on tbl1 (b);",
+            "create unique index idx_d on tbl1 (d);",
+        ] {
+            Plan::build(Parser::new(sql).parse()?, &mut txn)?.executor(&mut txn)?;
+        }
+        let mut source = |sql: &str| -> Result<Node> {
+            match Plan::build(Parser::new(sql).parse()?, &mut txn)?.0 {
+                Node::Filter { source, .. } => Ok(*source),
+                node => Ok(node),
+            }
+        };
+        let scan = Node::Scan {
+            table_name: "tbl1".to_string(),
+            alias: None,
+        };
+        let range = |column: &str, range: (Bound<Value>, Bound<Value>)| Node::IndexRangeScan {
+            table_name: "tbl1".to_string(),
+            column: column.to_string(),
+            range,
+        };
+
+        // 等值条件优先使用主键，其次是唯一索引
+        assert_eq!(
+            source("select * from tbl1 where b = 'x' and a = -1;")?,
+            Node::KeyLookup {
+                table_name: "tbl1".to_string(),
+                value: Value::Integer(-1),
+            }
+        );
+        assert_eq!(
+            source("select * from tbl1 where b = 'x' and d = 1.5;")?,
+            Node::IndexLookup {
+                table_name: "tbl1".to_string(),
+                column: "d".to_string(),
+                value: Value::Float(1.5),
+            }
+        );
+        assert_eq!(
+            source("update tbl1 set c = 1 where 'x' = b;")?,
+            Node::Update {
+                table_name: "tbl1".to_string(),
+                source: Box::new(Node::Filter {
+                    source: Box::new(Node::IndexLookup {
+                        table_name: "tbl1".to_string(),
+                        column: "b".to_string(),
+                        value: Value::String("x".to_string()),
+                    }),
+                    predicate: ast::Operation::Equal(
+                        Box::new(ast::Consts::String("x".to_string()).into()),
+                        Box::new(Expression::Field("b".to_string())),
+                    )
+                    .into(),
+                }),
+                columns: BTreeMap::from([("c".to_string(), ast::Consts::Integer(1).into())]),
+            }
+        );
+
+        // 同一列上的范围条件取交集
+        assert_eq!(
+            source("select * from tbl1 where a > 1 and c = 2 and a <= 5 and a >= 1;")?,
+            range(
+                "a",
+                (
+                    Bound::Excluded(Value::Integer(1)),
+                    Bound::Included(Value::Integer(5))
+                )
+            )
+        );
+        assert_eq!(
+            source("delete from tbl1 where 'm' < b;")?,
+            Node::Delete {
+                table_name: "tbl1".to_string(),
+                source: Box::new(Node::Filter {
+                    source: Box::new(range(
+                        "b",
+                        (
+                            Bound::Excluded(Value::String("m".to_string())),
+                            Bound::Unbounded
+                        )
+                    )),
+                    predicate: ast::Operation::LessThan(
+                        Box::new(ast::Consts::String("m".to_string()).into()),
+                        Box::new(Expression::Field("b".to_string())),
+                    )
+                    .into(),
+                }),
+            }
+        );
+
+        // 无法使用主键或者索引时扫描全表
+        assert_eq!(source("select * from tbl1 where c = 1;")?, scan);
+        assert_eq!(source("select * from tbl1 where a = 1.0;")?, scan);
+        assert_eq!(source("select * from tbl1 where a = c;")?, scan);
+        assert_eq!(source("select * from tbl1 where a = 1 or a = 2;")?, scan);
+        assert_eq!(source("select * from tbl1 where a != 1;")?, scan);
+        Ok(())
+    }
+
+    #[test]
+    fn test_plan_join() -> Result<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new());
+        let mut txn = kvengine.begin()?;
+        for sql in [
+            "create table tbl1 (a int primary key, b text);",
+            "create table tbl2 (c int primary key, d int, b float);",
+        ] {
+            Plan::build(Parser::new(sql).parse()?, &mut txn)?.executor(&mut txn)?;
+        }
+        let mut plan = |sql: &str| Plan::build(Parser::new(sql).parse()?, &mut txn);
+        let scan = |table_name: &str, alias: Option<&str>| {
+            Box::new(Node::Scan {
+                table_name: table_name.to_string(),
+                alias: alias.map(|a| a.to_string()),
+            })
+        };
+
+        // 两边各有一列的等值条件使用哈希连接
+        assert_eq!(
+            plan("select * from tbl1 x join tbl2 on d = x.a;")?,
+            Plan(Node::HashJoin {
+                left: scan("tbl1", Some("x")),
+                left_field: "x.a".to_string(),
+                right: scan("tbl2", None),
+                right_field: "d".to_string(),
+                join_type: ast::JoinType::Inner,
+            })
+        );
+        // 类型不同或者有歧义的列使用嵌套循环连接
+        let predicate: Expression = ast::Operation::Equal(
+            Box::new(Expression::Field("tbl1.b".to_string())),
+            Box::new(Expression::Field("tbl2.b".to_string())),
+        )
+        .into();
+        assert_eq!(
+            plan("select * from tbl1 left join tbl2 on tbl1.b = tbl2.b;")?,
+            Plan(Node::NestedLoopJoin {
+                left: scan("tbl1", None),
+                right: scan("tbl2", None),
+                predicate: Some(predicate),
+                join_type: ast::JoinType::Left,
+            })
+        );
+        assert_eq!(
+            plan("select * from tbl1, tbl2;")?,
+            Plan(Node::NestedLoopJoin {
+                left: scan("tbl1", None),
+                right: scan("tbl2", None),
+                predicate: None,
+                join_type: ast::JoinType::Cross,
+            })
+        );
+
+        // 同一个表需要使用不同的别名
+        assert!(plan("select * from tbl1 join tbl1 on a = a;").is_err());
+        assert!(plan("select * from tbl1 x, tbl2 x;").is_err());
+        assert!(plan("select * from tbl1 x, tbl3;").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_plan_aggregate() -> Result<()> {
+        let sql = "select b, count(*) + 1 as c, sum(a) from tbl1 group by b having sum(a) > 10;";
+        let p = build(Parser::new(sql).parse()?)?;
+        let field = |name: &str| Box::new(Expression::Field(name.to_string()));
+        assert_eq!(
+            p,
+            Plan(Node::Projection {
+                source: Box::new(Node::Filter {
+                    source: Box::new(Node::Aggregate {
+                        source: Box::new(Node::Scan {
+                            table_name: "tbl1".to_string(),
+                            alias: None,
+                        }),
+                        group_by: vec![Expression::Field("b".to_string())],
+                        aggregates: vec![
+                            (AggregateFunction::Count, None),
+                            (
+                                AggregateFunction::Sum,
+                                Some(Expression::Field("a".to_string()))
+                            ),
+                        ],
+                    }),
+                    predicate: ast::Operation::GreaterThan(
+                        field("sum(a)"),
+                        Box::new(ast::Consts::Integer(10).into()),
+                    )
+                    .into(),
+                }),
+                exprs: vec![
+                    (Expression::Field("b".to_string()), None),
+                    (
+                        ast::Operation::Add(
+                            field("count(*)"),
+                            Box::new(ast::Consts::Integer(1).into())
+                        )
+                        .into(),
+                        Some("c".to_string())
+                    ),
+                    (Expression::Field("sum(a)".to_string()), None),
+                ],
+            })
+        );
+
+        let build = |sql: &str| build(Parser::new(sql).parse()?);
+        assert!(build("select avg(a) from tbl1;").is_ok());
+        assert!(build("select * from tbl1 group by a;").is_err());
+        assert!(build("select a, count(*) from tbl1;").is_err());
+        assert!(build("select a from tbl1 group by b;").is_err());
+        assert!(build("select count(*) from tbl1 where sum(a) > 1;").is_err());
+        assert!(build("select sum(max(a)) from tbl1;").is_err());
+        assert!(build("select count(a) from tbl1 group by count(a);").is_err());
+        assert!(build("select sum(a, b) from tbl1;").is_err());
+        assert!(build("select sum(*) from tbl1;").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_plan_order_by() -> Result<()> {
+        let sql = "select a + 1 as x, b from tbl1 order by x desc, c limit 5 offset 2;";
+        let p = build(Parser::new(sql).parse()?)?;
+        let add: Expression = ast::Operation::Add(
+            Box::new(Expression::Field("a".to_string())),
+            Box::new(ast::Consts::Integer(1).into()),
+        )
+        .into();
+        // 排序在投影之前进行，别名替换为对应的表达式
+        assert_eq!(
+            p,
+            Plan(Node::Limit {
+                source: Box::new(Node::Offset {
+                    source: Box::new(Node::Projection {
+                        source: Box::new(Node::Order {
+                            source: Box::new(Node::Scan {
+                                table_name: "tbl1".to_string(),
+                                alias: None,
+                            }),
+                            order_by: vec![
+                                ast::OrderBy {
+                                    expr: add.clone(),
+                                    direction: ast::OrderDirection::Desc,
+                                    nulls_first: true,
+                                },
+                                ast::OrderBy {
+                                    expr: Expression::Field("c".to_string()),
+                                    direction: ast::OrderDirection::Asc,
+                                    nulls_first: false,
+                                },
+                            ],
+                        }),
+                        exprs: vec![
+                            (add, Some("x".to_string())),
+                            (Expression::Field("b".to_string()), None),
+                        ],
+                    }),
+                    offset: 2,
+                }),
+                limit: 5,
+            })
+        );
+
+        // 排序中的聚合函数在聚合节点中计算
+        let sql = "select b from tbl1 group by b order by count(*);";
+        match build(Parser::new(sql).parse()?)?.0 {
+            Node::Projection { source, .. } => match *source {
+                Node::Order { source, order_by } => {
+                    assert_eq!(order_by[0].expr, Expression::Field("count(*)".to_string()));
+                    assert!(matches!(*source, Node::Aggregate { aggregates, .. }
+                        if aggregates == vec![(AggregateFunction::Count, None)]));
+                }
+                node => panic!("unexpected node {:?}", node),
+            },
+            node => panic!("unexpected node {:?}", node),
+        }
+
+        let build = |sql: &str| build(Parser::new(sql).parse()?);
+        assert!(build("select * from tbl1 limit -1;").is_err());
+        assert!(build("select * from tbl1 limit 1.5;").is_err());
+        assert!(build("select * from tbl1 offset a;").is_err());
+        assert!(build("select b from tbl1 group by b order by c;").is_err());
         Ok(())
     }
 }
@@ -1,45 +1,71 @@
-use crate::sql::{
-    parser::ast,
-    schema::{self, Table},
-    types::Value,
+use std::ops::Bound;
+
+use crate::{
+    error::{Error, Result},
+    sql::{
+        engine::Transaction,
+        parser::ast::{self, resolve_column, Expression, Operation, OrderBy},
+        schema::{self, Column, Index, Table},
+        types::{DataType, Value},
+    },
 };
 
-use super::{Node, Plan};
+use super::{AggregateFunction, Node, Plan};
 
-pub struct Planner;
+// 通过事务读取表的信息，根据主键和索引选择读取数据的方式
+pub struct Planner<'a, T: Transaction> {
+    txn: &'a mut T,
+}
 
-impl Planner {
-    pub fn new() -> Self {
-        Self {}
+impl<'a, T: Transaction> Planner<'a, T> {
+    pub fn new(txn: &'a mut T) -> Self {
+        Self { txn }
     }
 
-    pub fn build(&mut self, stmt: ast::Statement) -> Plan {
-        Plan(self.build_statment(stmt))
+    pub fn build(&mut self, stmt: ast::Statement) -> Result<Plan> {
+        Ok(Plan(self.build_statment(stmt)?))
     }
 
-    fn build_statment(&self, stmt: ast::Statement) -> Node {
-        match stmt {
+    fn build_statment(&mut self, stmt: ast::Statement) -> Result<Node> {
+        Ok(match stmt {
             ast::Statement::CreateTable { name, columns } => Node::CreateTable {
                 schema: Table {
                     name,
+                    indexes: Vec::new(),
                     columns: columns
                         .into_iter()
                         .map(|c| {
-                            let nullable = c.nullable.unwrap_or(true);
+                            // 主键默认不能为空，其他列默认可以为空
+                            let nullable = c.nullable.unwrap_or(!c.primary_key);
                             let default = match c.default {
-                                Some(expr) => Some(Value::from_expression(expr)),
+                                // 默认值只能是常量表达式
+                                Some(expr) => Some(expr.evaluate(&[], &Vec::new())?),
                                 None if nullable => Some(Value::Null),
                                 None => None,
                             };
 
-                            schema::Column {
+                            Ok(schema::Column {
                                 name: c.name,
                                 datatype: c.datatype,
                                 nullable,
                                 default,
-                            }
+                                primary_key: c.primary_key,
+                            })
                         })
-                        .collect(),
+                        .collect::<Result<_>>()?,
+                },
+            },
+            ast::Statement::CreateIndex {
+                name,
+                table_name,
+                column,
+                unique,
+            } => Node::CreateIndex {
+                table_name,
+                index: Index {
+                    name,
+                    column,
+                    unique,
                 },
             },
             ast::Statement::Insert {
@@ -51,7 +77,502 @@ impl Planner {
                 columns: columns.unwrap_or_default(),
                 values,
             },
-            ast::Statement::Select { table_name } => Node::Scan { table_name },
+            ast::Statement::Select {
+                select,
+                from,
+                where_clause,
+                group_by,
+                having,
+                order_by,
+                limit,
+                offset,
+            } => {
+                if where_clause.as_ref().is_some_and(contains_aggregate) {
+                    return Err(Error::Internal(
+                        "aggregate functions are not allowed in WHERE".to_string(),
+                    ));
+                }
+                let mut node = match from {
+                    // 单表查询可以根据主键或者索引读取数据
+                    ast::FromItem::Table { name, alias: None } => {
+                        self.build_scan(name, where_clause)?
+                    }
+                    from => {
+                        let (node, _) = self.build_from(from, &mut Vec::new())?;
+                        match where_clause {
+                            Some(predicate) => Node::Filter {
+                                source: Box::new(node),
+                                predicate,
+                            },
+                            None => node,
+                        }
+                    }
+                };
+                // 排序在投影之前进行，order by 中使用的别名替换为对应的表达式
+                let order_by = order_by
+                    .into_iter()
+                    .map(|mut order| {
+                        if let Expression::Field(name) = &order.expr {
+                            if let Some((expr, _)) = select
+                                .iter()
+                                .find(|(_, alias)| alias.as_ref() == Some(name))
+                            {
+                                order.expr = expr.clone();
+                            }
+                        }
+                        order
+                    })
+                    .collect::<Vec<_>>();
+
+                // 有分组或者聚合函数时，先计算聚合，having、排序和投影基于聚合的结果计算
+                let aggregate = !group_by.is_empty()
+                    || having.is_some()
+                    || select.iter().any(|(expr, _)| contains_aggregate(expr))
+                    || order_by.iter().any(|order| contains_aggregate(&order.expr));
+                if aggregate {
+                    node = build_aggregate(node, select, group_by, having, order_by)?;
+                } else {
+                    if !order_by.is_empty() {
+                        node = Node::Order {
+                            source: Box::new(node),
+                            order_by,
+                        };
+                    }
+                    // 如果不是 select *，需要计算投影
+                    if !select.is_empty() {
+                        node = Node::Projection {
+                            source: Box::new(node),
+                            exprs: select,
+                        };
+                    }
+                }
+
+                if let Some(expr) = offset {
+                    node = Node::Offset {
+                        source: Box::new(node),
+                        offset: evaluate_count(&expr)?,
+                    };
+                }
+                if let Some(expr) = limit {
+                    node = Node::Limit {
+                        source: Box::new(node),
+                        limit: evaluate_count(&expr)?,
+                    };
+                }
+                node
+            }
+            ast::Statement::Update {
+                table_name,
+                columns,
+                where_clause,
+            } => Node::Update {
+                table_name: table_name.clone(),
+                source: Box::new(self.build_scan(table_name, where_clause)?),
+                columns,
+            },
+            ast::Statement::Delete {
+                table_name,
+                where_clause,
+            } => Node::Delete {
+                table_name: table_name.clone(),
+                source: Box::new(self.build_scan(table_name, where_clause)?),
+            },
+            // 事务语句由 Session 直接处理，不需要生成执行计划
+            ast::Statement::Begin { .. } | ast::Statement::Commit | ast::Statement::Rollback => {
+                return Err(Error::Internal(format!(
+                    "unexpected transaction statement {:?}",
+                    stmt
+                )))
+            }
+        })
+    }
+
+    // 扫描表，如果有 Where 条件，在扫描节点之上加一个过滤节点
+    // 条件中有主键或者索引列和常量的比较时，根据主键或者索引读取数据，过滤节点仍然保留完整的条件
+    fn build_scan(&mut self, table_name: String, filter: Option<ast::Expression>) -> Result<Node> {
+        let node = match (&filter, self.txn.get_table(table_name.clone())?) {
+            (Some(predicate), Some(table)) => build_lookup(&table, predicate)?,
+            _ => None,
+        };
+        let node = node.unwrap_or(Node::Scan {
+            table_name,
+            alias: None,
+        });
+        Ok(match filter {
+            Some(predicate) => Node::Filter {
+                source: Box::new(node),
+                predicate,
+            },
+            None => node,
+        })
+    }
+
+    // 构建 From 子句中的表以及连接，同时返回输出的列名和类型，用于选择连接的方式
+    // tables 中记录已经出现的表名或者别名，不能重复
+    fn build_from(
+        &mut self,
+        item: ast::FromItem,
+        tables: &mut Vec<String>,
+    ) -> Result<(Node, Vec<(String, DataType)>)> {
+        Ok(match item {
+            ast::FromItem::Table { name, alias } => {
+                let table = self.txn.must_get_table(name.clone())?;
+                let label = alias.clone().unwrap_or(name.clone());
+                if tables.contains(&label) {
+                    return Err(Error::Internal(format!(
+                        "duplicate table name or alias {}",
+                        label
+                    )));
+                }
+                tables.push(label.clone());
+                let columns = table
+                    .columns
+                    .into_iter()
+                    .map(|c| (format!("{}.{}", label, c.name), c.datatype))
+                    .collect();
+                (
+                    Node::Scan {
+                        table_name: name,
+                        alias,
+                    },
+                    columns,
+                )
+            }
+            ast::FromItem::Join {
+                left,
+                right,
+                join_type,
+                predicate,
+            } => {
+                let (left, mut columns) = self.build_from(*left, tables)?;
+                let (right, right_columns) = self.build_from(*right, tables)?;
+                let node = match equi_join(predicate.as_ref(), &columns, &right_columns) {
+                    Some((left_field, right_field)) => Node::HashJoin {
+                        left: Box::new(left),
+                        left_field,
+                        right: Box::new(right),
+                        right_field,
+                        join_type,
+                    },
+                    None => Node::NestedLoopJoin {
+                        left: Box::new(left),
+                        right: Box::new(right),
+                        predicate,
+                        join_type,
+                    },
+                };
+                columns.extend(right_columns);
+                (node, columns)
+            }
+        })
+    }
+}
+
+// limit 和 offset 必须是非负整数的常量表达式
+fn evaluate_count(expr: &Expression) -> Result<usize> {
+    match expr.evaluate(&[], &Vec::new()) {
+        Ok(Value::Integer(n)) if n >= 0 => Ok(n as usize),
+        _ => Err(Error::Internal(format!(
+            "{} is not a non-negative integer constant",
+            expr
+        ))),
+    }
+}
+
+fn contains_aggregate(expr: &Expression) -> bool {
+    expr.contains(&|e| match e {
+        Expression::Function(name, _) => AggregateFunction::from_name(name).is_some(),
+        _ => false,
+    })
+}
+
+// 在查询节点之上加入聚合节点，select 和 having 中的聚合函数以及分组表达式替换为聚合节点输出的列
+fn build_aggregate(
+    source: Node,
+    select: Vec<(Expression, Option<String>)>,
+    group_by: Vec<Expression>,
+    having: Option<Expression>,
+    order_by: Vec<OrderBy>,
+) -> Result<Node> {
+    if select.is_empty() {
+        return Err(Error::Internal(
+            "select * is not allowed with GROUP BY or aggregate functions".to_string(),
+        ));
+    }
+    if group_by.iter().any(contains_aggregate) {
+        return Err(Error::Internal(
+            "aggregate functions are not allowed in GROUP BY".to_string(),
+        ));
+    }
+
+    let mut aggregates = Vec::new();
+    let select = select
+        .into_iter()
+        .map(|(expr, alias)| Ok((rewrite_aggregate(expr, &group_by, &mut aggregates)?, alias)))
+        .collect::<Result<Vec<_>>>()?;
+    let having = having
+        .map(|expr| rewrite_aggregate(expr, &group_by, &mut aggregates))
+        .transpose()?;
+    let order_by = order_by
+        .into_iter()
+        .map(|order| {
+            Ok(OrderBy {
+                expr: rewrite_aggregate(order.expr, &group_by, &mut aggregates)?,
+                ..order
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    // 替换之后剩下的列必须是分组表达式或者聚合函数的结果
+    let columns = group_by
+        .iter()
+        .map(|expr| expr.to_string())
+        .chain(aggregates.iter().map(|(f, arg)| f.column_name(arg)))
+        .collect::<Vec<_>>();
+    let exprs = select
+        .iter()
+        .map(|(expr, _)| expr)
+        .chain(having.iter())
+        .chain(order_by.iter().map(|order| &order.expr));
+    for expr in exprs {
+        let unknown = expr.contains(
+            &|e| matches!(e, Expression::Field(name) if resolve_column(&columns, name).is_err()),
+        );
+        if unknown {
+            return Err(Error::Internal(format!(
+                "{} must appear in GROUP BY or be used in an aggregate function",
+                expr
+            )));
+        }
+    }
+
+    let mut node = Node::Aggregate {
+        source: Box::new(source),
+        group_by,
+        aggregates,
+    };
+    if let Some(predicate) = having {
+        node = Node::Filter {
+            source: Box::new(node),
+            predicate,
+        };
+    }
+    if !order_by.is_empty() {
+        node = Node::Order {
+            source: Box::new(node),
+            order_by,
+        };
+    }
+    Ok(Node::Projection {
+        source: Box::new(node),
+        exprs: select,
+    })
+}
+
+// 分组表达式替换为对应的列，聚合函数记录到 aggregates 中并替换为聚合结果的列
+fn rewrite_aggregate(
+    expr: Expression,
+    group_by: &[Expression],
+    aggregates: &mut Vec<(AggregateFunction, Option<Expression>)>,
+) -> Result<Expression> {
+    if group_by.contains(&expr) {
+        return Ok(Expression::Field(expr.to_string()));
+    }
+    let function = match &expr {
+        Expression::Function(name, _) => AggregateFunction::from_name(name),
+        _ => None,
+    };
+    let (function, mut args) = match (function, expr) {
+        (Some(function), Expression::Function(_, args)) => (function, args),
+        (_, expr) => {
+            return expr.map_children(&mut |e| rewrite_aggregate(e, group_by, aggregates));
+        }
+    };
+    if args.len() != 1 || contains_aggregate(&args[0]) {
+        return Err(Error::Internal(format!(
+            "invalid arguments for aggregate function {}",
+            function
+        )));
+    }
+    // 只有 count 可以使用 * 作为参数
+    let arg = match args.remove(0) {
+        Expression::Field(name) if name == "*" => match function {
+            AggregateFunction::Count => None,
+            _ => return Err(Error::Internal(format!("{}(*) is not supported", function))),
+        },
+        arg => Some(arg),
+    };
+    let column = function.column_name(&arg);
+    if !aggregates.contains(&(function, arg.clone())) {
+        aggregates.push((function, arg));
+    }
+    Ok(Expression::Field(column))
+}
+
+// 连接条件是两边类型相同的列相等时，可以使用哈希连接，返回左边和右边的列名
+fn equi_join(
+    predicate: Option<&Expression>,
+    left: &[(String, DataType)],
+    right: &[(String, DataType)],
+) -> Option<(String, String)> {
+    let (a, b) = match predicate? {
+        Expression::Operation(Operation::Equal(a, b)) => match (a.as_ref(), b.as_ref()) {
+            (Expression::Field(a), Expression::Field(b)) => (a, b),
+            _ => return None,
+        },
+        _ => return None,
+    };
+    let names =
+        |columns: &[(String, DataType)]| columns.iter().map(|(n, _)| n.clone()).collect::<Vec<_>>();
+    let (left_names, right_names) = (names(left), names(right));
+    // 列名在两边都能找到时有歧义，交给嵌套循环连接在执行时报错
+    let find = |name: &str| match (
+        resolve_column(&left_names, name),
+        resolve_column(&right_names, name),
+    ) {
+        (Ok(i), Err(_)) => Some((true, &left[i].1)),
+        (Err(_), Ok(i)) => Some((false, &right[i].1)),
+        _ => None,
+    };
+    match (find(a)?, find(b)?) {
+        ((true, x), (false, y)) if x == y => Some((a.clone(), b.clone())),
+        ((false, x), (true, y)) if x == y => Some((b.clone(), a.clone())),
+        _ => None,
+    }
+}
+
+// 选择读取数据的方式，优先级依次是主键等值、索引等值、主键范围、索引范围
+fn build_lookup(table: &Table, predicate: &Expression) -> Result<Option<Node>> {
+    let mut conditions = Vec::new();
+    split_and(predicate, &mut conditions);
+
+    // 可以使用的列，主键在最前面，唯一索引在普通索引前面
+    let mut columns = vec![table.get_primary_key_column()?];
+    let mut indexes = table.indexes.iter().collect::<Vec<_>>();
+    indexes.sort_by_key(|i| !i.unique);
+    for index in indexes {
+        columns.push(&table.columns[table.get_col_index(&index.column)?]);
+    }
+
+    // 每一列上的条件合并为一个范围
+    let ranges = columns
+        .into_iter()
+        .filter_map(|column| {
+            conditions
+                .iter()
+                .filter_map(|c| column_range(c, &table.name, column))
+                .reduce(intersect)
+                .map(|range| (column, range))
+        })
+        .collect::<Vec<_>>();
+
+    let primary_key = |column: &Column| column.primary_key;
+    if let Some((column, value)) = ranges.iter().find_map(|(column, range)| match range {
+        (Bound::Included(a), Bound::Included(b)) if a == b => Some((column, a.clone())),
+        _ => None,
+    }) {
+        return Ok(Some(match primary_key(column) {
+            true => Node::KeyLookup {
+                table_name: table.name.clone(),
+                value,
+            },
+            false => Node::IndexLookup {
+                table_name: table.name.clone(),
+                column: column.name.clone(),
+                value,
+            },
+        }));
+    }
+
+    Ok(ranges
+        .into_iter()
+        .next()
+        .map(|(column, range)| Node::IndexRangeScan {
+            table_name: table.name.clone(),
+            column: column.name.clone(),
+            range,
+        }))
+}
+
+// 拆分 AND 连接的条件
+fn split_and<'e>(expr: &'e Expression, conditions: &mut Vec<&'e Expression>) {
+    match expr {
+        Expression::Operation(Operation::And(l, r)) => {
+            split_and(l, conditions);
+            split_and(r, conditions);
+        }
+        expr => conditions.push(expr),
+    }
+}
+
+// 列和常量比较的条件转换为这一列的范围，常量的类型必须和列的类型一致
+// 列名可以带上表名，例如 a.x
+fn column_range(
+    expr: &Expression,
+    table_name: &str,
+    column: &Column,
+) -> Option<(Bound<Value>, Bound<Value>)> {
+    let is_column =
+        |name: &str| name == column.name || name == format!("{}.{}", table_name, column.name);
+    let (l, r, reversed) = match expr {
+        Expression::Operation(op) => match op {
+            Operation::Equal(l, r)
+            | Operation::GreaterThan(l, r)
+            | Operation::GreaterThanOrEqual(l, r)
+            | Operation::LessThan(l, r)
+            | Operation::LessThanOrEqual(l, r) => match (l.as_ref(), r.as_ref()) {
+                (Expression::Field(name), e) if is_column(name) => (op, e, false),
+                (e, Expression::Field(name)) if is_column(name) => (op, e, true),
+                _ => return None,
+            },
+            _ => return None,
+        },
+        _ => return None,
+    };
+    // 只有常量表达式才能计算出结果
+    let value = r.evaluate(&[], &Vec::new()).ok()?;
+    if value.datatype().as_ref() != Some(&column.datatype) {
+        return None;
+    }
+    Some(match (l, reversed) {
+        (Operation::Equal(..), _) => (Bound::Included(value.clone()), Bound::Included(value)),
+        (Operation::GreaterThan(..), false) | (Operation::LessThan(..), true) => {
+            (Bound::Excluded(value), Bound::Unbounded)
+        }
+        (Operation::GreaterThanOrEqual(..), false) | (Operation::LessThanOrEqual(..), true) => {
+            (Bound::Included(value), Bound::Unbounded)
+        }
+        (Operation::LessThan(..), false) | (Operation::GreaterThan(..), true) => {
+            (Bound::Unbounded, Bound::Excluded(value))
+        }
+        (_, _) => (Bound::Unbounded, Bound::Included(value)),
+    })
+}
+
+// 两个范围的交集
+fn intersect(
+    a: (Bound<Value>, Bound<Value>),
+    b: (Bound<Value>, Bound<Value>),
+) -> (Bound<Value>, Bound<Value>) {
+    (tighter(a.0, b.0, true), tighter(a.1, b.1, false))
+}
+
+// 选择更严格的边界，start 为 true 时选择更大的下界，否则选择更小的上界
+fn tighter(a: Bound<Value>, b: Bound<Value>, start: bool) -> Bound<Value> {
+    let (x, y) = match (&a, &b) {
+        (Bound::Unbounded, _) => return b,
+        (_, Bound::Unbounded) => return a,
+        (Bound::Included(x) | Bound::Excluded(x), Bound::Included(y) | Bound::Excluded(y)) => {
+            (x, y)
         }
+    };
+    match x.compare(y) {
+        Some(ord) if ord.is_lt() == start => b,
+        Some(ord) if ord.is_ne() => a,
+        // 值相等时，不包含边界的更严格
+        _ => match a {
+            Bound::Excluded(_) => a,
+            _ => b,
+        },
     }
 }
@@ -2,12 +2,157 @@
 
 use serde::{Deserialize, Serialize};
 
-use super::types::{DataType, Value};
+use crate::error::{Error, Result};
+
+use super::types::{DataType, Row, Value};
 
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
 pub struct Table {
     pub name: String,
     pub columns: Vec<Column>,
+    // 表上的二级索引
+    pub indexes: Vec<Index>,
+}
+
+impl Table {
+    // 校验表的有效性
+    pub fn validate(&self) -> Result<()> {
+        // 校验是否有列信息
+        if self.columns.is_empty() {
+            return Err(Error::Internal(format!(
+                "table {} has no columns",
+                self.name
+            )));
+        }
+
+        // 校验是否有且只有一个主键
+        match self.columns.iter().filter(|c| c.primary_key).count() {
+            1 => {}
+            0 => {
+                return Err(Error::Internal(format!(
+                    "no primary key for table {}",
+                    self.name
+                )))
+            }
+            _ => {
+                return Err(Error::Internal(format!(
+                    "multiple primary keys for table {}",
+                    self.name
+                )))
+            }
+        }
+
+        // 校验列信息
+        for column in &self.columns {
+            // 主键不能为空
+            if column.primary_key && column.nullable {
+                return Err(Error::Internal(format!(
+                    "primary key {} cannot be nullable in table {}",
+                    column.name, self.name
+                )));
+            }
+
+            // 校验默认值的类型
+            if let Some(default) = &column.default {
+                match default.datatype() {
+                    None if !column.nullable => {
+                        return Err(Error::Internal(format!(
+                            "default value for column {} cannot be null",
+                            column.name
+                        )))
+                    }
+                    Some(dt) if dt != column.datatype => {
+                        return Err(Error::Internal(format!(
+                            "default value for column {} type mismatch",
+                            column.name
+                        )))
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // 校验行的有效性，包括列数、类型以及是否可以为空
+    pub fn validate_row(&self, row: &Row) -> Result<()> {
+        if row.len() != self.columns.len() {
+            return Err(Error::Internal(format!(
+                "row has {} values, table {} has {} columns",
+                row.len(),
+                self.name,
+                self.columns.len()
+            )));
+        }
+        for (i, col) in self.columns.iter().enumerate() {
+            match row[i].datatype() {
+                None if col.nullable => {}
+                None => {
+                    return Err(Error::Internal(format!(
+                        "column {} cannot be null",
+                        col.name
+                    )))
+                }
+                Some(dt) if dt != col.datatype => {
+                    return Err(Error::Internal(format!(
+                        "column {} type mismatch",
+                        col.name
+                    )))
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    // 获取主键列
+    pub fn get_primary_key_column(&self) -> Result<&Column> {
+        self.columns
+            .iter()
+            .find(|c| c.primary_key)
+            .ok_or(Error::Internal(format!(
+                "no primary key for table {}",
+                self.name
+            )))
+    }
+
+    // 获取一行数据的主键
+    pub fn get_primary_key(&self, row: &Row) -> Result<Value> {
+        let pos = self
+            .columns
+            .iter()
+            .position(|c| c.primary_key)
+            .ok_or(Error::Internal(format!(
+                "no primary key for table {}",
+                self.name
+            )))?;
+        Ok(row[pos].clone())
+    }
+
+    // 添加索引，索引名不能重复，索引的列必须存在
+    pub fn add_index(&mut self, index: Index) -> Result<()> {
+        if self.indexes.iter().any(|i| i.name == index.name) {
+            return Err(Error::Internal(format!(
+                "index {} already exists in table {}",
+                index.name, self.name
+            )));
+        }
+        self.get_col_index(&index.column)?;
+        self.indexes.push(index);
+        Ok(())
+    }
+
+    // 获取列在表中的位置
+    pub fn get_col_index(&self, col_name: &str) -> Result<usize> {
+        self.columns
+            .iter()
+            .position(|c| c.name == col_name)
+            .ok_or(Error::Internal(format!(
+                "column {} not found in table {}",
+                col_name, self.name
+            )))
+    }
 }
 
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
@@ -16,4 +161,14 @@ pub struct Column {
     pub datatype: DataType,
     pub nullable: bool,
     pub default: Option<Value>,
+    pub primary_key: bool,
+}
+
+// 单列的二级索引
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Index {
+    pub name: String,
+    pub column: String,
+    // 唯一索引中不能有重复的值，NULL 除外
+    pub unique: bool,
 }
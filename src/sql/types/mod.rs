@@ -1,8 +1,12 @@
 // use serde::{Deserialize, Serialize};
 
+use std::cmp::Ordering;
+
 use serde::{Deserialize, Serialize};
 
-use super::parser::ast::{Consts, Expression};
+use crate::error::Result;
+
+use super::parser::ast::Consts;
 
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
 
@@ -23,13 +27,13 @@ pub enum Value {
 }
 
 impl Value {
-    pub fn from_expression(expr: Expression) -> Self {
-        match expr {
-            Expression::Consts(Consts::Null) => Self::Null,
-            Expression::Consts(Consts::Boolean(b)) => Self::Boolean(b),
-            Expression::Consts(Consts::Integer(i)) => Self::Integer(i),
-            Expression::Consts(Consts::Float(f)) => Self::Float(f),
-            Expression::Consts(Consts::String(s)) => Self::String(s),
+    pub fn from_consts(c: &Consts) -> Self {
+        match c {
+            Consts::Null => Self::Null,
+            Consts::Boolean(b) => Self::Boolean(*b),
+            Consts::Integer(i) => Self::Integer(*i),
+            Consts::Float(f) => Self::Float(*f),
+            Consts::String(s) => Self::String(s.clone()),
         }
     }
 
@@ -42,6 +46,177 @@ impl Value {
             Self::String(_) => Some(DataType::String),
         }
     }
+
+    // 编码成 key 时使用的值，-0.0 和 0.0 相等，统一成 0.0
+    pub fn canonical(self) -> Value {
+        match self {
+            // 浮点数的模式使用 == 比较，可以匹配到 -0.0
+            Value::Float(0.0) => Value::Float(0.0),
+            v => v,
+        }
+    }
+
+    // 比较两个值的大小，同类型的值之间可以比较，整数和浮点数之间也可以比较
+    // NULL 以及不同类型之间无法比较，返回 None
+    pub fn compare(&self, other: &Value) -> Option<Ordering> {
+        match (self, other) {
+            (Value::Boolean(a), Value::Boolean(b)) => a.partial_cmp(b),
+            (Value::Integer(a), Value::Integer(b)) => a.partial_cmp(b),
+            (Value::Integer(a), Value::Float(b)) => cmp_integer_float(*a, *b),
+            (Value::Float(a), Value::Integer(b)) => {
+                cmp_integer_float(*b, *a).map(Ordering::reverse)
+            }
+            (Value::Float(a), Value::Float(b)) => a.partial_cmp(b),
+            (Value::String(a), Value::String(b)) => a.partial_cmp(b),
+            (_, _) => None,
+        }
+    }
+
+    // 所有值之间的全序关系，用于排序
+    // 不同类型按照 NULL、布尔、数字、字符串的顺序排列，整数和浮点数按照数值比较
+    // 浮点数使用 f64::total_cmp，数值相等的整数排在浮点数之前
+    pub fn total_cmp(&self, other: &Value) -> Ordering {
+        let rank = |v: &Value| match v {
+            Value::Null => 0,
+            Value::Boolean(_) => 1,
+            Value::Integer(_) | Value::Float(_) => 2,
+            Value::String(_) => 3,
+        };
+        match (self, other) {
+            (Value::Integer(a), Value::Float(b)) => total_cmp_integer_float(*a, *b),
+            (Value::Float(a), Value::Integer(b)) => total_cmp_integer_float(*b, *a).reverse(),
+            (Value::Float(a), Value::Float(b)) => a.total_cmp(b),
+            (a, b) => a.compare(b).unwrap_or_else(|| rank(a).cmp(&rank(b))),
+        }
+    }
+}
+
+// 精确比较整数和浮点数的数值，不转换成 f64，避免超过 2^53 的整数丢失精度
+// 浮点数为 NaN 时无法比较，返回 None
+fn cmp_integer_float(a: i64, b: f64) -> Option<Ordering> {
+    // 2^63，超出 i64 范围的浮点数（包括无穷大）由浮点数本身决定大小
+    const LIMIT: f64 = 9223372036854775808.0;
+    if b.is_nan() {
+        return None;
+    }
+    if b >= LIMIT {
+        return Some(Ordering::Less);
+    }
+    if b < -LIMIT {
+        return Some(Ordering::Greater);
+    }
+    let int = b.trunc();
+    let frac = b - int;
+    Some(a.cmp(&(int as i64)).then(if frac > 0.0 {
+        Ordering::Less
+    } else if frac < 0.0 {
+        Ordering::Greater
+    } else {
+        Ordering::Equal
+    }))
+}
+
+// 整数和浮点数的全序关系，NaN 和 f64::total_cmp 一样排在最前面或者最后面
+// 值相等时整数排在前面，-0.0 排在 0 的前面
+fn total_cmp_integer_float(a: i64, b: f64) -> Ordering {
+    match cmp_integer_float(a, b) {
+        None if b.is_sign_negative() => Ordering::Greater,
+        None => Ordering::Less,
+        Some(ordering) => ordering.then(if b == 0.0 && b.is_sign_negative() {
+            Ordering::Greater
+        } else {
+            Ordering::Less
+        }),
+    }
 }
 
 pub type Row = Vec<Value>;
+
+// 按需读取的数据行
+pub type Rows = Box<dyn Iterator<Item = Result<Row>>>;
+
+#[cfg(test)]
+mod tests {
+    use std::cmp::Ordering;
+
+    use super::Value;
+
+    #[test]
+    fn test_compare() {
+        let int = Value::Integer;
+        let float = Value::Float;
+        // 整数和浮点数按照精确的数值比较，-0.0 和 0 相等
+        assert_eq!(
+            int((1 << 53) + 1).compare(&float((1i64 << 53) as f64)),
+            Some(Ordering::Greater)
+        );
+        assert_eq!(
+            float((1i64 << 53) as f64).compare(&int((1 << 53) + 1)),
+            Some(Ordering::Less)
+        );
+        assert_eq!(
+            int(1 << 53).compare(&float((1i64 << 53) as f64)),
+            Some(Ordering::Equal)
+        );
+        assert_eq!(
+            int(i64::MAX).compare(&float(i64::MAX as f64)),
+            Some(Ordering::Less)
+        );
+        assert_eq!(
+            int(i64::MIN).compare(&float(i64::MIN as f64)),
+            Some(Ordering::Equal)
+        );
+        assert_eq!(int(-3).compare(&float(-2.5)), Some(Ordering::Less));
+        assert_eq!(int(0).compare(&float(-0.0)), Some(Ordering::Equal));
+        assert_eq!(float(-0.0).compare(&float(0.0)), Some(Ordering::Equal));
+        assert_eq!(int(1).compare(&float(f64::NAN)), None);
+        assert_eq!(int(1).compare(&Value::Null), None);
+    }
+
+    #[test]
+    fn test_total_cmp() {
+        let values = vec![
+            Value::Null,
+            Value::Boolean(false),
+            Value::Boolean(true),
+            Value::Float(f64::NEG_INFINITY),
+            Value::Integer(i64::MIN),
+            Value::Float(i64::MIN as f64),
+            Value::Integer(-3),
+            Value::Float(-2.5),
+            Value::Float(-0.0),
+            Value::Integer(0),
+            Value::Float(0.0),
+            Value::Integer(1),
+            Value::Float(1.0),
+            Value::Float(1.5),
+            // 超过 2^53 的整数转换成 f64 会丢失精度
+            Value::Integer(1 << 53),
+            Value::Float((1i64 << 53) as f64),
+            Value::Integer((1 << 53) + 1),
+            Value::Float(((1i64 << 53) + 2) as f64),
+            Value::Integer(i64::MAX - 1),
+            Value::Integer(i64::MAX),
+            Value::Float(i64::MAX as f64),
+            Value::Float(f64::INFINITY),
+            Value::Float(f64::NAN),
+            Value::String("".to_string()),
+            Value::String("a".to_string()),
+        ];
+        for (i, a) in values.iter().enumerate() {
+            for (j, b) in values.iter().enumerate() {
+                assert_eq!(a.total_cmp(b), i.cmp(&j), "{:?} {:?}", a, b);
+            }
+        }
+
+        // 排序的结果和值的插入顺序无关
+        let mut sorted = values.clone();
+        sorted.reverse();
+        sorted.sort_by(|a, b| a.total_cmp(b));
+        assert_eq!(format!("{:?}", sorted), format!("{:?}", values));
+        assert_eq!(
+            Value::Float(f64::NAN).total_cmp(&Value::Float(f64::NAN)),
+            Ordering::Equal
+        );
+    }
+}
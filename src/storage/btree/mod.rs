@@ -0,0 +1,585 @@
+use std::{
+    fs::OpenOptions,
+    ops::{Bound, RangeBounds},
+    path::PathBuf,
+    sync::Arc,
+};
+
+use fs4::FileExt;
+use node::{Node, PageId, Value, MAX_INLINE_VALUE_SIZE, MAX_KEY_SIZE, PAGE_SIZE};
+use pager::Pager;
+
+use crate::error::{Error, Result};
+
+use super::engine::{Engine, EngineIterator};
+
+mod node;
+mod pager;
+
+// B+ 树存储引擎的配置
+#[derive(Debug, Clone)]
+pub struct BTreeOptions {
+    // 页缓存中最多保存的页数
+    pub cache_pages: usize,
+}
+
+impl Default for BTreeOptions {
+    fn default() -> Self {
+        Self { cache_pages: 1024 }
+    }
+}
+
+// B+ 树存储引擎，全部的数据保存在一个按页划分的文件中
+// 写入只修改页缓存，调用 flush 时才提交到磁盘
+pub struct BTreeEngine {
+    pager: Pager,
+}
+
+// 修改子节点之后的结果，每一项是子节点中最小的 key 和页，第一项的 key 没有使用
+type Pieces = Vec<(Vec<u8>, PageId)>;
+
+// 删除 key 之后节点的状态
+enum Removed {
+    // key 不存在
+    NotFound,
+    // 节点已经为空，被删除
+    Empty,
+    // 节点被修改，保存在新的页中
+    Updated(PageId),
+}
+
+impl BTreeEngine {
+    pub fn new(path: PathBuf) -> Result<Self> {
+        Self::with_options(path, BTreeOptions::default())
+    }
+
+    pub fn with_options(path: PathBuf, options: BTreeOptions) -> Result<Self> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .read(true)
+            .write(true)
+            .open(&path)?;
+        // 同一个文件只能被打开一次
+        file.try_lock_exclusive()?;
+        Ok(Self {
+            pager: Pager::open(file, options.cache_pages)?,
+        })
+    }
+
+    // 在 id 对应的子树中写入 key/value
+    fn insert(&mut self, id: PageId, key: Vec<u8>, value: Value) -> Result<Pieces> {
+        let mut node = self.pager.read(id)?.as_ref().clone();
+        match &mut node {
+            Node::Leaf(entries) => match entries.binary_search_by(|(k, _)| k.cmp(&key)) {
+                Ok(i) => {
+                    let old = std::mem::replace(&mut entries[i].1, value);
+                    self.pager.release_value(&old)?;
+                }
+                Err(i) => entries.insert(i, (key, value)),
+            },
+            Node::Internal { keys, children } => {
+                let i = keys.partition_point(|k| k <= &key);
+                let pieces = self.insert(children[i], key, value)?;
+                children[i] = pieces[0].1;
+                for (j, (key, child)) in pieces.into_iter().enumerate().skip(1) {
+                    keys.insert(i + j - 1, key);
+                    children.insert(i + j, child);
+                }
+            }
+        }
+        self.write_node(Some(id), node)
+    }
+
+    // 在 id 对应的子树中删除 key，只删除空的节点，不合并未满的节点
+    fn remove(&mut self, id: PageId, key: &[u8]) -> Result<Removed> {
+        let mut node = self.pager.read(id)?.as_ref().clone();
+        match &mut node {
+            Node::Leaf(entries) => {
+                let i = match entries.binary_search_by(|(k, _)| k.as_slice().cmp(key)) {
+                    Ok(i) => i,
+                    Err(_) => return Ok(Removed::NotFound),
+                };
+                let (_, value) = entries.remove(i);
+                self.pager.release_value(&value)?;
+                if entries.is_empty() {
+                    self.pager.release(id);
+                    return Ok(Removed::Empty);
+                }
+            }
+            Node::Internal { keys, children } => {
+                let i = keys.partition_point(|k| k.as_slice() <= key);
+                match self.remove(children[i], key)? {
+                    Removed::NotFound => return Ok(Removed::NotFound),
+                    Removed::Updated(child) => children[i] = child,
+                    // 删除空的子节点和对应的 key
+                    Removed::Empty => {
+                        children.remove(i);
+                        if !keys.is_empty() {
+                            keys.remove(i.saturating_sub(1));
+                        }
+                        if children.is_empty() {
+                            self.pager.release(id);
+                            return Ok(Removed::Empty);
+                        }
+                    }
+                }
+            }
+        }
+        let pieces = self.write_node(Some(id), node)?;
+        Ok(Removed::Updated(pieces[0].1))
+    }
+
+    // 写入修改后的节点，超过页的大小时分裂为两个节点
+    fn write_node(&mut self, id: Option<PageId>, node: Node) -> Result<Pieces> {
+        let nodes = match node.encoded_size() > PAGE_SIZE {
+            true => {
+                let (left, key, right) = node.split();
+                vec![(Vec::new(), left), (key, right)]
+            }
+            false => vec![(Vec::new(), node)],
+        };
+        let mut pieces = Vec::new();
+        for (i, (key, node)) in nodes.into_iter().enumerate() {
+            let id = self.pager.writable(if i == 0 { id } else { None });
+            self.pager.write(id, node)?;
+            pieces.push((key, id));
+        }
+        Ok(pieces)
+    }
+}
+
+impl Engine for BTreeEngine {
+    type EngineIterator<'a> = BTreeEngineIterator<'a>;
+
+    fn set(&mut self, key: Vec<u8>, value: Vec<u8>) -> Result<()> {
+        if key.len() > MAX_KEY_SIZE {
+            return Err(Error::Internal(format!(
+                "key size {} exceeds the limit {}",
+                key.len(),
+                MAX_KEY_SIZE
+            )));
+        }
+        let value = match value.len() > MAX_INLINE_VALUE_SIZE {
+            true => self.pager.write_overflow(&value)?,
+            false => Value::Inline(value),
+        };
+        let pieces = match self.pager.root() {
+            0 => self.write_node(None, Node::Leaf(vec![(key, value)]))?,
+            root => self.insert(root, key, value)?,
+        };
+        // 根节点分裂时，树的高度增加一层
+        let root = match pieces.len() {
+            1 => pieces[0].1,
+            _ => {
+                let (mut keys, children): (Vec<_>, Vec<_>) = pieces.into_iter().unzip();
+                keys.remove(0);
+                self.write_node(None, Node::Internal { keys, children })?[0].1
+            }
+        };
+        self.pager.set_root(root);
+        Ok(())
+    }
+
+    fn get(&mut self, key: Vec<u8>) -> Result<Option<Vec<u8>>> {
+        let mut id = self.pager.root();
+        while id != 0 {
+            let node = self.pager.read(id)?;
+            match node.as_ref() {
+                Node::Leaf(entries) => {
+                    return match entries.binary_search_by(|(k, _)| k.cmp(&key)) {
+                        Ok(i) => Ok(Some(self.pager.read_value(&entries[i].1)?)),
+                        Err(_) => Ok(None),
+                    };
+                }
+                Node::Internal { keys, children } => {
+                    id = children[keys.partition_point(|k| k <= &key)];
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    fn delete(&mut self, key: Vec<u8>) -> Result<()> {
+        let root = match self.pager.root() {
+            0 => return Ok(()),
+            root => root,
+        };
+        let mut root = match self.remove(root, &key)? {
+            Removed::NotFound => return Ok(()),
+            Removed::Empty => 0,
+            Removed::Updated(root) => root,
+        };
+        // 根节点只有一个子节点时，树的高度减少一层
+        while root != 0 {
+            match self.pager.read(root)?.as_ref() {
+                Node::Internal { children, .. } if children.len() == 1 => {
+                    self.pager.release(root);
+                    root = children[0];
+                }
+                _ => break,
+            }
+        }
+        self.pager.set_root(root);
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.pager.commit()
+    }
+
+    fn scan(&mut self, range: impl RangeBounds<Vec<u8>>) -> Self::EngineIterator<'_> {
+        BTreeEngineIterator {
+            pager: &mut self.pager,
+            range: (range.start_bound().cloned(), range.end_bound().cloned()),
+            front: None,
+            back: None,
+            last_front: None,
+            last_back: None,
+            done: false,
+        }
+    }
+}
+
+impl Drop for BTreeEngine {
+    // 关闭时提交还没有持久化的修改
+    fn drop(&mut self) {
+        let _ = self.pager.commit();
+    }
+}
+
+// 迭代时从根节点到叶子节点的路径，以及每个节点中当前的位置
+type Path = Vec<(Arc<Node>, usize)>;
+
+// 从两端按需读取叶子节点，不会一次加载全部的数据
+pub struct BTreeEngineIterator<'a> {
+    pager: &'a mut Pager,
+    range: (Bound<Vec<u8>>, Bound<Vec<u8>>),
+    front: Option<Path>,
+    back: Option<Path>,
+    // 两端最后返回的 key，两端相遇时结束
+    last_front: Option<Vec<u8>>,
+    last_back: Option<Vec<u8>>,
+    done: bool,
+}
+
+impl<'a> BTreeEngineIterator<'a> {
+    // 从根节点找到范围的起点（front 为 true）或者终点
+    fn seek(&mut self, front: bool) -> Result<Path> {
+        let mut path = Path::new();
+        let mut id = self.pager.root();
+        while id != 0 {
+            let node = self.pager.read(id)?;
+            let (i, child) = match (node.as_ref(), front) {
+                (Node::Internal { keys, children }, true) => {
+                    let i = match &self.range.0 {
+                        Bound::Included(k) | Bound::Excluded(k) => keys.partition_point(|s| s <= k),
+                        Bound::Unbounded => 0,
+                    };
+                    (i, children[i])
+                }
+                (Node::Internal { keys, children }, false) => {
+                    let i = match &self.range.1 {
+                        Bound::Included(k) => keys.partition_point(|s| s <= k),
+                        Bound::Excluded(k) => keys.partition_point(|s| s < k),
+                        Bound::Unbounded => keys.len(),
+                    };
+                    (i, children[i])
+                }
+                (Node::Leaf(entries), true) => {
+                    let i = match &self.range.0 {
+                        Bound::Included(k) => entries.partition_point(|(key, _)| key < k),
+                        Bound::Excluded(k) => entries.partition_point(|(key, _)| key <= k),
+                        Bound::Unbounded => 0,
+                    };
+                    (i, 0)
+                }
+                (Node::Leaf(entries), false) => {
+                    let i = match &self.range.1 {
+                        Bound::Included(k) => entries.partition_point(|(key, _)| key <= k),
+                        Bound::Excluded(k) => entries.partition_point(|(key, _)| key < k),
+                        Bound::Unbounded => entries.len(),
+                    };
+                    (i, 0)
+                }
+            };
+            path.push((node, i));
+            id = child;
+        }
+        Ok(path)
+    }
+
+    // 沿着最左边（front 为 true）或者最右边的路径到达叶子节点
+    fn descend(pager: &mut Pager, path: &mut Path, mut id: PageId, front: bool) -> Result<()> {
+        loop {
+            let node = pager.read(id)?;
+            let (i, child) = match (node.as_ref(), front) {
+                (Node::Internal { children, .. }, true) => (0, Some(children[0])),
+                (Node::Internal { children, .. }, false) => {
+                    (children.len() - 1, children.last().copied())
+                }
+                (Node::Leaf(_), true) => (0, None),
+                (Node::Leaf(entries), false) => (entries.len(), None),
+            };
+            path.push((node, i));
+            match child {
+                Some(child) => id = child,
+                None => return Ok(()),
+            }
+        }
+    }
+
+    // 从前向后读取下一条记录
+    fn step_front(pager: &mut Pager, path: &mut Path) -> Result<Option<(Vec<u8>, Value)>> {
+        while let Some((node, i)) = path.last_mut() {
+            let next = match node.as_ref() {
+                Node::Leaf(entries) if *i < entries.len() => {
+                    *i += 1;
+                    return Ok(Some(entries[*i - 1].clone()));
+                }
+                Node::Internal { children, .. } if *i + 1 < children.len() => {
+                    *i += 1;
+                    Some(children[*i])
+                }
+                _ => None,
+            };
+            match next {
+                Some(child) => Self::descend(pager, path, child, true)?,
+                None => {
+                    path.pop();
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    // 从后向前读取下一条记录
+    fn step_back(pager: &mut Pager, path: &mut Path) -> Result<Option<(Vec<u8>, Value)>> {
+        while let Some((node, i)) = path.last_mut() {
+            let next = match node.as_ref() {
+                Node::Leaf(entries) if *i > 0 => {
+                    *i -= 1;
+                    return Ok(Some(entries[*i].clone()));
+                }
+                Node::Internal { children, .. } if *i > 0 => {
+                    *i -= 1;
+                    Some(children[*i])
+                }
+                _ => None,
+            };
+            match next {
+                Some(child) => Self::descend(pager, path, child, false)?,
+                None => {
+                    path.pop();
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    fn try_next(&mut self) -> Result<Option<(Vec<u8>, Vec<u8>)>> {
+        if self.front.is_none() {
+            self.front = Some(self.seek(true)?);
+        }
+        let path = self.front.as_mut().unwrap();
+        let (key, value) = match Self::step_front(self.pager, path)? {
+            Some(entry) => entry,
+            None => return Ok(None),
+        };
+        let in_range = match &self.range.1 {
+            Bound::Included(end) => &key <= end,
+            Bound::Excluded(end) => &key < end,
+            Bound::Unbounded => true,
+        };
+        if !in_range || self.last_back.as_ref().is_some_and(|last| &key >= last) {
+            return Ok(None);
+        }
+        let value = self.pager.read_value(&value)?;
+        self.last_front = Some(key.clone());
+        Ok(Some((key, value)))
+    }
+
+    fn try_next_back(&mut self) -> Result<Option<(Vec<u8>, Vec<u8>)>> {
+        if self.back.is_none() {
+            self.back = Some(self.seek(false)?);
+        }
+        let path = self.back.as_mut().unwrap();
+        let (key, value) = match Self::step_back(self.pager, path)? {
+            Some(entry) => entry,
+            None => return Ok(None),
+        };
+        let in_range = match &self.range.0 {
+            Bound::Included(start) => &key >= start,
+            Bound::Excluded(start) => &key > start,
+            Bound::Unbounded => true,
+        };
+        if !in_range || self.last_front.as_ref().is_some_and(|last| &key <= last) {
+            return Ok(None);
+        }
+        let value = self.pager.read_value(&value)?;
+        self.last_back = Some(key.clone());
+        Ok(Some((key, value)))
+    }
+}
+
+impl<'a> EngineIterator for BTreeEngineIterator<'a> {}
+
+impl<'a> Iterator for BTreeEngineIterator<'a> {
+    type Item = Result<(Vec<u8>, Vec<u8>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let item = self.try_next().transpose();
+        self.done = !matches!(item, Some(Ok(_)));
+        item
+    }
+}
+
+impl<'a> DoubleEndedIterator for BTreeEngineIterator<'a> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let item = self.try_next_back().transpose();
+        self.done = !matches!(item, Some(Ok(_)));
+        item
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BTreeEngine, BTreeOptions, PAGE_SIZE};
+    use crate::{
+        error::{Error, Result},
+        storage::engine::Engine,
+    };
+    use std::io::{Seek, SeekFrom, Write};
+
+    fn key(i: usize) -> Vec<u8> {
+        format!("key{:05}", i).into_bytes()
+    }
+
+    // 每 10 个 key 中有一个大的 value，保存在溢出页中
+    fn value(i: usize, round: usize) -> Vec<u8> {
+        let len = if i.is_multiple_of(10) { 6000 } else { 20 };
+        format!("{}-{}", i, round).into_bytes().repeat(len / 8)
+    }
+
+    #[test]
+    fn test_btree() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-btree");
+        let options = BTreeOptions { cache_pages: 8 };
+        let mut eng = BTreeEngine::with_options(p.clone(), options.clone())?;
+        for i in 0..2000 {
+            eng.set(key(i), value(i, 0))?;
+        }
+        for i in (0..2000).filter(|i| i % 3 == 0) {
+            eng.delete(key(i))?;
+        }
+        for i in (0..2000).filter(|i| i % 3 == 1) {
+            eng.set(key(i), value(i, 1))?;
+        }
+        eng.flush()?;
+
+        let check = |eng: &mut BTreeEngine| -> Result<()> {
+            let expected = (0..2000)
+                .filter(|i| i % 3 != 0)
+                .map(|i| (key(i), value(i, if i % 3 == 1 { 1 } else { 0 })))
+                .collect::<Vec<_>>();
+            assert_eq!(eng.scan(..).collect::<Result<Vec<_>>>()?, expected);
+            let mut reversed = eng.scan(..).rev().collect::<Result<Vec<_>>>()?;
+            reversed.reverse();
+            assert_eq!(reversed, expected);
+            assert_eq!(eng.get(key(3))?, None);
+            assert_eq!(eng.get(key(10))?, Some(value(10, 1)));
+            assert_eq!(eng.get(key(2000))?, None);
+
+            // 从两端交替读取
+            let mut iter = eng.scan(key(100)..=key(110));
+            assert_eq!(iter.next().transpose()?, Some((key(100), value(100, 1))));
+            assert_eq!(
+                iter.next_back().transpose()?,
+                Some((key(110), value(110, 0)))
+            );
+            assert_eq!(
+                iter.next_back().transpose()?,
+                Some((key(109), value(109, 1)))
+            );
+            assert_eq!(iter.next().transpose()?, Some((key(101), value(101, 0))));
+            assert_eq!(iter.count(), 4);
+            Ok(())
+        };
+        check(&mut eng)?;
+        drop(eng);
+
+        let mut eng = BTreeEngine::with_options(p.clone(), options.clone())?;
+        check(&mut eng)?;
+        // 同一个文件只能被打开一次
+        assert!(BTreeEngine::with_options(p.clone(), options.clone()).is_err());
+
+        // 删除的页会被重新使用，文件不会一直增长
+        let page_count = eng.pager.page_count();
+        for _ in 0..3 {
+            for i in 0..2000 {
+                eng.delete(key(i))?;
+            }
+            assert_eq!(eng.scan(..).count(), 0);
+            eng.flush()?;
+            for i in 0..2000 {
+                eng.set(key(i), value(i, 0))?;
+            }
+            eng.flush()?;
+        }
+        assert!(eng.pager.page_count() < page_count * 2);
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_btree_recover() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-btree");
+        let mut eng = BTreeEngine::new(p.clone())?;
+        eng.set(b"a".to_vec(), b"1".to_vec())?;
+        eng.flush()?;
+        eng.set(b"b".to_vec(), b"2".to_vec())?;
+        eng.flush()?;
+        drop(eng);
+
+        // 最后一次提交的 meta 页损坏，使用上一次提交的数据
+        let mut file = std::fs::OpenOptions::new().write(true).open(&p)?;
+        file.seek(SeekFrom::Start(PAGE_SIZE as u64 + 20))?;
+        file.write_all(b"broken")?;
+        drop(file);
+        let mut eng = BTreeEngine::new(p.clone())?;
+        assert_eq!(
+            eng.scan(..).collect::<Result<Vec<_>>>()?,
+            vec![(b"a".to_vec(), b"1".to_vec())]
+        );
+        eng.set(b"c".to_vec(), b"3".to_vec())?;
+        drop(eng);
+
+        let mut eng = BTreeEngine::new(p.clone())?;
+        assert_eq!(
+            eng.scan(..).collect::<Result<Vec<_>>>()?,
+            vec![
+                (b"a".to_vec(), b"1".to_vec()),
+                (b"c".to_vec(), b"3".to_vec())
+            ]
+        );
+        // key 过长
+        assert!(matches!(
+            eng.set(vec![0; 1024], vec![]),
+            Err(Error::Internal(_))
+        ));
+        drop(eng);
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+}
@@ -0,0 +1,272 @@
+pub type PageId = u64;
+
+// 页的大小，数据文件按页划分
+pub const PAGE_SIZE: usize = 4096;
+// key 的最大长度
+pub const MAX_KEY_SIZE: usize = 512;
+// value 超过这个长度时单独保存在溢出页中
+pub const MAX_INLINE_VALUE_SIZE: usize = 512;
+
+// crc(4) | kind(1) | count(2)
+const NODE_HEADER_SIZE: usize = 7;
+const KIND_LEAF: u8 = 1;
+const KIND_INTERNAL: u8 = 2;
+const VALUE_INLINE: u8 = 0;
+const VALUE_OVERFLOW: u8 = 1;
+
+// 叶子节点中保存的 value
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Inline(Vec<u8>),
+    // 溢出页链表的第一个页和 value 的长度
+    Overflow(PageId, u32),
+}
+
+// B+ 树的节点，每个节点占用一个页
+// 叶子节点
+// +--------+---------+----------+---------------------------------------------------+
+// | crc(4) | kind(1) | count(2) | key len(2) key flag(1) val len(2) val / page(8) len(4) |
+// +--------+---------+----------+---------------------------------------------------+
+// 内部节点，children[i] 中的 key 都小于 keys[i]，children[i + 1] 中的 key 都大于等于 keys[i]
+// +--------+---------+----------+-----------+---------------------------+
+// | crc(4) | kind(1) | count(2) | child(8)  | key len(2) key child(8)   |
+// +--------+---------+----------+-----------+---------------------------+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Node {
+    Leaf(Vec<(Vec<u8>, Value)>),
+    Internal {
+        keys: Vec<Vec<u8>>,
+        children: Vec<PageId>,
+    },
+}
+
+impl Node {
+    // 编码之后的大小，超过 PAGE_SIZE 时需要分裂
+    pub fn encoded_size(&self) -> usize {
+        match self {
+            Node::Leaf(entries) => {
+                NODE_HEADER_SIZE
+                    + entries
+                        .iter()
+                        .map(|(key, value)| leaf_entry_size(key, value))
+                        .sum::<usize>()
+            }
+            Node::Internal { keys, .. } => {
+                NODE_HEADER_SIZE + 8 + keys.iter().map(|key| 2 + key.len() + 8).sum::<usize>()
+            }
+        }
+    }
+
+    // 编码为一个页，前 4 个字节留给校验和
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(PAGE_SIZE);
+        buf.extend_from_slice(&[0; 4]);
+        match self {
+            Node::Leaf(entries) => {
+                buf.push(KIND_LEAF);
+                buf.extend_from_slice(&(entries.len() as u16).to_be_bytes());
+                for (key, value) in entries {
+                    buf.extend_from_slice(&(key.len() as u16).to_be_bytes());
+                    buf.extend_from_slice(key);
+                    match value {
+                        Value::Inline(value) => {
+                            buf.push(VALUE_INLINE);
+                            buf.extend_from_slice(&(value.len() as u16).to_be_bytes());
+                            buf.extend_from_slice(value);
+                        }
+                        Value::Overflow(page, len) => {
+                            buf.push(VALUE_OVERFLOW);
+                            buf.extend_from_slice(&page.to_be_bytes());
+                            buf.extend_from_slice(&len.to_be_bytes());
+                        }
+                    }
+                }
+            }
+            Node::Internal { keys, children } => {
+                buf.push(KIND_INTERNAL);
+                buf.extend_from_slice(&(keys.len() as u16).to_be_bytes());
+                buf.extend_from_slice(&children[0].to_be_bytes());
+                for (key, child) in keys.iter().zip(children.iter().skip(1)) {
+                    buf.extend_from_slice(&(key.len() as u16).to_be_bytes());
+                    buf.extend_from_slice(key);
+                    buf.extend_from_slice(&child.to_be_bytes());
+                }
+            }
+        }
+        buf.resize(PAGE_SIZE, 0);
+        buf
+    }
+
+    // 从页中解析节点，数据不合法时返回 None
+    pub fn decode(page: &[u8]) -> Option<Self> {
+        let mut decoder = Decoder {
+            data: page.get(4..)?,
+        };
+        let kind = decoder.u8()?;
+        let count = decoder.u16()? as usize;
+        match kind {
+            KIND_LEAF => {
+                let mut entries = Vec::with_capacity(count);
+                for _ in 0..count {
+                    let key_len = decoder.u16()? as usize;
+                    let key = decoder.bytes(key_len)?;
+                    let value = match decoder.u8()? {
+                        VALUE_INLINE => {
+                            let len = decoder.u16()? as usize;
+                            Value::Inline(decoder.bytes(len)?)
+                        }
+                        VALUE_OVERFLOW => Value::Overflow(decoder.u64()?, decoder.u32()?),
+                        _ => return None,
+                    };
+                    entries.push((key, value));
+                }
+                Some(Node::Leaf(entries))
+            }
+            KIND_INTERNAL => {
+                let mut keys = Vec::with_capacity(count);
+                let mut children = Vec::with_capacity(count + 1);
+                children.push(decoder.u64()?);
+                for _ in 0..count {
+                    let key_len = decoder.u16()? as usize;
+                    keys.push(decoder.bytes(key_len)?);
+                    children.push(decoder.u64()?);
+                }
+                Some(Node::Internal { keys, children })
+            }
+            _ => None,
+        }
+    }
+
+    // 从中间分裂为两个节点，返回左节点、右节点中最小的 key 和右节点
+    // 单条记录的大小有上限，分裂后的两个节点都能放进一个页中
+    pub fn split(self) -> (Node, Vec<u8>, Node) {
+        let half = self.encoded_size() / 2;
+        match self {
+            Node::Leaf(mut entries) => {
+                let mut size = NODE_HEADER_SIZE;
+                let mut at = 0;
+                while at < entries.len() - 1 {
+                    size += leaf_entry_size(&entries[at].0, &entries[at].1);
+                    if size > half {
+                        break;
+                    }
+                    at += 1;
+                }
+                let right = entries.split_off(at.max(1));
+                let separator = right[0].0.clone();
+                (Node::Leaf(entries), separator, Node::Leaf(right))
+            }
+            Node::Internal {
+                mut keys,
+                mut children,
+            } => {
+                let mut size = NODE_HEADER_SIZE + 8;
+                let mut at = 0;
+                while at < keys.len() - 1 {
+                    size += 2 + keys[at].len() + 8;
+                    if size > half {
+                        break;
+                    }
+                    at += 1;
+                }
+                let at = at.max(1);
+                // 中间的 key 移动到父节点中
+                let right_keys = keys.split_off(at + 1);
+                let separator = keys.pop().unwrap();
+                let right_children = children.split_off(at + 1);
+                (
+                    Node::Internal { keys, children },
+                    separator,
+                    Node::Internal {
+                        keys: right_keys,
+                        children: right_children,
+                    },
+                )
+            }
+        }
+    }
+}
+
+fn leaf_entry_size(key: &[u8], value: &Value) -> usize {
+    2 + key.len()
+        + 1
+        + match value {
+            Value::Inline(value) => 2 + value.len(),
+            Value::Overflow(..) => 12,
+        }
+}
+
+struct Decoder<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Decoder<'a> {
+    fn bytes(&mut self, len: usize) -> Option<Vec<u8>> {
+        if self.data.len() < len {
+            return None;
+        }
+        let (bytes, rest) = self.data.split_at(len);
+        self.data = rest;
+        Some(bytes.to_vec())
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        Some(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        Some(u16::from_be_bytes(self.bytes(2)?.try_into().ok()?))
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        Some(u32::from_be_bytes(self.bytes(4)?.try_into().ok()?))
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        Some(u64::from_be_bytes(self.bytes(8)?.try_into().ok()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Node, Value, MAX_INLINE_VALUE_SIZE, MAX_KEY_SIZE, PAGE_SIZE};
+
+    #[test]
+    fn test_node_split() {
+        // 最大的记录也能保证分裂后放进一个页中
+        let entries = (0..4u8)
+            .map(|i| {
+                (
+                    vec![i; MAX_KEY_SIZE],
+                    Value::Inline(vec![i; MAX_INLINE_VALUE_SIZE]),
+                )
+            })
+            .collect::<Vec<_>>();
+        let node = Node::Leaf(entries);
+        assert!(node.encoded_size() > PAGE_SIZE);
+        let (left, separator, right) = node.clone().split();
+        assert!(left.encoded_size() <= PAGE_SIZE);
+        assert!(right.encoded_size() <= PAGE_SIZE);
+        assert_eq!(separator, vec![1; MAX_KEY_SIZE]);
+        assert_eq!(Node::decode(&right.encode()), Some(right));
+
+        let node = Node::Internal {
+            keys: (1..=9u8).map(|i| vec![i; MAX_KEY_SIZE]).collect(),
+            children: (0..10).collect(),
+        };
+        let (left, separator, right) = node.clone().split();
+        assert_eq!(separator, vec![5; MAX_KEY_SIZE]);
+        assert_eq!(
+            left,
+            Node::Internal {
+                keys: (1..=4u8).map(|i| vec![i; MAX_KEY_SIZE]).collect(),
+                children: (0..5).collect(),
+            }
+        );
+        assert_eq!(Node::decode(&right.encode()), Some(right));
+        assert_eq!(
+            Node::decode(&Node::Leaf(vec![(b"a".to_vec(), Value::Overflow(7, 9000))]).encode()),
+            Some(Node::Leaf(vec![(b"a".to_vec(), Value::Overflow(7, 9000))]))
+        );
+    }
+}
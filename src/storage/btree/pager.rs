@@ -0,0 +1,446 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fs::File,
+    io::{Read, Seek, SeekFrom, Write},
+    sync::Arc,
+};
+
+use crate::error::{Error, Result};
+
+use super::node::{Node, PageId, Value, PAGE_SIZE};
+
+const MAGIC: u32 = 0x42545245;
+// meta 页: crc(4) | magic(4) | txn(8) | root(8) | page count(8) | free list(8)
+// 第 0 页和第 1 页是两个 meta 页，轮流写入
+const META_PAGES: PageId = 2;
+// crc(4) | next(8) | count(4) | page id(8) ...
+const FREELIST_HEADER_SIZE: usize = 16;
+const FREELIST_CAPACITY: usize = (PAGE_SIZE - FREELIST_HEADER_SIZE) / 8;
+// crc(4) | next(8) | len(2) | data
+const OVERFLOW_HEADER_SIZE: usize = 14;
+const OVERFLOW_CAPACITY: usize = PAGE_SIZE - OVERFLOW_HEADER_SIZE;
+
+// 最近一次提交的状态
+#[derive(Debug, Clone, Copy)]
+struct Meta {
+    txn: u64,
+    root: PageId,
+    page_count: u64,
+    freelist: PageId,
+}
+
+// 管理数据文件中的页
+// 修改页时使用写时复制，上次提交之后新分配的页可以直接修改，其他的页复制到新的页中再修改，
+// 被替换的页在下次提交之后才能重新使用。提交时先写入全部的页，再写入 meta 页，
+// 写入 meta 页之前崩溃的话，重新打开时使用上一次提交的 meta 页，数据仍然是一致的
+pub struct Pager {
+    file: File,
+    cache: PageCache,
+    meta: Meta,
+    // 当前的根节点，0 表示空树
+    root: PageId,
+    page_count: u64,
+    // 可以使用的空闲页
+    free: Vec<PageId>,
+    // 被替换的页，下次提交之后成为空闲页
+    pending: Vec<PageId>,
+    // 上次提交之后新分配的页
+    fresh: HashSet<PageId>,
+    // 上次提交时保存空闲页列表的页
+    freelist_pages: Vec<PageId>,
+    // 上次提交之后是否有修改
+    changed: bool,
+}
+
+impl Pager {
+    pub fn open(mut file: File, cache_pages: usize) -> Result<Self> {
+        let cache = PageCache::new(cache_pages);
+        if file.metadata()?.len() == 0 {
+            let mut pager = Self {
+                file,
+                cache,
+                meta: Meta {
+                    txn: 0,
+                    root: 0,
+                    page_count: META_PAGES,
+                    freelist: 0,
+                },
+                root: 0,
+                page_count: META_PAGES,
+                free: Vec::new(),
+                pending: Vec::new(),
+                fresh: HashSet::new(),
+                freelist_pages: Vec::new(),
+                changed: true,
+            };
+            pager.commit()?;
+            return Ok(pager);
+        }
+
+        // 使用两个 meta 页中最新的一个
+        let meta = (0..META_PAGES)
+            .filter_map(|id| {
+                let page = read_page(&mut file, id).ok()?;
+                decode_meta(&page)
+            })
+            .max_by_key(|meta| meta.txn)
+            .ok_or(Error::Corruption("no valid meta page".to_string()))?;
+
+        // 读取空闲页列表
+        let mut free = Vec::new();
+        let mut freelist_pages = Vec::new();
+        let mut next = meta.freelist;
+        while next != 0 {
+            let page = read_checked(&mut file, next)?;
+            let count = u32::from_be_bytes(page[12..16].try_into().unwrap()) as usize;
+            if count > FREELIST_CAPACITY {
+                return Err(Error::Corruption(format!(
+                    "invalid free list page {}",
+                    next
+                )));
+            }
+            freelist_pages.push(next);
+            next = u64::from_be_bytes(page[4..12].try_into().unwrap());
+            for i in 0..count {
+                let offset = FREELIST_HEADER_SIZE + i * 8;
+                free.push(u64::from_be_bytes(
+                    page[offset..offset + 8].try_into().unwrap(),
+                ));
+            }
+        }
+
+        Ok(Self {
+            file,
+            cache,
+            meta,
+            root: meta.root,
+            page_count: meta.page_count,
+            free,
+            pending: Vec::new(),
+            fresh: HashSet::new(),
+            freelist_pages,
+            changed: false,
+        })
+    }
+
+    pub fn root(&self) -> PageId {
+        self.root
+    }
+
+    pub fn set_root(&mut self, root: PageId) {
+        self.root = root;
+        self.changed = true;
+    }
+
+    // 读取一个节点，优先从缓存中读取
+    pub fn read(&mut self, id: PageId) -> Result<Arc<Node>> {
+        if let Some(node) = self.cache.get(id) {
+            return Ok(node);
+        }
+        let page = read_checked(&mut self.file, id)?;
+        let node =
+            Arc::new(Node::decode(&page).ok_or(Error::Corruption(format!("invalid page {}", id)))?);
+        self.cache.insert(id, node.clone(), false);
+        self.evict()?;
+        Ok(node)
+    }
+
+    // 写入一个节点，页必须是通过 writable 得到的
+    pub fn write(&mut self, id: PageId, node: Node) -> Result<()> {
+        self.cache.insert(id, Arc::new(node), true);
+        self.changed = true;
+        self.evict()
+    }
+
+    // 返回可以直接修改的页，如果 id 是上次提交之前的页，则换成一个新的页
+    pub fn writable(&mut self, id: Option<PageId>) -> PageId {
+        match id {
+            Some(id) if self.fresh.contains(&id) => id,
+            Some(id) => {
+                self.release(id);
+                self.allocate()
+            }
+            None => self.allocate(),
+        }
+    }
+
+    // 释放不再使用的页
+    pub fn release(&mut self, id: PageId) {
+        self.cache.remove(id);
+        self.changed = true;
+        if self.fresh.remove(&id) {
+            self.free.push(id);
+        } else {
+            self.pending.push(id);
+        }
+    }
+
+    fn allocate(&mut self) -> PageId {
+        let id = self.free.pop().unwrap_or_else(|| {
+            self.page_count += 1;
+            self.page_count - 1
+        });
+        self.fresh.insert(id);
+        self.changed = true;
+        id
+    }
+
+    // 将 value 写入溢出页链表
+    pub fn write_overflow(&mut self, value: &[u8]) -> Result<Value> {
+        let ids = (0..value.len().div_ceil(OVERFLOW_CAPACITY))
+            .map(|_| self.allocate())
+            .collect::<Vec<_>>();
+        for (i, chunk) in value.chunks(OVERFLOW_CAPACITY).enumerate() {
+            let next = ids.get(i + 1).copied().unwrap_or(0);
+            let mut page = vec![0; PAGE_SIZE];
+            page[4..12].copy_from_slice(&next.to_be_bytes());
+            page[12..14].copy_from_slice(&(chunk.len() as u16).to_be_bytes());
+            page[OVERFLOW_HEADER_SIZE..OVERFLOW_HEADER_SIZE + chunk.len()].copy_from_slice(chunk);
+            // 新分配的页不会被上次提交的数据引用，可以直接写入文件
+            write_page(&mut self.file, ids[i], page)?;
+        }
+        Ok(Value::Overflow(ids[0], value.len() as u32))
+    }
+
+    pub fn read_value(&mut self, value: &Value) -> Result<Vec<u8>> {
+        match value {
+            Value::Inline(value) => Ok(value.clone()),
+            Value::Overflow(id, len) => {
+                let mut value = Vec::with_capacity(*len as usize);
+                for (_, page) in self.overflow_pages(*id)? {
+                    let size = u16::from_be_bytes(page[12..14].try_into().unwrap()) as usize;
+                    if size > OVERFLOW_CAPACITY {
+                        return Err(Error::Corruption(format!("invalid overflow page {}", id)));
+                    }
+                    value.extend_from_slice(
+                        &page[OVERFLOW_HEADER_SIZE..OVERFLOW_HEADER_SIZE + size],
+                    );
+                }
+                if value.len() != *len as usize {
+                    return Err(Error::Corruption(format!("invalid overflow page {}", id)));
+                }
+                Ok(value)
+            }
+        }
+    }
+
+    // 释放 value 占用的溢出页
+    pub fn release_value(&mut self, value: &Value) -> Result<()> {
+        if let Value::Overflow(id, _) = value {
+            for (id, _) in self.overflow_pages(*id)? {
+                self.release(id);
+            }
+        }
+        Ok(())
+    }
+
+    fn overflow_pages(&mut self, mut id: PageId) -> Result<Vec<(PageId, Vec<u8>)>> {
+        let mut pages = Vec::new();
+        while id != 0 {
+            let page = read_checked(&mut self.file, id)?;
+            let next = u64::from_be_bytes(page[4..12].try_into().unwrap());
+            pages.push((id, page));
+            id = next;
+        }
+        Ok(pages)
+    }
+
+    // 提交全部的修改
+    pub fn commit(&mut self) -> Result<()> {
+        if !self.changed {
+            return Ok(());
+        }
+        for (id, node) in self.cache.take_dirty() {
+            write_page(&mut self.file, id, node.encode())?;
+        }
+
+        // 空闲页列表只能写入到上次提交时已经空闲的页中
+        let mut chain = Vec::new();
+        while chain.len() * FREELIST_CAPACITY
+            < self.free.len() + self.pending.len() + self.freelist_pages.len()
+        {
+            let id = self.free.pop().unwrap_or_else(|| {
+                self.page_count += 1;
+                self.page_count - 1
+            });
+            chain.push(id);
+        }
+        let mut free = std::mem::take(&mut self.free);
+        free.append(&mut self.pending);
+        free.append(&mut self.freelist_pages);
+        for (i, ids) in free.chunks(FREELIST_CAPACITY).enumerate() {
+            let next = chain.get(i + 1).copied().unwrap_or(0);
+            let mut page = vec![0; PAGE_SIZE];
+            page[4..12].copy_from_slice(&next.to_be_bytes());
+            page[12..16].copy_from_slice(&(ids.len() as u32).to_be_bytes());
+            for (j, id) in ids.iter().enumerate() {
+                let offset = FREELIST_HEADER_SIZE + j * 8;
+                page[offset..offset + 8].copy_from_slice(&id.to_be_bytes());
+            }
+            write_page(&mut self.file, chain[i], page)?;
+        }
+        self.file.sync_data()?;
+
+        let meta = Meta {
+            txn: self.meta.txn + 1,
+            root: self.root,
+            page_count: self.page_count,
+            freelist: chain.first().copied().unwrap_or(0),
+        };
+        write_page(&mut self.file, meta.txn % META_PAGES, encode_meta(&meta))?;
+        self.file.sync_data()?;
+
+        self.meta = meta;
+        self.free = free;
+        self.freelist_pages = chain;
+        self.fresh.clear();
+        self.changed = false;
+        Ok(())
+    }
+
+    // 缓存超过容量之后淘汰最久没有使用的页，修改过的页写入文件
+    fn evict(&mut self) -> Result<()> {
+        while let Some((id, node, dirty)) = self.cache.evict() {
+            if dirty {
+                write_page(&mut self.file, id, node.encode())?;
+            }
+        }
+        Ok(())
+    }
+
+    #[cfg(test)]
+    pub fn page_count(&self) -> u64 {
+        self.page_count
+    }
+}
+
+// 页缓存，按照 LRU 的顺序淘汰
+struct PageCache {
+    capacity: usize,
+    pages: HashMap<PageId, CachedPage>,
+    // 访问时间 -> 页
+    lru: BTreeMap<u64, PageId>,
+    tick: u64,
+}
+
+struct CachedPage {
+    node: Arc<Node>,
+    dirty: bool,
+    tick: u64,
+}
+
+impl PageCache {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            pages: HashMap::new(),
+            lru: BTreeMap::new(),
+            tick: 0,
+        }
+    }
+
+    fn get(&mut self, id: PageId) -> Option<Arc<Node>> {
+        let page = self.pages.get_mut(&id)?;
+        self.lru.remove(&page.tick);
+        self.tick += 1;
+        page.tick = self.tick;
+        self.lru.insert(self.tick, id);
+        Some(page.node.clone())
+    }
+
+    fn insert(&mut self, id: PageId, node: Arc<Node>, dirty: bool) {
+        self.tick += 1;
+        self.lru.insert(self.tick, id);
+        if let Some(page) = self.pages.insert(
+            id,
+            CachedPage {
+                node,
+                dirty,
+                tick: self.tick,
+            },
+        ) {
+            self.lru.remove(&page.tick);
+        }
+    }
+
+    fn remove(&mut self, id: PageId) {
+        if let Some(page) = self.pages.remove(&id) {
+            self.lru.remove(&page.tick);
+        }
+    }
+
+    // 超过容量时返回一个被淘汰的页
+    fn evict(&mut self) -> Option<(PageId, Arc<Node>, bool)> {
+        if self.pages.len() <= self.capacity {
+            return None;
+        }
+        let (_, id) = self.lru.pop_first()?;
+        let page = self.pages.remove(&id)?;
+        Some((id, page.node, page.dirty))
+    }
+
+    // 取出全部修改过的页，并标记为没有修改
+    fn take_dirty(&mut self) -> Vec<(PageId, Arc<Node>)> {
+        let mut dirty = Vec::new();
+        for (id, page) in self.pages.iter_mut().filter(|(_, page)| page.dirty) {
+            page.dirty = false;
+            dirty.push((*id, page.node.clone()));
+        }
+        dirty
+    }
+}
+
+fn encode_meta(meta: &Meta) -> Vec<u8> {
+    let mut page = vec![0; PAGE_SIZE];
+    page[4..8].copy_from_slice(&MAGIC.to_be_bytes());
+    page[8..16].copy_from_slice(&meta.txn.to_be_bytes());
+    page[16..24].copy_from_slice(&meta.root.to_be_bytes());
+    page[24..32].copy_from_slice(&meta.page_count.to_be_bytes());
+    page[32..40].copy_from_slice(&meta.freelist.to_be_bytes());
+    page
+}
+
+// meta 页不完整或者校验失败时返回 None
+fn decode_meta(page: &[u8]) -> Option<Meta> {
+    let crc = u32::from_be_bytes(page[0..4].try_into().ok()?);
+    if crc != crc32fast::hash(&page[4..])
+        || u32::from_be_bytes(page[4..8].try_into().ok()?) != MAGIC
+    {
+        return None;
+    }
+    Some(Meta {
+        txn: u64::from_be_bytes(page[8..16].try_into().ok()?),
+        root: u64::from_be_bytes(page[16..24].try_into().ok()?),
+        page_count: u64::from_be_bytes(page[24..32].try_into().ok()?),
+        freelist: u64::from_be_bytes(page[32..40].try_into().ok()?),
+    })
+}
+
+// 写入一个页，前 4 个字节写入校验和
+fn write_page(file: &mut File, id: PageId, mut page: Vec<u8>) -> Result<()> {
+    let crc = crc32fast::hash(&page[4..]);
+    page[0..4].copy_from_slice(&crc.to_be_bytes());
+    file.seek(SeekFrom::Start(id * PAGE_SIZE as u64))?;
+    file.write_all(&page)?;
+    Ok(())
+}
+
+fn read_page(file: &mut File, id: PageId) -> Result<Vec<u8>> {
+    file.seek(SeekFrom::Start(id * PAGE_SIZE as u64))?;
+    let mut page = vec![0; PAGE_SIZE];
+    file.read_exact(&mut page)?;
+    Ok(page)
+}
+
+// 读取一个页并检查校验和
+fn read_checked(file: &mut File, id: PageId) -> Result<Vec<u8>> {
+    let page = read_page(file, id)?;
+    let crc = u32::from_be_bytes(page[0..4].try_into().unwrap());
+    if crc != crc32fast::hash(&page[4..]) {
+        return Err(Error::Corruption(format!(
+            "checksum mismatch in page {}",
+            id
+        )));
+    }
+    Ok(page)
+}
@@ -2,117 +2,591 @@ use std::{
     collections::{btree_map, BTreeMap},
     fs::{File, OpenOptions},
     io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write},
-    path::PathBuf,
+    path::{Path, PathBuf},
+    sync::mpsc,
+    thread,
+    time::Duration,
     vec,
 };
 
 use fs4::FileExt;
 
-use crate::error::Result;
+use crate::error::{Error, Result};
 
-pub type KeyDir = BTreeMap<Vec<u8>, (u64, u32)>;
-const LOG_HEADER_SIZE: u32 = 8;
+pub type FileId = u32;
+// 数据所在的文件 id、value 的偏移和大小
+pub type KeyDirEntry = (FileId, u64, u32);
+pub type KeyDir = BTreeMap<Vec<u8>, KeyDirEntry>;
+const LOG_HEADER_SIZE: u32 = 12;
+// 一条记录的最大大小，超过文件末尾的记录只有在这个范围内才可能是没有写完整的记录
+const MAX_ENTRY_SIZE: u64 = 64 * 1024 * 1024;
+// 目录锁，保证同时只能有一个服务去使用这个目录
+const LOCK_FILE: &str = "LOCK";
+const LOG_EXT: &str = "log";
+// 合并完成但还没有替换掉旧文件的段文件
+const MERGE_EXT: &str = "merge";
+// 正在写入的合并文件
+const MERGE_TMP_EXT: &str = "merge.tmp";
+// 合并后的段文件的索引
+const HINT_EXT: &str = "hint";
+const HINT_TMP_EXT: &str = "hint.tmp";
+
+// 数据写入磁盘的方式
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SyncMode {
+    // 每次写入都调用 fsync
+    Always,
+    // 每次事务提交时调用 fsync
+    OnCommit,
+    // 后台定时调用 fsync
+    Interval(Duration),
+    // 不主动调用 fsync，由操作系统决定什么时候写入磁盘
+    Never,
+}
+
+// 磁盘存储引擎的配置
+#[derive(Debug, Clone)]
+pub struct DiskOptions {
+    pub sync_mode: SyncMode,
+    // 垃圾数据的占比超过这个值时，在后台压缩日志文件
+    pub compact_threshold: f64,
+    // 日志文件小于这个大小时不压缩
+    pub compact_min_bytes: u64,
+    // 单个段文件的大小上限，超过之后写入新的段文件
+    pub segment_max_bytes: u64,
+}
+
+impl Default for DiskOptions {
+    fn default() -> Self {
+        Self {
+            sync_mode: SyncMode::OnCommit,
+            compact_threshold: 0.5,
+            compact_min_bytes: 16 * 1024 * 1024,
+            segment_max_bytes: 64 * 1024 * 1024,
+        }
+    }
+}
+
+// 日志文件中有效数据和垃圾数据的大小
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct DiskStats {
+    // keydir 中的记录占用的大小
+    pub live_bytes: u64,
+    // 被覆盖、删除的记录，以及删除标记占用的大小
+    pub dead_bytes: u64,
+}
+
+impl DiskStats {
+    // 垃圾数据的占比
+    pub fn garbage_ratio(&self) -> f64 {
+        let total = self.live_bytes + self.dead_bytes;
+        if total == 0 {
+            return 0.0;
+        }
+        self.dead_bytes as f64 / total as f64
+    }
+}
+
+// 合并后的段文件，以及每个 key 合并前后的位置
+type MergeResult = (Log, Vec<(Vec<u8>, KeyDirEntry, KeyDirEntry)>);
+
+// 正在后台进行的合并
+struct Compaction {
+    // 合并后的段文件 id，比它小的段文件都会被合并
+    merge_id: FileId,
+    handle: thread::JoinHandle<Result<MergeResult>>,
+}
 
 // 磁盘存储引擎定义
+// 数据保存在一个目录中，分为多个段文件，只有最新的段文件会写入数据
 pub struct DiskEngine {
+    dir: PathBuf,
+    // 持有目录锁
+    _lock: File,
     keydir: KeyDir,
-    log: Log,
+    logs: BTreeMap<FileId, Log>,
+    // 当前写入的段文件
+    active_id: FileId,
+    options: DiskOptions,
+    stats: DiskStats,
+    compaction: Option<Compaction>,
 }
 
 impl DiskEngine {
-    pub fn new(file_path: PathBuf) -> Result<Self> {
-        let mut log = Log::new(file_path)?;
-        // 从 log 中去恢复的 keydir
-        let keydir = log.build_keydir()?;
-        Ok(Self { keydir, log })
+    pub fn new(dir: PathBuf) -> Result<Self> {
+        Self::with_options(dir, DiskOptions::default())
     }
 
-    pub fn new_compact(file_path: PathBuf) -> Result<Self> {
-        let mut eng = Self::new(file_path)?;
+    pub fn with_options(dir: PathBuf, options: DiskOptions) -> Result<Self> {
+        std::fs::create_dir_all(&dir)?;
+        let lock = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(dir.join(LOCK_FILE))?;
+        lock.try_lock_exclusive()?;
+
+        // 完成上一次崩溃时没有完成的合并
+        Self::recover_merge(&dir)?;
+
+        // 按照顺序从每个段文件中去恢复的 keydir
+        let ids = Self::list_segments(&dir)?;
+        let active_id = ids.last().copied().unwrap_or(1);
+        let mut keydir = KeyDir::new();
+        let mut logs = BTreeMap::new();
+        for id in ids {
+            let mut log = Log::new(segment_path(&dir, id), SyncMode::Never)?;
+            match Self::load_hint(&dir, id, log.file.metadata()?.len())? {
+                // 合并后的段文件直接从 hint 文件中加载索引
+                Some(entries) => keydir.extend(entries),
+                // 只有最后一个段文件末尾可能有没写完整的记录
+                None => log.build_keydir(id, &mut keydir, id == active_id)?,
+            }
+            logs.insert(id, log);
+        }
+        if let btree_map::Entry::Vacant(entry) = logs.entry(active_id) {
+            entry.insert(Log::new(segment_path(&dir, active_id), SyncMode::Never)?);
+        }
+        if let Some(log) = logs.get_mut(&active_id) {
+            log.set_sync_mode(options.sync_mode)?;
+        }
+
+        let stats = Self::build_stats(&keydir, &logs)?;
+        Ok(Self {
+            dir,
+            _lock: lock,
+            keydir,
+            logs,
+            active_id,
+            options,
+            stats,
+            compaction: None,
+        })
+    }
+
+    pub fn new_compact(dir: PathBuf) -> Result<Self> {
+        let mut eng = Self::new(dir)?;
         eng.compact()?;
         Ok(eng)
     }
 
-    fn compact(&mut self) -> Result<()> {
-        // 新打开一个临时日志文件
-        let mut new_path = self.log.file_path.clone();
-        new_path.set_extension("compact");
+    // 获取日志文件中有效数据和垃圾数据的大小
+    pub fn stats(&self) -> DiskStats {
+        self.stats
+    }
 
-        let mut new_log = Log::new(new_path)?;
-        let mut new_keydir = KeyDir::new();
+    // 压缩日志文件，会等待压缩完成
+    pub fn compact(&mut self) -> Result<()> {
+        if self.compaction.is_none() {
+            self.start_compaction()?;
+        }
+        self.finish_compaction()
+    }
 
-        // 重写数据到临时文件中
-        for (key, (offset, val_size)) in self.keydir.iter() {
-            // 读取 value
-            let value = self.log.read_value(*offset, *val_size)?;
-            let (new_offset, new_size) = new_log.write_entry(key, Some(&value))?;
+    // 获取目录中全部段文件的 id
+    fn list_segments(dir: &Path) -> Result<Vec<FileId>> {
+        let mut ids = Vec::new();
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some(LOG_EXT) {
+                continue;
+            }
+            if let Some(id) = path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .and_then(|stem| stem.parse().ok())
+            {
+                ids.push(id);
+            }
+        }
+        ids.sort();
+        Ok(ids)
+    }
 
-            new_keydir.insert(
-                key.clone(),
-                (new_offset + new_size as u64 - *val_size as u64, *val_size),
-            );
+    // 如果合并文件已经写入完成，删除被合并的段文件，并用合并文件替换
+    // 没有写入完成的合并文件直接删除
+    fn recover_merge(dir: &Path) -> Result<()> {
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+            let name = match path.file_name().and_then(|name| name.to_str()) {
+                Some(name) => name.to_string(),
+                None => continue,
+            };
+            if name.ends_with(&format!(".{}", MERGE_TMP_EXT))
+                || name.ends_with(&format!(".{}", HINT_TMP_EXT))
+            {
+                std::fs::remove_file(&path)?;
+            } else if let Some(stem) = name.strip_suffix(&format!(".{}", MERGE_EXT)) {
+                let merge_id: FileId = stem
+                    .parse()
+                    .map_err(|_| Error::Corruption(format!("invalid merge file {}", name)))?;
+                Self::replace_merged(dir, merge_id)?;
+            }
+        }
+        Ok(())
+    }
+
+    // 删除被合并的段文件，将合并文件改为正式的段文件
+    fn replace_merged(dir: &Path, merge_id: FileId) -> Result<()> {
+        for id in Self::list_segments(dir)? {
+            if id <= merge_id {
+                std::fs::remove_file(segment_path(dir, id))?;
+                // 之前的 hint 文件已经无效
+                let hint_path = dir.join(format!("{:08}.{}", id, HINT_EXT));
+                if hint_path.exists() {
+                    std::fs::remove_file(hint_path)?;
+                }
+            }
+        }
+        std::fs::rename(
+            dir.join(format!("{:08}.{}", merge_id, MERGE_EXT)),
+            segment_path(dir, merge_id),
+        )?;
+        File::open(dir)?.sync_all()?;
+        Ok(())
+    }
+
+    // +-----------------+-------------+-----------+-------------+-------+--------+
+    // | segment size(8) | key len(4)    offset(8)   val size(4)   key   | crc(4) |
+    // +-----------------+-------------+-----------+-------------+-------+--------+
+    // hint 文件记录了合并后的段文件中每个 key 的位置，中间的部分每个 key 一条
+    fn write_hint(
+        path: PathBuf,
+        segment_size: u64,
+        entries: &[(&[u8], KeyDirEntry)],
+    ) -> Result<()> {
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(true)
+            .write(true)
+            .open(path)?;
+        let mut writer = BufWriter::new(&file);
+        let mut hasher = crc32fast::Hasher::new();
+        let mut write = |buf: &[u8]| -> Result<()> {
+            hasher.update(buf);
+            writer.write_all(buf)?;
+            Ok(())
+        };
+        write(&segment_size.to_be_bytes())?;
+        for (key, (_, offset, val_size)) in entries {
+            write(&(key.len() as u32).to_be_bytes())?;
+            write(&offset.to_be_bytes())?;
+            write(&val_size.to_be_bytes())?;
+            write(key)?;
+        }
+        writer.write_all(&hasher.finalize().to_be_bytes())?;
+        writer.flush()?;
+        drop(writer);
+        file.sync_all()?;
+        Ok(())
+    }
+
+    // 加载段文件的 hint 文件
+    // hint 文件不存在、校验失败或者和段文件不匹配时返回 None，需要扫描整个段文件
+    fn load_hint(dir: &Path, id: FileId, segment_size: u64) -> Result<Option<KeyDir>> {
+        let path = dir.join(format!("{:08}.{}", id, HINT_EXT));
+        if !path.exists() {
+            return Ok(None);
+        }
+        let data = std::fs::read(path)?;
+        if data.len() < 12 {
+            return Ok(None);
+        }
+        let (data, crc) = data.split_at(data.len() - 4);
+        if crc32fast::hash(data) != u32::from_be_bytes(crc.try_into().unwrap()) {
+            return Ok(None);
+        }
+        if u64::from_be_bytes(data[..8].try_into().unwrap()) != segment_size {
+            return Ok(None);
+        }
+
+        let mut entries = KeyDir::new();
+        let mut pos = 8;
+        while pos < data.len() {
+            if pos + 16 > data.len() {
+                return Ok(None);
+            }
+            let key_size = u32::from_be_bytes(data[pos..pos + 4].try_into().unwrap()) as usize;
+            let offset = u64::from_be_bytes(data[pos + 4..pos + 12].try_into().unwrap());
+            let val_size = u32::from_be_bytes(data[pos + 12..pos + 16].try_into().unwrap());
+            pos += 16;
+            if pos + key_size > data.len() {
+                return Ok(None);
+            }
+            entries.insert(data[pos..pos + key_size].to_vec(), (id, offset, val_size));
+            pos += key_size;
+        }
+        Ok(Some(entries))
+    }
+
+    // 根据 keydir 计算有效数据的大小，其余的都是垃圾数据
+    fn build_stats(keydir: &KeyDir, logs: &BTreeMap<FileId, Log>) -> Result<DiskStats> {
+        let live_bytes = keydir
+            .iter()
+            .map(|(key, (_, _, val_size))| Self::entry_size(key, *val_size))
+            .sum();
+        let mut file_size = 0;
+        for log in logs.values() {
+            file_size += log.file.metadata()?.len();
         }
+        Ok(DiskStats {
+            live_bytes,
+            dead_bytes: file_size - live_bytes,
+        })
+    }
 
-        // 将临时文件更改为正式文件
-        std::fs::rename(&new_log.file_path, &self.log.file_path)?;
+    // 一条记录在日志文件中占用的大小
+    fn entry_size(key: &[u8], val_size: u32) -> u64 {
+        LOG_HEADER_SIZE as u64 + key.len() as u64 + val_size as u64
+    }
 
-        new_log.file_path = self.log.file_path.clone();
-        self.keydir = new_keydir;
-        self.log = new_log;
+    // 写入当前的段文件，超过大小上限后切换到新的段文件
+    fn write_entry(&mut self, key: &Vec<u8>, value: Option<&Vec<u8>>) -> Result<(u64, u32)> {
+        let log = self
+            .logs
+            .get_mut(&self.active_id)
+            .ok_or(Error::Internal(format!(
+                "segment {} not found",
+                self.active_id
+            )))?;
+        let (offset, size) = log.write_entry(key, value)?;
+        if offset + size as u64 >= self.options.segment_max_bytes {
+            self.rotate()?;
+        }
+        Ok((offset, size))
+    }
+
+    // 关闭当前的段文件，开始写入新的段文件
+    fn rotate(&mut self) -> Result<()> {
+        if let Some(log) = self.logs.get_mut(&self.active_id) {
+            log.set_sync_mode(SyncMode::Never)?;
+            log.file.sync_all()?;
+        }
+        let id = self.active_id + 1;
+        let log = Log::new(segment_path(&self.dir, id), self.options.sync_mode)?;
+        File::open(&self.dir)?.sync_all()?;
+        self.logs.insert(id, log);
+        self.active_id = id;
+        Ok(())
+    }
+
+    // 写入数据后，检查后台压缩是否完成，以及是否需要开始新的压缩
+    fn maybe_compact(&mut self) -> Result<()> {
+        if let Some(compaction) = &self.compaction {
+            if compaction.handle.is_finished() {
+                self.finish_compaction()?;
+            }
+            return Ok(());
+        }
+        if self.stats.live_bytes + self.stats.dead_bytes >= self.options.compact_min_bytes
+            && self.stats.garbage_ratio() >= self.options.compact_threshold
+        {
+            self.start_compaction()?;
+        }
+        Ok(())
+    }
+
+    // 在后台线程中将不再写入的段文件合并成一个新的段文件
+    // 合并期间新写入的数据都在之后的段文件中
+    fn start_compaction(&mut self) -> Result<()> {
+        // 当前的段文件也需要合并
+        if self.logs[&self.active_id].file.metadata()?.len() > 0 {
+            self.rotate()?;
+        }
+        let merge_id = match self.logs.range(..self.active_id).next_back() {
+            Some((id, _)) => *id,
+            None => return Ok(()),
+        };
+
+        let entries = self
+            .keydir
+            .iter()
+            .filter(|(_, (file_id, _, _))| *file_id <= merge_id)
+            .map(|(key, entry)| (key.clone(), *entry))
+            .collect::<Vec<_>>();
+        let paths = self
+            .logs
+            .iter()
+            .filter(|(id, _)| **id <= merge_id)
+            .map(|(id, log)| (*id, log.file_path.clone()))
+            .collect::<Vec<_>>();
+        let tmp_path = self.dir.join(format!("{:08}.{}", merge_id, MERGE_TMP_EXT));
+        let hint_path = self.dir.join(format!("{:08}.{}", merge_id, HINT_TMP_EXT));
+
+        let handle = thread::spawn(move || -> Result<MergeResult> {
+            // 使用单独的文件句柄读取，不影响前台的读写
+            let mut files = BTreeMap::new();
+            for (id, path) in paths {
+                files.insert(id, File::open(path)?);
+            }
+            // 写入临时文件时不需要 fsync，替换之前会统一写入磁盘
+            let mut new_log = Log::new(tmp_path, SyncMode::Never)?;
+            let mut moved = Vec::with_capacity(entries.len());
+
+            // 重写数据到临时文件中
+            for (key, (file_id, offset, val_size)) in entries.into_iter() {
+                let file = files
+                    .get_mut(&file_id)
+                    .ok_or(Error::Internal(format!("segment {} not found", file_id)))?;
+                let value = read_at(file, offset, val_size)?;
+                let (new_offset, new_size) = new_log.write_entry(&key, Some(&value))?;
+                let new_entry = (
+                    merge_id,
+                    new_offset + new_size as u64 - val_size as u64,
+                    val_size,
+                );
+                moved.push((key, (file_id, offset, val_size), new_entry));
+            }
+
+            // 写入合并后的段文件的 hint 文件
+            let hints = moved
+                .iter()
+                .map(|(key, _, new_entry)| (key.as_slice(), *new_entry))
+                .collect::<Vec<_>>();
+            Self::write_hint(hint_path, new_log.file.metadata()?.len(), &hints)?;
+            Ok((new_log, moved))
+        });
+        self.compaction = Some(Compaction { merge_id, handle });
+        Ok(())
+    }
+
+    // 等待后台合并完成，然后替换掉被合并的段文件
+    fn finish_compaction(&mut self) -> Result<()> {
+        let compaction = match self.compaction.take() {
+            Some(compaction) => compaction,
+            None => return Ok(()),
+        };
+        let merge_id = compaction.merge_id;
+        let (mut new_log, moved) = compaction
+            .handle
+            .join()
+            .map_err(|_| Error::Internal("compaction thread panicked".to_string()))??;
+
+        // 合并文件写入磁盘之后改名，之后即使崩溃，重启时也会完成替换
+        new_log.file.sync_all()?;
+        let merge_path = self.dir.join(format!("{:08}.{}", merge_id, MERGE_EXT));
+        std::fs::rename(&new_log.file_path, &merge_path)?;
+        File::open(&self.dir)?.sync_all()?;
+
+        // 关闭被合并的段文件
+        self.logs.retain(|id, _| *id > merge_id);
+        Self::replace_merged(&self.dir, merge_id)?;
+        new_log.file_path = segment_path(&self.dir, merge_id);
+        self.logs.insert(merge_id, new_log);
+
+        // 段文件替换完成之后 hint 文件才能生效
+        std::fs::rename(
+            self.dir.join(format!("{:08}.{}", merge_id, HINT_TMP_EXT)),
+            self.dir.join(format!("{:08}.{}", merge_id, HINT_EXT)),
+        )?;
+        File::open(&self.dir)?.sync_all()?;
+
+        // 合并期间没有被修改的 key 指向新的位置
+        for (key, old_entry, new_entry) in moved {
+            if let Some(entry) = self.keydir.get_mut(&key) {
+                if *entry == old_entry {
+                    *entry = new_entry;
+                }
+            }
+        }
+        self.stats = Self::build_stats(&self.keydir, &self.logs)?;
 
         Ok(())
     }
 }
 
+impl Drop for DiskEngine {
+    // 关闭之前等待后台压缩完成
+    fn drop(&mut self) {
+        let _ = self.finish_compaction();
+    }
+}
+
+// 段文件的路径
+fn segment_path(dir: &Path, id: FileId) -> PathBuf {
+    dir.join(format!("{:08}.{}", id, LOG_EXT))
+}
+
 impl super::engine::Engine for DiskEngine {
     type EngineIterator<'a> = DiskEngineIterator<'a>;
 
     fn set(&mut self, key: Vec<u8>, value: Vec<u8>) -> Result<()> {
         // 先写日志
-        let (offset, size) = self.log.write_entry(&key, Some(&value))?;
+        let file_id = self.active_id;
+        let (offset, size) = self.write_entry(&key, Some(&value))?;
         // 更新内存索引
         // 100----------------|-----150
         //                   130
         // val size = 20
         let val_size = value.len() as u32;
-        self.keydir
-            .insert(key, (offset + size as u64 - val_size as u64, val_size));
-        Ok(())
+        self.stats.live_bytes += size as u64;
+        if let Some((_, _, old_size)) = self.keydir.insert(
+            key.clone(),
+            (file_id, offset + size as u64 - val_size as u64, val_size),
+        ) {
+            let old_size = Self::entry_size(&key, old_size);
+            self.stats.live_bytes -= old_size;
+            self.stats.dead_bytes += old_size;
+        }
+        self.maybe_compact()
     }
 
     fn get(&mut self, key: Vec<u8>) -> Result<Option<Vec<u8>>> {
         match self.keydir.get(&key) {
-            Some((offset, val_size)) => {
-                let val = self.log.read_value(*offset, *val_size)?;
-                Ok(Some(val))
-            }
+            Some(entry) => Ok(Some(read_entry_value(&mut self.logs, entry)?)),
             None => Ok(None),
         }
     }
 
     fn delete(&mut self, key: Vec<u8>) -> Result<()> {
-        self.log.write_entry(&key, None)?;
-        self.keydir.remove(&key);
+        let (_, size) = self.write_entry(&key, None)?;
+        self.stats.dead_bytes += size as u64;
+        if let Some((_, _, old_size)) = self.keydir.remove(&key) {
+            let old_size = Self::entry_size(&key, old_size);
+            self.stats.live_bytes -= old_size;
+            self.stats.dead_bytes += old_size;
+        }
+        self.maybe_compact()
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        // 其他模式下不需要在事务提交时写入磁盘
+        if self.options.sync_mode == SyncMode::OnCommit {
+            if let Some(log) = self.logs.get_mut(&self.active_id) {
+                log.file.sync_data()?;
+            }
+        }
         Ok(())
     }
 
     fn scan(&mut self, range: impl std::ops::RangeBounds<Vec<u8>>) -> Self::EngineIterator<'_> {
         DiskEngineIterator {
             inner: self.keydir.range(range),
-            log: &mut self.log,
+            logs: &mut self.logs,
         }
     }
 }
 
+// 根据 keydir 中的位置读取 value
+fn read_entry_value(
+    logs: &mut BTreeMap<FileId, Log>,
+    (file_id, offset, val_size): &KeyDirEntry,
+) -> Result<Vec<u8>> {
+    match logs.get_mut(file_id) {
+        Some(log) => log.read_value(*offset, *val_size),
+        None => Err(Error::Internal(format!("segment {} not found", file_id))),
+    }
+}
+
 pub struct DiskEngineIterator<'a> {
-    inner: btree_map::Range<'a, Vec<u8>, (u64, u32)>,
-    log: &'a mut Log,
+    inner: btree_map::Range<'a, Vec<u8>, KeyDirEntry>,
+    logs: &'a mut BTreeMap<FileId, Log>,
 }
 
 impl<'a> DiskEngineIterator<'a> {
-    fn map(&mut self, item: (&Vec<u8>, &(u64, u32))) -> <Self as Iterator>::Item {
-        let (k, (offset, val_size)) = item;
-        let value = self.log.read_value(*offset, *val_size)?;
+    fn map(&mut self, item: (&Vec<u8>, &KeyDirEntry)) -> <Self as Iterator>::Item {
+        let (k, entry) = item;
+        let value = read_entry_value(self.logs, entry)?;
         Ok((k.clone(), value))
     }
 }
@@ -136,10 +610,13 @@ impl<'a> DoubleEndedIterator for DiskEngineIterator<'a> {
 struct Log {
     file_path: PathBuf,
     file: std::fs::File,
+    sync_mode: SyncMode,
+    // 定时 fsync 的后台线程，关闭 channel 后线程退出
+    syncer: Option<(mpsc::Sender<()>, thread::JoinHandle<()>)>,
 }
 
 impl Log {
-    fn new(file_path: PathBuf) -> Result<Self> {
+    fn new(file_path: PathBuf, sync_mode: SyncMode) -> Result<Self> {
         // 如果目录不存在的话则创建
         if let Some(dir) = file_path.parent() {
             if !dir.exists() {
@@ -154,15 +631,50 @@ impl Log {
             .write(true)
             .open(&file_path)?;
 
-        // 加文件锁，保证同时只能有一个服务去使用这个文件
-        file.try_lock_exclusive()?;
+        let mut log = Self {
+            file,
+            file_path,
+            sync_mode: SyncMode::Never,
+            syncer: None,
+        };
+        log.set_sync_mode(sync_mode)?;
+        Ok(log)
+    }
+
+    // 修改 fsync 的方式，Interval 模式下启动后台 fsync 线程
+    fn set_sync_mode(&mut self, sync_mode: SyncMode) -> Result<()> {
+        self.stop_syncer();
+        if let SyncMode::Interval(interval) = sync_mode {
+            let sync_file = self.file.try_clone()?;
+            let (tx, rx) = mpsc::channel::<()>();
+            let handle = thread::spawn(move || {
+                while let Err(mpsc::RecvTimeoutError::Timeout) = rx.recv_timeout(interval) {
+                    let _ = sync_file.sync_data();
+                }
+            });
+            self.syncer = Some((tx, handle));
+        }
+        self.sync_mode = sync_mode;
+        Ok(())
+    }
 
-        Ok(Self { file, file_path })
+    // 停止后台 fsync 线程，并把剩余的数据写入磁盘
+    fn stop_syncer(&mut self) {
+        if let Some((tx, handle)) = self.syncer.take() {
+            drop(tx);
+            let _ = handle.join();
+            let _ = self.file.sync_data();
+        }
     }
 
     // 遍历数据文件，构建内存索引
-    fn build_keydir(&mut self) -> Result<KeyDir> {
-        let mut keydir = KeyDir::new();
+    // allow_torn 为 true 时，截断文件末尾没有写完整的记录，否则认为文件已经损坏
+    fn build_keydir(
+        &mut self,
+        file_id: FileId,
+        keydir: &mut KeyDir,
+        allow_torn: bool,
+    ) -> Result<()> {
         let file_size = self.file.metadata()?.len();
         let mut buf_reader = BufReader::new(&self.file);
 
@@ -172,7 +684,21 @@ impl Log {
                 break;
             }
 
-            let (key, val_size) = Self::read_entry(&mut buf_reader, offset)?;
+            let (key, val_size) = match Self::read_entry(&mut buf_reader, offset, file_size)? {
+                Some(entry) => entry,
+                None if !allow_torn => {
+                    return Err(Error::Corruption(format!(
+                        "incomplete entry at offset {} in segment {}",
+                        offset, file_id
+                    )))
+                }
+                None => {
+                    // 最后一条记录没有写完整，截断这部分数据
+                    self.file.set_len(offset)?;
+                    self.file.sync_all()?;
+                    break;
+                }
+            };
             let key_size = key.len() as u32;
             if val_size == -1 {
                 keydir.remove(&key);
@@ -181,6 +707,7 @@ impl Log {
                 keydir.insert(
                     key,
                     (
+                        file_id,
                         offset + LOG_HEADER_SIZE as u64 + key_size as u64,
                         val_size as u32,
                     ),
@@ -189,43 +716,77 @@ impl Log {
             }
         }
 
-        Ok(keydir)
+        Ok(())
     }
 
-    // +-------------+-------------+----------------+----------------+
-    // | key len(4)    val len(4)     key(varint)       val(varint)  |
-    // +-------------+-------------+----------------+----------------+
+    // +-------------+-------------+-------------+----------------+----------------+
+    // | crc(4)        key len(4)    val len(4)     key(varint)       val(varint)  |
+    // +-------------+-------------+-------------+----------------+----------------+
+    // crc 校验的是 crc 之后的全部数据
     fn write_entry(&mut self, key: &Vec<u8>, value: Option<&Vec<u8>>) -> Result<(u64, u32)> {
         // 首先将文件偏移移动到文件末尾
+        let size = LOG_HEADER_SIZE as u64 + key.len() as u64 + value.map_or(0, |v| v.len() as u64);
+        if size > MAX_ENTRY_SIZE {
+            return Err(Error::Internal(format!(
+                "entry size {} exceeds the limit {}",
+                size, MAX_ENTRY_SIZE
+            )));
+        }
         let offset = self.file.seek(SeekFrom::End(0))?;
         let key_size = key.len() as u32;
         let val_size = value.map_or(0, |v| v.len() as u32);
         let total_size = key_size + val_size + LOG_HEADER_SIZE;
 
-        // 写入 key size、value size、key、value
+        let key_size_buf = key_size.to_be_bytes();
+        let val_size_buf = value.map_or(-1, |v| v.len() as i32).to_be_bytes();
+        let mut hasher = crc32fast::Hasher::new();
+        hasher.update(&key_size_buf);
+        hasher.update(&val_size_buf);
+        hasher.update(key);
+        if let Some(v) = value {
+            hasher.update(v);
+        }
+
+        // 写入 crc、key size、value size、key、value
         let mut writer = BufWriter::with_capacity(total_size as usize, &self.file);
-        writer.write_all(&key_size.to_be_bytes())?;
-        writer.write_all(&value.map_or(-1, |v| v.len() as i32).to_be_bytes())?;
-        writer.write_all(&key)?;
+        writer.write_all(&hasher.finalize().to_be_bytes())?;
+        writer.write_all(&key_size_buf)?;
+        writer.write_all(&val_size_buf)?;
+        writer.write_all(key)?;
         if let Some(v) = value {
             writer.write_all(v)?;
         }
         writer.flush()?;
+        drop(writer);
+
+        if self.sync_mode == SyncMode::Always {
+            self.file.sync_data()?;
+        }
 
         Ok((offset, total_size))
     }
 
     fn read_value(&mut self, offset: u64, val_size: u32) -> Result<Vec<u8>> {
-        self.file.seek(SeekFrom::Start(offset))?;
-        let mut buf = vec![0; val_size as usize];
-        self.file.read_exact(&mut buf)?;
-        Ok(buf)
+        read_at(&mut self.file, offset, val_size)
     }
 
-    fn read_entry(buf_reader: &mut BufReader<&File>, offset: u64) -> Result<(Vec<u8>, i32)> {
+    // 读取一条记录，返回 key 和 value size
+    // 如果文件末尾的记录没有写完整，返回 None
+    fn read_entry(
+        buf_reader: &mut BufReader<&File>,
+        offset: u64,
+        file_size: u64,
+    ) -> Result<Option<(Vec<u8>, i32)>> {
+        if offset + LOG_HEADER_SIZE as u64 > file_size {
+            return Ok(None);
+        }
         buf_reader.seek(SeekFrom::Start(offset))?;
         let mut len_buf = [0; 4];
 
+        // 读取 crc
+        buf_reader.read_exact(&mut len_buf)?;
+        let crc = u32::from_be_bytes(len_buf);
+
         // 读取 key size
         buf_reader.read_exact(&mut len_buf)?;
         let key_size = u32::from_be_bytes(len_buf);
@@ -233,22 +794,102 @@ impl Log {
         // 读取 value size
         buf_reader.read_exact(&mut len_buf)?;
         let val_size = i32::from_be_bytes(len_buf);
+        if val_size < -1 {
+            return Err(Error::Corruption(format!(
+                "invalid value size {} at offset {}",
+                val_size, offset
+            )));
+        }
+
+        // 超过上限的记录不可能被写入，说明长度字段已经损坏
+        let size = LOG_HEADER_SIZE as u64 + key_size as u64 + val_size.max(0) as u64;
+        if size > MAX_ENTRY_SIZE {
+            return Err(Error::Corruption(format!(
+                "invalid entry size {} at offset {}",
+                size, offset
+            )));
+        }
+        let end = offset + size;
+        if end > file_size {
+            return Ok(None);
+        }
 
-        // 读取 key
+        // 读取 key 和 value
         let mut key = vec![0; key_size as usize];
         buf_reader.read_exact(&mut key)?;
+        let mut value = vec![0; val_size.max(0) as usize];
+        buf_reader.read_exact(&mut value)?;
 
-        Ok((key, val_size))
+        // 校验 crc
+        let mut hasher = crc32fast::Hasher::new();
+        hasher.update(&key_size.to_be_bytes());
+        hasher.update(&val_size.to_be_bytes());
+        hasher.update(&key);
+        hasher.update(&value);
+        if hasher.finalize() != crc {
+            // 最后一条记录校验失败，说明写入的时候发生了崩溃
+            if end == file_size {
+                return Ok(None);
+            }
+            return Err(Error::Corruption(format!(
+                "checksum mismatch at offset {}",
+                offset
+            )));
+        }
+
+        Ok(Some((key, val_size)))
     }
 }
 
+impl Drop for Log {
+    fn drop(&mut self) {
+        self.stop_syncer();
+    }
+}
+
+// 从文件指定的位置读取数据
+fn read_at(file: &mut File, offset: u64, size: u32) -> Result<Vec<u8>> {
+    file.seek(SeekFrom::Start(offset))?;
+    let mut buf = vec![0; size as usize];
+    file.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
 #[cfg(test)]
 mod tets {
     use crate::{
-        error::Result,
-        storage::{disk::DiskEngine, engine::Engine},
+        error::{Error, Result},
+        storage::{
+            disk::{segment_path, DiskEngine, DiskOptions, Log, SyncMode},
+            engine::Engine,
+        },
     };
-    use std::path::PathBuf;
+    use std::{
+        io::{Seek, SeekFrom, Write},
+        path::{Path, PathBuf},
+        time::Duration,
+    };
+
+    // 目录中全部文件的名字
+    fn list_files(dir: &Path) -> Result<Vec<String>> {
+        let mut names = Vec::new();
+        for entry in std::fs::read_dir(dir)? {
+            names.push(entry?.file_name().to_string_lossy().to_string());
+        }
+        names.sort();
+        Ok(names)
+    }
+
+    // 全部段文件的大小
+    fn segments_size(dir: &Path) -> Result<u64> {
+        let mut size = 0;
+        for name in list_files(dir)? {
+            if name.ends_with(".log") {
+                size += std::fs::metadata(dir.join(name))?.len();
+            }
+        }
+        Ok(size)
+    }
 
     #[test]
     fn test_disk_engine_compact() -> Result<()> {
@@ -296,4 +937,338 @@ mod tets {
 
         Ok(())
     }
+
+    #[test]
+    fn test_disk_engine_torn_tail() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let seg = segment_path(&p, 1);
+        let mut eng = DiskEngine::new(p.clone())?;
+        eng.set(b"key1".to_vec(), b"value1".to_vec())?;
+        eng.set(b"key2".to_vec(), b"value2".to_vec())?;
+        drop(eng);
+        let size = std::fs::metadata(&seg)?.len();
+
+        let mut eng = DiskEngine::new(p.clone())?;
+        eng.set(b"key3".to_vec(), b"value3".to_vec())?;
+        drop(eng);
+
+        // 最后一条记录只写入了一部分
+        let file = std::fs::OpenOptions::new().write(true).open(&seg)?;
+        file.set_len(std::fs::metadata(&seg)?.len() - 3)?;
+        drop(file);
+
+        let mut eng = DiskEngine::new(p.clone())?;
+        assert_eq!(std::fs::metadata(&seg)?.len(), size);
+        assert_eq!(eng.get(b"key3".to_vec())?, None);
+        eng.set(b"key4".to_vec(), b"value4".to_vec())?;
+        drop(eng);
+
+        // 最后一条记录的数据没有完整写入
+        let mut file = std::fs::OpenOptions::new().write(true).open(&seg)?;
+        file.seek(SeekFrom::End(-1))?;
+        file.write_all(b"x")?;
+        drop(file);
+
+        let mut eng = DiskEngine::new(p.clone())?;
+        let v = eng.scan(..).collect::<Result<Vec<_>>>()?;
+        assert_eq!(
+            v,
+            vec![
+                (b"key1".to_vec(), b"value1".to_vec()),
+                (b"key2".to_vec(), b"value2".to_vec()),
+            ]
+        );
+        drop(eng);
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_disk_engine_corruption() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let seg = segment_path(&p, 1);
+        let mut eng = DiskEngine::new(p.clone())?;
+        eng.set(b"key1".to_vec(), b"value1".to_vec())?;
+        eng.set(b"key2".to_vec(), b"value2".to_vec())?;
+        drop(eng);
+
+        // 修改第一条记录中的一个字节
+        let mut data = std::fs::read(&seg)?;
+        data[14] ^= 0x01;
+        std::fs::write(&seg, data)?;
+
+        assert!(matches!(
+            DiskEngine::new(p.clone()),
+            Err(Error::Corruption(_))
+        ));
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_disk_engine_corrupted_size() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let seg = segment_path(&p, 1);
+        let mut eng = DiskEngine::new(p.clone())?;
+        eng.set(b"key1".to_vec(), b"value1".to_vec())?;
+        eng.set(b"key2".to_vec(), b"value2".to_vec())?;
+        eng.set(b"key3".to_vec(), b"value3".to_vec())?;
+        drop(eng);
+
+        // 第一条记录的 key 长度被修改，超出了文件末尾，不能当作没有写完整的记录截断
+        let mut data = std::fs::read(&seg)?;
+        let size = data.len() as u64;
+        data[4] = 0x7f;
+        std::fs::write(&seg, data)?;
+
+        assert!(matches!(
+            DiskEngine::new(p.clone()),
+            Err(Error::Corruption(_))
+        ));
+        assert_eq!(std::fs::metadata(&seg)?.len(), size);
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_disk_engine_sync_mode() -> Result<()> {
+        for sync_mode in [
+            SyncMode::Always,
+            SyncMode::OnCommit,
+            SyncMode::Interval(Duration::from_millis(10)),
+            SyncMode::Never,
+        ] {
+            let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+            let options = DiskOptions {
+                sync_mode,
+                ..Default::default()
+            };
+            let mut eng = DiskEngine::with_options(p.clone(), options.clone())?;
+            eng.set(b"key1".to_vec(), b"value1".to_vec())?;
+            eng.set(b"key2".to_vec(), b"value2".to_vec())?;
+            eng.flush()?;
+            std::thread::sleep(Duration::from_millis(20));
+            eng.delete(b"key1".to_vec())?;
+            eng.compact()?;
+            eng.set(b"key3".to_vec(), b"value3".to_vec())?;
+            drop(eng);
+
+            // 关闭之后文件锁已经释放，可以重新打开
+            let mut eng = DiskEngine::with_options(p.clone(), options)?;
+            let v = eng.scan(..).collect::<Result<Vec<_>>>()?;
+            assert_eq!(
+                v,
+                vec![
+                    (b"key2".to_vec(), b"value2".to_vec()),
+                    (b"key3".to_vec(), b"value3".to_vec()),
+                ]
+            );
+            drop(eng);
+
+            std::fs::remove_dir_all(p.parent().unwrap())?;
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_disk_engine_online_compact() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let options = DiskOptions {
+            compact_threshold: 0.5,
+            compact_min_bytes: 1024,
+            ..Default::default()
+        };
+        let mut eng = DiskEngine::with_options(p.clone(), options.clone())?;
+        for i in 0..10 {
+            eng.set(format!("key{}", i).into_bytes(), b"value".to_vec())?;
+        }
+        assert_eq!(eng.stats().dead_bytes, 0);
+        eng.delete(b"key0".to_vec())?;
+        eng.set(b"key1".to_vec(), b"value1".to_vec())?;
+        assert_eq!(eng.stats().dead_bytes, 16 + 21 + 21);
+
+        // 压缩期间写入的数据不会丢失
+        eng.start_compaction()?;
+        eng.set(b"key2".to_vec(), b"value2".to_vec())?;
+        eng.delete(b"key3".to_vec())?;
+        eng.set(b"key10".to_vec(), b"value10".to_vec())?;
+        eng.finish_compaction()?;
+        // 压缩期间写入的删除标记等数据仍然保留在文件中
+        let stats = eng.stats();
+        assert_eq!(stats.live_bytes, 22 * 2 + 21 * 6 + 24);
+        assert_eq!(stats.live_bytes + stats.dead_bytes, segments_size(&p)?);
+        assert!(stats.dead_bytes > 0);
+
+        let expected = eng.scan(..).collect::<Result<Vec<_>>>()?;
+        assert_eq!(expected.len(), 9);
+        assert_eq!(eng.get(b"key3".to_vec())?, None);
+        assert_eq!(eng.get(b"key10".to_vec())?, Some(b"value10".to_vec()));
+
+        // 不断覆盖写入，垃圾数据超过阈值后会自动压缩
+        for i in 0..200 {
+            eng.set(b"key1".to_vec(), format!("value{}", i).into_bytes())?;
+        }
+        eng.finish_compaction()?;
+        assert!(segments_size(&p)? < 200 * 21);
+        assert_eq!(eng.get(b"key1".to_vec())?, Some(b"value199".to_vec()));
+        drop(eng);
+
+        // 重新打开后数据不变
+        let mut eng = DiskEngine::with_options(p.clone(), options)?;
+        assert_eq!(eng.get(b"key1".to_vec())?, Some(b"value199".to_vec()));
+        assert_eq!(eng.scan(..).count(), 9);
+        assert!(list_files(&p)?
+            .iter()
+            .all(|name| name == "LOCK" || name.ends_with(".log") || name.ends_with(".hint")));
+        drop(eng);
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_disk_engine_segments() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let options = DiskOptions {
+            segment_max_bytes: 100,
+            ..Default::default()
+        };
+        let mut eng = DiskEngine::with_options(p.clone(), options.clone())?;
+        // 同一个目录只能被打开一次
+        assert!(DiskEngine::with_options(p.clone(), options.clone()).is_err());
+
+        for i in 0..20 {
+            eng.set(format!("key{:02}", i).into_bytes(), b"value".to_vec())?;
+        }
+        for i in 0..10 {
+            eng.delete(format!("key{:02}", i).into_bytes())?;
+        }
+        assert!(list_files(&p)?.len() > 5);
+        let expected = eng.scan(..).collect::<Result<Vec<_>>>()?;
+        assert_eq!(expected.len(), 10);
+        drop(eng);
+
+        let mut eng = DiskEngine::with_options(p.clone(), options.clone())?;
+        assert_eq!(eng.scan(..).collect::<Result<Vec<_>>>()?, expected);
+
+        // 合并之前的段文件
+        eng.compact()?;
+        eng.set(b"key20".to_vec(), b"value".to_vec())?;
+        assert_eq!(eng.scan(..).count(), 11);
+        assert_eq!(
+            eng.stats().live_bytes + eng.stats().dead_bytes,
+            segments_size(&p)?
+        );
+        drop(eng);
+
+        let mut eng = DiskEngine::with_options(p.clone(), options)?;
+        assert_eq!(eng.scan(..).count(), 11);
+        assert_eq!(eng.get(b"key05".to_vec())?, None);
+        assert_eq!(eng.get(b"key15".to_vec())?, Some(b"value".to_vec()));
+        drop(eng);
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_disk_engine_recover_merge() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let options = DiskOptions {
+            segment_max_bytes: 40,
+            ..Default::default()
+        };
+        let mut eng = DiskEngine::with_options(p.clone(), options.clone())?;
+        eng.set(b"key1".to_vec(), b"value1".to_vec())?;
+        eng.set(b"key2".to_vec(), b"value2".to_vec())?;
+        eng.delete(b"key1".to_vec())?;
+        eng.set(b"key3".to_vec(), b"value3".to_vec())?;
+        eng.set(b"key2".to_vec(), b"value2-2".to_vec())?;
+        eng.set(b"key4".to_vec(), b"value4".to_vec())?;
+        drop(eng);
+        assert_eq!(
+            list_files(&p)?,
+            vec!["00000001.log", "00000002.log", "00000003.log", "LOCK"]
+        );
+
+        // 没有写完的合并文件会被删除
+        let mut log = Log::new(p.join("00000002.merge.tmp"), SyncMode::Never)?;
+        log.write_entry(&b"key1".to_vec(), Some(&b"value1".to_vec()))?;
+        drop(log);
+        // 写完的合并文件会替换掉之前的段文件
+        let mut log = Log::new(p.join("00000002.merge"), SyncMode::Never)?;
+        log.write_entry(&b"key2".to_vec(), Some(&b"value2-2".to_vec()))?;
+        log.write_entry(&b"key3".to_vec(), Some(&b"value3".to_vec()))?;
+        drop(log);
+
+        let mut eng = DiskEngine::with_options(p.clone(), options)?;
+        assert_eq!(
+            list_files(&p)?,
+            vec!["00000002.log", "00000003.log", "LOCK"]
+        );
+        assert_eq!(
+            eng.scan(..).collect::<Result<Vec<_>>>()?,
+            vec![
+                (b"key2".to_vec(), b"value2-2".to_vec()),
+                (b"key3".to_vec(), b"value3".to_vec()),
+                (b"key4".to_vec(), b"value4".to_vec()),
+            ]
+        );
+        drop(eng);
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_disk_engine_hint() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let mut eng = DiskEngine::new(p.clone())?;
+        for i in 0..10 {
+            eng.set(format!("key{}", i).into_bytes(), b"value".to_vec())?;
+        }
+        eng.delete(b"key0".to_vec())?;
+        eng.compact()?;
+        eng.set(b"key1".to_vec(), b"value1".to_vec())?;
+        eng.delete(b"key2".to_vec())?;
+        let expected = eng.scan(..).collect::<Result<Vec<_>>>()?;
+        drop(eng);
+        assert_eq!(
+            list_files(&p)?,
+            vec!["00000001.hint", "00000001.log", "00000002.log", "LOCK"]
+        );
+
+        // 从 hint 文件加载索引，不会扫描合并后的段文件
+        let seg = segment_path(&p, 1);
+        let mut data = std::fs::read(&seg)?;
+        data[14] ^= 0x01;
+        std::fs::write(&seg, &data)?;
+        let mut eng = DiskEngine::new(p.clone())?;
+        assert_eq!(eng.get(b"key5".to_vec())?, Some(b"value".to_vec()));
+        assert_eq!(eng.scan(..).count(), expected.len());
+        drop(eng);
+
+        // hint 文件校验失败时扫描整个段文件
+        data[14] ^= 0x01;
+        std::fs::write(&seg, &data)?;
+        let hint = p.join("00000001.hint");
+        let mut hint_data = std::fs::read(&hint)?;
+        hint_data[10] ^= 0x01;
+        std::fs::write(&hint, &hint_data)?;
+        let mut eng = DiskEngine::new(p.clone())?;
+        assert_eq!(eng.scan(..).collect::<Result<Vec<_>>>()?, expected);
+        drop(eng);
+
+        // hint 文件不存在时扫描整个段文件
+        std::fs::remove_file(&hint)?;
+        let mut eng = DiskEngine::new(p.clone())?;
+        assert_eq!(eng.scan(..).collect::<Result<Vec<_>>>()?, expected);
+        drop(eng);
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
 }
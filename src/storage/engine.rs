@@ -17,6 +17,11 @@ pub trait Engine {
     // 删除 key 对应的数据，如果 key 不存在的话则忽略
     fn delete(&mut self, key: Vec<u8>) -> Result<()>;
 
+    // 将已经写入的数据持久化，事务提交时调用
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+
     // 扫描
     fn scan(&mut self, range: impl RangeBounds<Vec<u8>>) -> Self::EngineIterator<'_>;
 
@@ -42,7 +47,7 @@ mod tests {
     use super::Engine;
     use crate::{
         error::Result,
-        storage::memory::MemoryEngine,
+        storage::{btree::BTreeEngine, lsm::LsmEngine, memory::MemoryEngine},
         // storage::{disk::DiskEngine, memory::MemoryEngine},
     };
     use std::{ops::Bound, path::PathBuf};
@@ -148,4 +153,24 @@ mod tests {
     //     std::fs::remove_dir_all(PathBuf::from("/tmp/sqldb3"))?;
     //     Ok(())
     // }
+
+    #[test]
+    fn test_btree() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path();
+        test_point_opt(BTreeEngine::new(p.join("sqldb1"))?)?;
+        test_scan(BTreeEngine::new(p.join("sqldb2"))?)?;
+        test_scan_prefix(BTreeEngine::new(p.join("sqldb3"))?)?;
+        std::fs::remove_dir_all(p)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_lsm() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path();
+        test_point_opt(LsmEngine::new(p.join("sqldb1"))?)?;
+        test_scan(LsmEngine::new(p.join("sqldb2"))?)?;
+        test_scan_prefix(LsmEngine::new(p.join("sqldb3"))?)?;
+        std::fs::remove_dir_all(p)?;
+        Ok(())
+    }
 }
@@ -5,6 +5,15 @@ use serde::{
 
 use crate::error::{Error, Result};
 
+// 保序的 key 编码，编码后的字节顺序和原始值的顺序一致
+// - bool: 0 / 1
+// - 整数: 大端序，有符号整数翻转符号位
+// - 浮点数: 正数翻转符号位，负数翻转全部的位
+// - 字符串 / 字节数组: 0 转义为 0 255，以 0 0 结尾
+// - Option: None 为 0，Some 为 1 加上值
+// - 变长的 seq / map: 每个元素前面放 1，以 0 结尾
+// - 枚举: 变体序号（一个字节）加上字段
+// - tuple / struct: 依次编码每个字段
 pub fn serialize_key<T: serde::Serialize>(key: &T) -> Result<Vec<u8>> {
     let mut ser = Serializer { output: Vec::new() };
     key.serialize(&mut ser)?;
@@ -13,14 +22,21 @@ pub fn serialize_key<T: serde::Serialize>(key: &T) -> Result<Vec<u8>> {
 
 pub fn deserialize_key<'a, T: serde::Deserialize<'a>>(input: &'a [u8]) -> Result<T> {
     let mut der = Deserializer { input };
-    T::deserialize(&mut der)
+    let value = T::deserialize(&mut der)?;
+    if !der.input.is_empty() {
+        return Err(Error::Internal(format!(
+            "unexpected trailing bytes {:?}",
+            der.input
+        )));
+    }
+    Ok(value)
 }
 
 pub struct Serializer {
     output: Vec<u8>,
 }
 
-impl<'a> ser::Serializer for &'a mut Serializer {
+impl ser::Serializer for &mut Serializer {
     type Ok = ();
 
     type Error = Error;
@@ -31,44 +47,57 @@ impl<'a> ser::Serializer for &'a mut Serializer {
 
     type SerializeTupleVariant = Self;
 
-    type SerializeTupleStruct = serde::ser::Impossible<Self::Ok, Self::Error>;
+    type SerializeTupleStruct = Self;
 
-    type SerializeMap = serde::ser::Impossible<Self::Ok, Self::Error>;
+    type SerializeMap = Self;
 
-    type SerializeStruct = serde::ser::Impossible<Self::Ok, Self::Error>;
+    type SerializeStruct = Self;
 
-    type SerializeStructVariant = serde::ser::Impossible<Self::Ok, Self::Error>;
+    type SerializeStructVariant = Self;
 
+    // false -> 0, true -> 1
     fn serialize_bool(self, v: bool) -> Result<()> {
-        todo!()
+        self.output.push(v as u8);
+        Ok(())
     }
 
+    // 有符号整数翻转符号位，负数排在正数前面
     fn serialize_i8(self, v: i8) -> Result<()> {
-        todo!()
+        self.output.extend(((v as u8) ^ (1 << 7)).to_be_bytes());
+        Ok(())
     }
 
     fn serialize_i16(self, v: i16) -> Result<()> {
-        todo!()
+        self.output.extend(((v as u16) ^ (1 << 15)).to_be_bytes());
+        Ok(())
     }
 
     fn serialize_i32(self, v: i32) -> Result<()> {
-        todo!()
+        self.output.extend(((v as u32) ^ (1 << 31)).to_be_bytes());
+        Ok(())
     }
 
+    // -1 -> 7f ff ff ff ff ff ff ff
+    //  0 -> 80 00 00 00 00 00 00 00
+    //  1 -> 80 00 00 00 00 00 00 01
     fn serialize_i64(self, v: i64) -> Result<()> {
-        todo!()
+        self.output.extend(((v as u64) ^ (1 << 63)).to_be_bytes());
+        Ok(())
     }
 
     fn serialize_u8(self, v: u8) -> Result<()> {
-        todo!()
+        self.output.push(v);
+        Ok(())
     }
 
     fn serialize_u16(self, v: u16) -> Result<()> {
-        todo!()
+        self.output.extend(v.to_be_bytes());
+        Ok(())
     }
 
     fn serialize_u32(self, v: u32) -> Result<()> {
-        todo!()
+        self.output.extend(v.to_be_bytes());
+        Ok(())
     }
 
     fn serialize_u64(self, v: u64) -> Result<()> {
@@ -77,19 +106,33 @@ impl<'a> ser::Serializer for &'a mut Serializer {
     }
 
     fn serialize_f32(self, v: f32) -> Result<()> {
-        todo!()
+        let bits = v.to_bits();
+        let bits = match v.is_sign_negative() {
+            true => !bits,
+            false => bits ^ (1 << 31),
+        };
+        self.output.extend(bits.to_be_bytes());
+        Ok(())
     }
 
+    // 正数翻转符号位，负数翻转全部的位，-NaN < -Inf < ... < -0.0 < 0.0 < ... < Inf < NaN
     fn serialize_f64(self, v: f64) -> Result<()> {
-        todo!()
+        let bits = v.to_bits();
+        let bits = match v.is_sign_negative() {
+            true => !bits,
+            false => bits ^ (1 << 63),
+        };
+        self.output.extend(bits.to_be_bytes());
+        Ok(())
     }
 
     fn serialize_char(self, v: char) -> Result<()> {
-        todo!()
+        self.serialize_u32(v as u32)
     }
 
+    // 和 bytes 的编码方式相同
     fn serialize_str(self, v: &str) -> Result<()> {
-        todo!()
+        self.serialize_bytes(v.as_bytes())
     }
 
     // 原始值           编码后
@@ -98,7 +141,7 @@ impl<'a> ser::Serializer for &'a mut Serializer {
     // 97 98 0 0 99 -> 97 98 0 255 0 255 99 0 0
     fn serialize_bytes(self, v: &[u8]) -> Result<()> {
         let mut res = Vec::new();
-        for e in v.into_iter() {
+        for e in v.iter() {
             match e {
                 0 => res.extend([0, 255]),
                 b => res.push(*b),
@@ -111,41 +154,50 @@ impl<'a> ser::Serializer for &'a mut Serializer {
         Ok(())
     }
 
+    // None 排在 Some 前面
     fn serialize_none(self) -> Result<()> {
-        todo!()
+        self.output.push(0);
+        Ok(())
     }
 
     fn serialize_some<T>(self, value: &T) -> Result<()>
     where
         T: ?Sized + ser::Serialize,
     {
-        todo!()
+        self.output.push(1);
+        value.serialize(self)
     }
 
     fn serialize_unit(self) -> Result<()> {
-        todo!()
+        Ok(())
     }
 
-    fn serialize_unit_struct(self, name: &'static str) -> Result<()> {
-        todo!()
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<()> {
+        Ok(())
     }
 
     // 类似 MvccKey::NextVersion
     fn serialize_unit_variant(
         self,
-        name: &'static str,
+        _name: &'static str,
         variant_index: u32,
         variant: &'static str,
     ) -> Result<()> {
-        self.output.extend(u8::try_from(variant_index));
+        let index = u8::try_from(variant_index).map_err(|_| {
+            Error::Internal(format!(
+                "variant {} index {} out of range",
+                variant, variant_index
+            ))
+        })?;
+        self.output.push(index);
         Ok(())
     }
 
-    fn serialize_newtype_struct<T>(self, name: &'static str, value: &T) -> Result<()>
+    fn serialize_newtype_struct<T>(self, _name: &'static str, value: &T) -> Result<()>
     where
         T: ?Sized + ser::Serialize,
     {
-        todo!()
+        value.serialize(self)
     }
 
     // 类似 TxnAcvtive(Version)
@@ -163,20 +215,21 @@ impl<'a> ser::Serializer for &'a mut Serializer {
         value.serialize(self)
     }
 
-    fn serialize_seq(self, len: Option<usize>) -> Result<Self::SerializeSeq> {
+    // 长度不固定，每个元素前面放 1，结尾放 0，短的序列排在以它为前缀的长序列前面
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq> {
         Ok(self)
     }
 
-    fn serialize_tuple(self, len: usize) -> Result<Self::SerializeTuple> {
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple> {
         Ok(self)
     }
 
     fn serialize_tuple_struct(
         self,
-        name: &'static str,
-        len: usize,
+        _name: &'static str,
+        _len: usize,
     ) -> Result<Self::SerializeTupleStruct> {
-        todo!()
+        Ok(self)
     }
 
     // 类似 TxnWrite(Version, Vec<u8>)
@@ -185,18 +238,20 @@ impl<'a> ser::Serializer for &'a mut Serializer {
         name: &'static str,
         variant_index: u32,
         variant: &'static str,
-        len: usize,
+        _len: usize,
     ) -> Result<Self::SerializeTupleVariant> {
         self.serialize_unit_variant(name, variant_index, variant)?;
         Ok(self)
     }
 
-    fn serialize_map(self, len: Option<usize>) -> Result<Self::SerializeMap> {
-        todo!()
+    // 和 seq 相同，每个 key value 前面放 1，结尾放 0
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap> {
+        Ok(self)
     }
 
-    fn serialize_struct(self, name: &'static str, len: usize) -> Result<Self::SerializeStruct> {
-        todo!()
+    // 按照定义的顺序依次编码每个字段，不保存字段名
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Self::SerializeStruct> {
+        Ok(self)
     }
 
     fn serialize_struct_variant(
@@ -204,13 +259,14 @@ impl<'a> ser::Serializer for &'a mut Serializer {
         name: &'static str,
         variant_index: u32,
         variant: &'static str,
-        len: usize,
+        _len: usize,
     ) -> Result<Self::SerializeStructVariant> {
-        todo!()
+        self.serialize_unit_variant(name, variant_index, variant)?;
+        Ok(self)
     }
 }
 
-impl<'a> ser::SerializeSeq for &'a mut Serializer {
+impl ser::SerializeSeq for &mut Serializer {
     type Ok = ();
 
     type Error = Error;
@@ -219,15 +275,17 @@ impl<'a> ser::SerializeSeq for &'a mut Serializer {
     where
         T: ?Sized + ser::Serialize,
     {
+        self.output.push(1);
         value.serialize(&mut **self)
     }
 
     fn end(self) -> Result<()> {
+        self.output.push(0);
         Ok(())
     }
 }
 
-impl<'a> ser::SerializeTuple for &'a mut Serializer {
+impl ser::SerializeTuple for &mut Serializer {
     type Ok = ();
 
     type Error = Error;
@@ -244,7 +302,7 @@ impl<'a> ser::SerializeTuple for &'a mut Serializer {
     }
 }
 
-impl<'a> ser::SerializeTupleVariant for &'a mut Serializer {
+impl ser::SerializeTupleVariant for &mut Serializer {
     type Ok = ();
 
     type Error = Error;
@@ -261,15 +319,110 @@ impl<'a> ser::SerializeTupleVariant for &'a mut Serializer {
     }
 }
 
+impl ser::SerializeTupleStruct for &mut Serializer {
+    type Ok = ();
+
+    type Error = Error;
+
+    fn serialize_field<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + ser::Serialize,
+    {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl ser::SerializeMap for &mut Serializer {
+    type Ok = ();
+
+    type Error = Error;
+
+    fn serialize_key<T>(&mut self, key: &T) -> Result<()>
+    where
+        T: ?Sized + ser::Serialize,
+    {
+        self.output.push(1);
+        key.serialize(&mut **self)
+    }
+
+    fn serialize_value<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + ser::Serialize,
+    {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<()> {
+        self.output.push(0);
+        Ok(())
+    }
+}
+
+impl ser::SerializeStruct for &mut Serializer {
+    type Ok = ();
+
+    type Error = Error;
+
+    fn serialize_field<T>(&mut self, _key: &'static str, value: &T) -> Result<()>
+    where
+        T: ?Sized + ser::Serialize,
+    {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl ser::SerializeStructVariant for &mut Serializer {
+    type Ok = ();
+
+    type Error = Error;
+
+    fn serialize_field<T>(&mut self, _key: &'static str, value: &T) -> Result<()>
+    where
+        T: ?Sized + ser::Serialize,
+    {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<()> {
+        Ok(())
+    }
+}
+
 pub struct Deserializer<'de> {
     input: &'de [u8],
 }
 
 impl<'de> Deserializer<'de> {
-    fn take_bytes(&mut self, len: usize) -> &[u8] {
-        let bytes = &self.input[..len];
-        self.input = &self.input[len..];
-        bytes
+    // 输入不够时返回错误，不会 panic
+    fn take_bytes(&mut self, len: usize) -> Result<&'de [u8]> {
+        if self.input.len() < len {
+            return Err(Error::Internal(format!(
+                "unexpected end of input, need {} bytes but only {} left",
+                len,
+                self.input.len()
+            )));
+        }
+        let (bytes, rest) = self.input.split_at(len);
+        self.input = rest;
+        Ok(bytes)
+    }
+
+    fn take_array<const N: usize>(&mut self) -> Result<[u8; N]> {
+        let mut buf = [0; N];
+        buf.copy_from_slice(self.take_bytes(N)?);
+        Ok(buf)
+    }
+
+    fn take_u8(&mut self) -> Result<u8> {
+        Ok(self.take_bytes(1)?[0])
     }
 
     // - 如果这个 0 之后的值是 255，说明是原始字符串中的 0，则继续解析
@@ -291,118 +444,154 @@ impl<'de> Deserializer<'de> {
         self.input = &self.input[i..];
         Ok(res)
     }
+
+    fn next_string(&mut self) -> Result<String> {
+        String::from_utf8(self.next_bytes()?)
+            .map_err(|e| Error::Internal(format!("invalid utf-8 string: {}", e)))
+    }
+
+    // seq / map 中是否还有下一个元素
+    fn has_next(&mut self) -> Result<bool> {
+        match self.take_u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            b => Err(Error::Internal(format!("invalid element marker {}", b))),
+        }
+    }
 }
 
-impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
+impl<'de> de::Deserializer<'de> for &mut Deserializer<'de> {
     type Error = Error;
 
-    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value>
+    // 编码中没有保存类型信息，必须由调用方指定类型
+    fn deserialize_any<V>(self, _visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        todo!()
+        Err(Error::Internal(
+            "keycode does not support deserialize_any".into(),
+        ))
     }
 
     fn deserialize_bool<V>(self, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        todo!()
+        match self.take_u8()? {
+            0 => visitor.visit_bool(false),
+            1 => visitor.visit_bool(true),
+            b => Err(Error::Internal(format!("invalid bool value {}", b))),
+        }
     }
 
     fn deserialize_i8<V>(self, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        todo!()
+        let v = u8::from_be_bytes(self.take_array()?) ^ (1 << 7);
+        visitor.visit_i8(v as i8)
     }
 
     fn deserialize_i16<V>(self, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        todo!()
+        let v = u16::from_be_bytes(self.take_array()?) ^ (1 << 15);
+        visitor.visit_i16(v as i16)
     }
 
     fn deserialize_i32<V>(self, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        todo!()
+        let v = u32::from_be_bytes(self.take_array()?) ^ (1 << 31);
+        visitor.visit_i32(v as i32)
     }
 
     fn deserialize_i64<V>(self, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        todo!()
+        let v = u64::from_be_bytes(self.take_array()?) ^ (1 << 63);
+        visitor.visit_i64(v as i64)
     }
 
     fn deserialize_u8<V>(self, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        todo!()
+        visitor.visit_u8(self.take_u8()?)
     }
 
     fn deserialize_u16<V>(self, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        todo!()
+        visitor.visit_u16(u16::from_be_bytes(self.take_array()?))
     }
 
     fn deserialize_u32<V>(self, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        todo!()
+        visitor.visit_u32(u32::from_be_bytes(self.take_array()?))
     }
 
-    // &[u8] -> Vec<u8>
-    // From TryFrom
     fn deserialize_u64<V>(self, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        let bytes = self.take_bytes(8);
-        let v = u64::from_be_bytes(bytes.try_into().unwrap());
-        visitor.visit_u64(v)
+        visitor.visit_u64(u64::from_be_bytes(self.take_array()?))
     }
 
+    // 最高位为 1 说明原来是正数，只需要翻转符号位，否则翻转全部的位
     fn deserialize_f32<V>(self, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        todo!()
+        let bits = u32::from_be_bytes(self.take_array()?);
+        let bits = match bits >> 31 {
+            1 => bits ^ (1 << 31),
+            _ => !bits,
+        };
+        visitor.visit_f32(f32::from_bits(bits))
     }
 
     fn deserialize_f64<V>(self, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        todo!()
+        let bits = u64::from_be_bytes(self.take_array()?);
+        let bits = match bits >> 63 {
+            1 => bits ^ (1 << 63),
+            _ => !bits,
+        };
+        visitor.visit_f64(f64::from_bits(bits))
     }
 
     fn deserialize_char<V>(self, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        todo!()
+        let v = u32::from_be_bytes(self.take_array()?);
+        match char::from_u32(v) {
+            Some(c) => visitor.visit_char(c),
+            None => Err(Error::Internal(format!("invalid char value {}", v))),
+        }
     }
 
     fn deserialize_str<V>(self, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        todo!()
+        visitor.visit_str(&self.next_string()?)
     }
 
     fn deserialize_string<V>(self, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        todo!()
+        visitor.visit_string(self.next_string()?)
     }
 
     fn deserialize_bytes<V>(self, visitor: V) -> Result<V::Value>
@@ -423,38 +612,42 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
     where
         V: de::Visitor<'de>,
     {
-        todo!()
+        match self.take_u8()? {
+            0 => visitor.visit_none(),
+            1 => visitor.visit_some(self),
+            b => Err(Error::Internal(format!("invalid option marker {}", b))),
+        }
     }
 
     fn deserialize_unit<V>(self, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        todo!()
+        visitor.visit_unit()
     }
 
-    fn deserialize_unit_struct<V>(self, name: &'static str, visitor: V) -> Result<V::Value>
+    fn deserialize_unit_struct<V>(self, _name: &'static str, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        todo!()
+        visitor.visit_unit()
     }
 
-    fn deserialize_newtype_struct<V>(self, name: &'static str, visitor: V) -> Result<V::Value>
+    fn deserialize_newtype_struct<V>(self, _name: &'static str, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        todo!()
+        visitor.visit_newtype_struct(self)
     }
 
     fn deserialize_seq<V>(self, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        visitor.visit_seq(self)
+        visitor.visit_seq(Elements { de: self })
     }
 
-    fn deserialize_tuple<V>(self, len: usize, visitor: V) -> Result<V::Value>
+    fn deserialize_tuple<V>(self, _len: usize, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
@@ -463,39 +656,40 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
 
     fn deserialize_tuple_struct<V>(
         self,
-        name: &'static str,
-        len: usize,
+        _name: &'static str,
+        _len: usize,
         visitor: V,
     ) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        todo!()
+        visitor.visit_seq(self)
     }
 
     fn deserialize_map<V>(self, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        todo!()
+        visitor.visit_map(Elements { de: self })
     }
 
+    // 字段按照定义的顺序编码，当作 tuple 解析
     fn deserialize_struct<V>(
         self,
-        name: &'static str,
-        fields: &'static [&'static str],
+        _name: &'static str,
+        _fields: &'static [&'static str],
         visitor: V,
     ) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        todo!()
+        visitor.visit_seq(self)
     }
 
     fn deserialize_enum<V>(
         self,
-        name: &'static str,
-        variants: &'static [&'static str],
+        _name: &'static str,
+        _variants: &'static [&'static str],
         visitor: V,
     ) -> Result<V::Value>
     where
@@ -504,22 +698,27 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
         visitor.visit_enum(self)
     }
 
-    fn deserialize_identifier<V>(self, visitor: V) -> Result<V::Value>
+    fn deserialize_identifier<V>(self, _visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        todo!()
+        Err(Error::Internal(
+            "keycode does not support deserialize_identifier".into(),
+        ))
     }
 
-    fn deserialize_ignored_any<V>(self, visitor: V) -> Result<V::Value>
+    fn deserialize_ignored_any<V>(self, _visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        todo!()
+        Err(Error::Internal(
+            "keycode does not support deserialize_ignored_any".into(),
+        ))
     }
 }
 
-impl<'de, 'a> de::SeqAccess<'de> for Deserializer<'de> {
+// tuple / struct 的字段个数是固定的，由 visitor 决定读取多少个
+impl<'de> de::SeqAccess<'de> for Deserializer<'de> {
     type Error = Error;
 
     fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>>
@@ -530,7 +729,47 @@ impl<'de, 'a> de::SeqAccess<'de> for Deserializer<'de> {
     }
 }
 
-impl<'de, 'a> de::EnumAccess<'de> for &mut Deserializer<'de> {
+// 长度不固定的 seq / map，根据每个元素前面的标记判断是否结束
+struct Elements<'a, 'de: 'a> {
+    de: &'a mut Deserializer<'de>,
+}
+
+impl<'de, 'a> de::SeqAccess<'de> for Elements<'a, 'de> {
+    type Error = Error;
+
+    fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>>
+    where
+        T: de::DeserializeSeed<'de>,
+    {
+        if !self.de.has_next()? {
+            return Ok(None);
+        }
+        seed.deserialize(&mut *self.de).map(Some)
+    }
+}
+
+impl<'de, 'a> de::MapAccess<'de> for Elements<'a, 'de> {
+    type Error = Error;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>>
+    where
+        K: de::DeserializeSeed<'de>,
+    {
+        if !self.de.has_next()? {
+            return Ok(None);
+        }
+        seed.deserialize(&mut *self.de).map(Some)
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value>
+    where
+        V: de::DeserializeSeed<'de>,
+    {
+        seed.deserialize(&mut *self.de)
+    }
+}
+
+impl<'de> de::EnumAccess<'de> for &mut Deserializer<'de> {
     type Error = Error;
 
     type Variant = Self;
@@ -539,13 +778,13 @@ impl<'de, 'a> de::EnumAccess<'de> for &mut Deserializer<'de> {
     where
         V: de::DeserializeSeed<'de>,
     {
-        let index = self.take_bytes(1)[0] as u32;
+        let index = self.take_u8()? as u32;
         let varint_index: Result<_> = seed.deserialize(index.into_deserializer());
         Ok((varint_index?, self))
     }
 }
 
-impl<'de, 'a> de::VariantAccess<'de> for &mut Deserializer<'de> {
+impl<'de> de::VariantAccess<'de> for &mut Deserializer<'de> {
     type Error = Error;
 
     fn unit_variant(self) -> Result<()> {
@@ -559,26 +798,34 @@ impl<'de, 'a> de::VariantAccess<'de> for &mut Deserializer<'de> {
         seed.deserialize(&mut *self)
     }
 
-    fn tuple_variant<V>(self, len: usize, visitor: V) -> Result<V::Value>
+    fn tuple_variant<V>(self, _len: usize, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
         visitor.visit_seq(self)
     }
 
-    fn struct_variant<V>(self, fields: &'static [&'static str], visitor: V) -> Result<V::Value>
+    fn struct_variant<V>(self, _fields: &'static [&'static str], visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        todo!()
+        visitor.visit_seq(self)
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::storage::{
-        keycode::{deserialize_key, serialize_key},
-        mvcc::{MvccKey, MvccKeyPrefix},
+    use std::{cmp::Ordering, collections::BTreeMap, fmt::Debug};
+
+    use proptest::prelude::*;
+    use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+    use crate::{
+        sql::types::Value,
+        storage::{
+            keycode::{deserialize_key, serialize_key},
+            mvcc::{MvccKey, MvccKeyPrefix},
+        },
     };
 
     #[test]
@@ -643,4 +890,241 @@ mod tests {
     //     let vvv: Vec<u8> = vv.try_into().unwrap();
     //     println!("{:?}", vvv);
     // }
+
+    // 同一类型的 Value，编码后的字节顺序和原始值的顺序一致
+    fn assert_order(a: Value, b: Value) {
+        let ea = serialize_key(&a).unwrap();
+        let eb = serialize_key(&b).unwrap();
+        match a.compare(&b) {
+            Some(Ordering::Equal) => {}
+            Some(ord) => assert_eq!(ea.cmp(&eb), ord, "{:?} {:?}", a, b),
+            None => {}
+        }
+    }
+
+    #[test]
+    fn test_encode_value_order() {
+        assert_order(Value::Integer(-1), Value::Integer(0));
+        assert_order(Value::Integer(i64::MIN), Value::Integer(i64::MAX));
+        assert_order(Value::Float(-1.5), Value::Float(-0.5));
+        assert_order(Value::Float(f64::NEG_INFINITY), Value::Float(-0.0));
+        assert_order(Value::Float(0.5), Value::Float(f64::INFINITY));
+        assert_order(Value::String("a".into()), Value::String("a\0".into()));
+        assert_order(Value::String("a\0".into()), Value::String("aa".into()));
+        assert_order(Value::Boolean(false), Value::Boolean(true));
+        // Null 排在其他值前面
+        assert!(
+            serialize_key(&Value::Null).unwrap() < serialize_key(&Value::Boolean(false)).unwrap()
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn proptest_encode_integer_order(a: i64, b: i64) {
+            assert_order(Value::Integer(a), Value::Integer(b));
+        }
+
+        #[test]
+        fn proptest_encode_float_order(a: f64, b: f64) {
+            assert_order(Value::Float(a), Value::Float(b));
+        }
+
+        #[test]
+        fn proptest_encode_string_order(a: String, b: String) {
+            assert_order(Value::String(a), Value::String(b));
+        }
+
+        #[test]
+        fn proptest_encode_bool_order(a: bool, b: bool) {
+            assert_order(Value::Boolean(a), Value::Boolean(b));
+        }
+    }
+
+    #[derive(Debug, Clone, PartialEq, PartialOrd, Serialize, Deserialize)]
+    struct Id(i64);
+
+    #[derive(Debug, Clone, PartialEq, PartialOrd, Serialize, Deserialize)]
+    struct Pair(String, Option<u16>);
+
+    #[derive(Debug, Clone, PartialEq, PartialOrd, Serialize, Deserialize)]
+    enum Kind {
+        Empty,
+        Number(i32),
+        Point { x: i8, y: f32 },
+    }
+
+    #[derive(Debug, Clone, PartialEq, PartialOrd, Serialize, Deserialize)]
+    struct Record {
+        id: Id,
+        pair: Pair,
+        kind: Kind,
+        flag: bool,
+        c: char,
+        n: u8,
+        big: u64,
+        f: f64,
+        list: Vec<u32>,
+    }
+
+    // -0.0 和 0.0 相等但编码不同，加上 0.0 统一为 0.0，避免影响后面字段的比较
+    fn kind() -> impl Strategy<Value = Kind> {
+        prop_oneof![
+            Just(Kind::Empty),
+            any::<i32>().prop_map(Kind::Number),
+            (any::<i8>(), any::<f32>()).prop_map(|(x, y)| Kind::Point { x, y: y + 0.0 }),
+        ]
+    }
+
+    fn record() -> impl Strategy<Value = Record> {
+        (
+            any::<i64>(),
+            any::<String>(),
+            any::<Option<u16>>(),
+            kind(),
+            any::<bool>(),
+            any::<char>(),
+            any::<u8>(),
+            any::<u64>(),
+            any::<f64>(),
+            any::<Vec<u32>>(),
+        )
+            .prop_map(|(id, s, o, kind, flag, c, n, big, f, list)| Record {
+                id: Id(id),
+                pair: Pair(s, o),
+                kind,
+                flag,
+                c,
+                n,
+                big,
+                f: f + 0.0,
+                list,
+            })
+    }
+
+    fn assert_round_trip<T: Serialize + DeserializeOwned + PartialEq + Debug>(v: T) {
+        let encoded = serialize_key(&v).unwrap();
+        assert_eq!(deserialize_key::<T>(&encoded).unwrap(), v);
+    }
+
+    // 编码后的字节顺序和 PartialOrd 的顺序一致
+    fn assert_encoded_order<T: Serialize + PartialOrd + Debug>(a: T, b: T) {
+        let ea = serialize_key(&a).unwrap();
+        let eb = serialize_key(&b).unwrap();
+        if let Some(ord) = a.partial_cmp(&b).filter(|ord| ord.is_ne()) {
+            assert_eq!(ea.cmp(&eb), ord, "{:?} {:?}", a, b);
+        }
+    }
+
+    #[test]
+    fn test_decode_errors() {
+        // 输入不完整
+        assert!(deserialize_key::<i64>(&[0x80, 0]).is_err());
+        assert!(deserialize_key::<String>(b"abc").is_err());
+        assert!(deserialize_key::<String>(&[97, 0]).is_err());
+        // 非法的值
+        assert!(deserialize_key::<bool>(&[2]).is_err());
+        assert!(deserialize_key::<Option<u8>>(&[2, 1]).is_err());
+        assert!(deserialize_key::<char>(&[0, 0, 0xd8, 0]).is_err());
+        assert!(deserialize_key::<String>(&[0xff, 0, 0]).is_err());
+        assert!(deserialize_key::<String>(&[0, 1]).is_err());
+        assert!(deserialize_key::<Vec<u8>>(&[1, 7, 2]).is_err());
+        // 多余的输入
+        assert!(deserialize_key::<u8>(&[1, 2]).is_err());
+        // 枚举的变体不存在
+        assert!(deserialize_key::<MvccKey>(&[9]).is_err());
+        assert!(deserialize_key::<Kind>(&[5]).is_err());
+    }
+
+    #[test]
+    fn test_encode_compound() {
+        assert_eq!(
+            serialize_key(&Id(1)).unwrap(),
+            serialize_key(&1i64).unwrap()
+        );
+        assert_eq!(serialize_key(&vec![7u8, 8]).unwrap(), vec![1, 7, 1, 8, 0]);
+        assert_eq!(
+            serialize_key(&Kind::Point { x: -1, y: 0.0 }).unwrap(),
+            vec![2, 0x7f, 0x80, 0, 0, 0]
+        );
+        let map = BTreeMap::from([(1u8, "a".to_string()), (2, "b".to_string())]);
+        assert_round_trip(map);
+        assert_round_trip(Kind::Empty);
+        assert_round_trip(Some(()));
+        assert_round_trip(f64::NEG_INFINITY);
+    }
+
+    proptest! {
+        #[test]
+        fn proptest_round_trip(v in record()) {
+            let encoded = serialize_key(&v).unwrap();
+            let decoded = deserialize_key::<Record>(&encoded).unwrap();
+            // NaN 不等于自身，比较编码后的结果
+            prop_assert_eq!(serialize_key(&decoded).unwrap(), encoded);
+        }
+
+        #[test]
+        fn proptest_round_trip_primitives(
+            a: (i8, i16, i32, i64),
+            b: (u8, u16, u32, u64),
+            c: (bool, char, String, Option<i32>),
+            d: (Vec<i16>, Vec<String>, Option<Option<bool>>),
+        ) {
+            assert_round_trip(a);
+            assert_round_trip(b);
+            assert_round_trip(c);
+            assert_round_trip(d);
+        }
+
+        #[test]
+        fn proptest_round_trip_value(v: (i64, f64, String, bool)) {
+            let values = vec![
+                Value::Null,
+                Value::Integer(v.0),
+                Value::Float(v.1),
+                Value::String(v.2),
+                Value::Boolean(v.3),
+            ];
+            for value in values {
+                let encoded = serialize_key(&value).unwrap();
+                let decoded = deserialize_key::<Value>(&encoded).unwrap();
+                prop_assert_eq!(serialize_key(&decoded).unwrap(), encoded);
+            }
+        }
+
+        #[test]
+        fn proptest_encode_order(a in record(), b in record()) {
+            assert_encoded_order(a, b);
+        }
+
+        #[test]
+        fn proptest_encode_order_primitives(
+            a: (i8, i16, i32, u16, u32),
+            b: (i8, i16, i32, u16, u32),
+            c: (char, Option<i64>, f32),
+            d: (char, Option<i64>, f32),
+            e: (Vec<i8>, String),
+            f: (Vec<i8>, String),
+        ) {
+            assert_encoded_order(a, b);
+            assert_encoded_order(c, d);
+            assert_encoded_order(e, f);
+        }
+
+        // 非法的输入返回错误，不会 panic
+        #[test]
+        fn proptest_decode_no_panic(input: Vec<u8>) {
+            let _ = deserialize_key::<Record>(&input);
+            let _ = deserialize_key::<MvccKey>(&input);
+            let _ = deserialize_key::<Value>(&input);
+        }
+
+        // 截断的输入都返回错误
+        #[test]
+        fn proptest_decode_truncated(v in record()) {
+            let encoded = serialize_key(&v).unwrap();
+            for len in 0..encoded.len() {
+                prop_assert!(deserialize_key::<Record>(&encoded[..len]).is_err());
+            }
+        }
+    }
 }
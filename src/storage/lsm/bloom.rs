@@ -0,0 +1,87 @@
+// 布隆过滤器，用于快速判断一个 key 是否不在 SSTable 中
+// 使用 FNV-1a 计算 hash，保证写入磁盘的数据在不同的版本中都能正确读取
+pub struct Bloom {
+    bits: Vec<u8>,
+    // hash 函数的个数
+    k: u8,
+}
+
+impl Bloom {
+    // 根据 key 的数量和每个 key 占用的 bit 数创建
+    pub fn build(keys: &[Vec<u8>], bits_per_key: usize) -> Self {
+        // k = ln2 * bits_per_key
+        let k = ((bits_per_key as f64 * 0.69) as u8).clamp(1, 30);
+        let nbits = (keys.len() * bits_per_key).max(64);
+        let mut bloom = Self {
+            bits: vec![0; nbits.div_ceil(8)],
+            k,
+        };
+        for key in keys {
+            for bit in bloom.bit_positions(key) {
+                bloom.bits[bit / 8] |= 1 << (bit % 8);
+            }
+        }
+        bloom
+    }
+
+    // 可能存在返回 true，一定不存在返回 false
+    pub fn may_contain(&self, key: &[u8]) -> bool {
+        self.bit_positions(key)
+            .all(|bit| self.bits[bit / 8] & (1 << (bit % 8)) != 0)
+    }
+
+    // +------------+--------+
+    // | bits(n)    | k(1)   |
+    // +------------+--------+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = self.bits.clone();
+        buf.push(self.k);
+        buf
+    }
+
+    pub fn decode(mut buf: Vec<u8>) -> Option<Self> {
+        let k = buf.pop()?;
+        if buf.is_empty() {
+            return None;
+        }
+        Some(Self { bits: buf, k })
+    }
+
+    // 通过两个 hash 值模拟 k 个 hash 函数
+    fn bit_positions(&self, key: &[u8]) -> impl Iterator<Item = usize> {
+        let hash = fnv1a(key);
+        let (h1, h2) = (hash as u32 as u64, (hash >> 32) | 1);
+        let nbits = (self.bits.len() * 8) as u64;
+        (0..self.k as u64).map(move |i| (h1.wrapping_add(i.wrapping_mul(h2)) % nbits) as usize)
+    }
+}
+
+fn fnv1a(data: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for b in data {
+        hash ^= *b as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Bloom;
+
+    #[test]
+    fn test_bloom() {
+        let keys = (0..1000)
+            .map(|i| format!("key{}", i).into_bytes())
+            .collect::<Vec<_>>();
+        let bloom = Bloom::build(&keys, 10);
+        let bloom = Bloom::decode(bloom.encode()).unwrap();
+        assert!(keys.iter().all(|key| bloom.may_contain(key)));
+
+        // 误判率大约为 1%
+        let false_positives = (1000..11000)
+            .filter(|i| bloom.may_contain(format!("key{}", i).as_bytes()))
+            .count();
+        assert!(false_positives < 300);
+    }
+}
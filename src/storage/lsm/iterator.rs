@@ -0,0 +1,172 @@
+use crate::error::Result;
+
+use super::Entry;
+
+pub type EntryIterator<'a> = Box<dyn DoubleEndedIterator<Item = Result<Entry>> + 'a>;
+
+// 多路归并迭代器，可以从两端读取
+// 多个数据源中有相同的 key 时，使用排在前面的数据源中的数据
+pub struct MergeIterator<'a> {
+    sources: Vec<Source<'a>>,
+}
+
+impl<'a> MergeIterator<'a> {
+    // sources 按照数据从新到旧排列
+    pub fn new(sources: Vec<EntryIterator<'a>>) -> Self {
+        Self {
+            sources: sources
+                .into_iter()
+                .map(|iter| Source {
+                    iter,
+                    front: None,
+                    back: None,
+                })
+                .collect(),
+        }
+    }
+
+    // 找到所有数据源中最小（front 为 true）或者最大的 key，并从全部的数据源中取出
+    fn take(&mut self, front: bool) -> Option<Result<Entry>> {
+        for source in self.sources.iter_mut() {
+            if let Err(err) = source.peek(front) {
+                return Some(Err(err));
+            }
+        }
+
+        let mut selected: Option<(usize, &Vec<u8>)> = None;
+        for (i, source) in self.sources.iter().enumerate() {
+            let key = match source.peeked(front) {
+                Some(key) => key,
+                None => continue,
+            };
+            let better = match selected {
+                None => true,
+                Some((_, current)) if front => key < current,
+                Some((_, current)) => key > current,
+            };
+            if better {
+                selected = Some((i, key));
+            }
+        }
+
+        let (i, _) = selected?;
+        let entry = self.sources[i].pop(front);
+        // 旧的数据源中相同的 key 已经被覆盖
+        for source in self.sources.iter_mut() {
+            if source.peeked(front) == Some(&entry.0) {
+                source.pop(front);
+            }
+        }
+        Some(Ok(entry))
+    }
+}
+
+impl<'a> Iterator for MergeIterator<'a> {
+    type Item = Result<Entry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.take(true)
+    }
+}
+
+impl<'a> DoubleEndedIterator for MergeIterator<'a> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.take(false)
+    }
+}
+
+// 一个数据源，分别缓存从两端读取到的下一条记录
+struct Source<'a> {
+    iter: EntryIterator<'a>,
+    front: Option<Entry>,
+    back: Option<Entry>,
+}
+
+impl<'a> Source<'a> {
+    // 从一端读取下一条记录缓存起来
+    fn peek(&mut self, front: bool) -> Result<()> {
+        let (this, other) = match front {
+            true => (&mut self.front, &mut self.back),
+            false => (&mut self.back, &mut self.front),
+        };
+        if this.is_none() {
+            let next = match front {
+                true => self.iter.next(),
+                false => self.iter.next_back(),
+            };
+            // 数据源已经读完，剩下的只有另一端缓存的记录
+            *this = match next.transpose()? {
+                Some(entry) => Some(entry),
+                None => other.take(),
+            };
+        }
+        Ok(())
+    }
+
+    fn peeked(&self, front: bool) -> Option<&Vec<u8>> {
+        let entry = match front {
+            true => &self.front,
+            false => &self.back,
+        };
+        entry.as_ref().map(|(key, _)| key)
+    }
+
+    fn pop(&mut self, front: bool) -> Entry {
+        match front {
+            true => self.front.take().unwrap(),
+            false => self.back.take().unwrap(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{EntryIterator, MergeIterator};
+    use crate::error::Result;
+
+    fn source(entries: &[(&str, Option<&str>)]) -> EntryIterator<'static> {
+        let entries = entries
+            .iter()
+            .map(|(k, v)| Ok((k.as_bytes().to_vec(), v.map(|v| v.as_bytes().to_vec()))))
+            .collect::<Vec<_>>();
+        Box::new(entries.into_iter())
+    }
+
+    #[test]
+    fn test_merge_iterator() -> Result<()> {
+        let sources = || {
+            vec![
+                source(&[("b", Some("b2")), ("d", None)]),
+                source(&[("a", Some("a1")), ("b", Some("b1")), ("d", Some("d1"))]),
+                source(&[("c", Some("c0")), ("e", Some("e0"))]),
+            ]
+        };
+        let expected = vec![
+            (b"a".to_vec(), Some(b"a1".to_vec())),
+            (b"b".to_vec(), Some(b"b2".to_vec())),
+            (b"c".to_vec(), Some(b"c0".to_vec())),
+            (b"d".to_vec(), None),
+            (b"e".to_vec(), Some(b"e0".to_vec())),
+        ];
+
+        let entries = MergeIterator::new(sources()).collect::<Result<Vec<_>>>()?;
+        assert_eq!(entries, expected);
+
+        let mut entries = MergeIterator::new(sources())
+            .rev()
+            .collect::<Result<Vec<_>>>()?;
+        entries.reverse();
+        assert_eq!(entries, expected);
+
+        // 从两端交替读取
+        let mut iter = MergeIterator::new(sources());
+        assert_eq!(iter.next().transpose()?, Some(expected[0].clone()));
+        assert_eq!(iter.next_back().transpose()?, Some(expected[4].clone()));
+        assert_eq!(iter.next_back().transpose()?, Some(expected[3].clone()));
+        assert_eq!(iter.next().transpose()?, Some(expected[1].clone()));
+        assert_eq!(iter.next().transpose()?, Some(expected[2].clone()));
+        assert_eq!(iter.next().transpose()?, None);
+        assert_eq!(iter.next_back().transpose()?, None);
+        Ok(())
+    }
+}
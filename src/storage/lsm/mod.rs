@@ -0,0 +1,568 @@
+use std::{
+    collections::BTreeMap,
+    fs::{File, OpenOptions},
+    ops::{Bound, RangeBounds},
+    path::{Path, PathBuf},
+};
+
+use fs4::FileExt;
+use iterator::{EntryIterator, MergeIterator};
+use serde::{Deserialize, Serialize};
+use sstable::{SsTable, SsTableWriter};
+use wal::Wal;
+
+use crate::error::Result;
+
+use super::engine::{Engine, EngineIterator};
+
+mod bloom;
+mod iterator;
+mod sstable;
+mod wal;
+
+// key 和 value，value 为 None 表示删除标记
+pub type Entry = (Vec<u8>, Option<Vec<u8>>);
+// 闭区间 [start, end]
+pub type KeyRange = (Vec<u8>, Vec<u8>);
+
+const LOCK_FILE: &str = "LOCK";
+const WAL_FILE: &str = "wal.log";
+const MANIFEST_FILE: &str = "MANIFEST";
+const MANIFEST_TMP_FILE: &str = "MANIFEST.tmp";
+const SSTABLE_EXT: &str = "sst";
+
+// LSM 存储引擎的配置
+#[derive(Debug, Clone)]
+pub struct LsmOptions {
+    // memtable 超过这个大小后写入 SSTable
+    pub memtable_max_bytes: usize,
+    // SSTable 中 data block 的大小
+    pub block_size: usize,
+    // 布隆过滤器中每个 key 占用的 bit 数
+    pub bloom_bits_per_key: usize,
+    // 合并时生成的单个 SSTable 的大小上限
+    pub sstable_max_bytes: u64,
+    // level 0 的 SSTable 数量超过这个值后合并到 level 1
+    pub level0_max_files: usize,
+    // level 1 的大小上限，之后每一层是上一层的 level_size_multiplier 倍
+    pub level1_max_bytes: u64,
+    pub level_size_multiplier: u64,
+}
+
+impl Default for LsmOptions {
+    fn default() -> Self {
+        Self {
+            memtable_max_bytes: 4 * 1024 * 1024,
+            block_size: 4 * 1024,
+            bloom_bits_per_key: 10,
+            sstable_max_bytes: 2 * 1024 * 1024,
+            level0_max_files: 4,
+            level1_max_bytes: 10 * 1024 * 1024,
+            level_size_multiplier: 10,
+        }
+    }
+}
+
+// 记录每一层有哪些 SSTable，替换时先写临时文件再改名
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Manifest {
+    next_id: u64,
+    levels: Vec<Vec<u64>>,
+}
+
+// LSM-tree 存储引擎
+// 写入先追加到预写日志，再写入内存中的 memtable，memtable 写满之后生成 level 0 的 SSTable
+// level 0 的 SSTable 之间 key 的范围可能重叠，其他层的 SSTable 之间是有序且不重叠的
+pub struct LsmEngine {
+    dir: PathBuf,
+    // 持有目录锁
+    _lock: File,
+    options: LsmOptions,
+    wal: Wal,
+    memtable: BTreeMap<Vec<u8>, Option<Vec<u8>>>,
+    memtable_size: usize,
+    // level 0 按照生成的顺序排列，其他层按照 key 的范围排列
+    levels: Vec<Vec<SsTable>>,
+    next_id: u64,
+}
+
+impl LsmEngine {
+    pub fn new(dir: PathBuf) -> Result<Self> {
+        Self::with_options(dir, LsmOptions::default())
+    }
+
+    pub fn with_options(dir: PathBuf, options: LsmOptions) -> Result<Self> {
+        std::fs::create_dir_all(&dir)?;
+        let lock = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(dir.join(LOCK_FILE))?;
+        lock.try_lock_exclusive()?;
+
+        // 加载 manifest 中记录的 SSTable
+        let manifest = match std::fs::read(dir.join(MANIFEST_FILE)) {
+            Ok(data) => bincode::deserialize(&data)?,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Manifest {
+                next_id: 1,
+                levels: Vec::new(),
+            },
+            Err(err) => return Err(err.into()),
+        };
+        let mut levels = Vec::new();
+        for ids in manifest.levels.iter() {
+            let mut tables = Vec::new();
+            for id in ids {
+                tables.push(SsTable::open(*id, sstable_path(&dir, *id))?);
+            }
+            levels.push(tables);
+        }
+        // 删除没有记录在 manifest 中的 SSTable，它们是崩溃前没有完成的合并留下的
+        for entry in std::fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some(SSTABLE_EXT) {
+                continue;
+            }
+            let id = path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .and_then(|stem| stem.parse::<u64>().ok());
+            if !manifest.levels.iter().flatten().any(|t| Some(*t) == id) {
+                std::fs::remove_file(path)?;
+            }
+        }
+
+        // 从预写日志中恢复 memtable
+        let (wal, entries) = Wal::open(dir.join(WAL_FILE))?;
+        let mut eng = Self {
+            dir,
+            _lock: lock,
+            options,
+            wal,
+            memtable: BTreeMap::new(),
+            memtable_size: 0,
+            levels,
+            next_id: manifest.next_id,
+        };
+        for (key, value) in entries {
+            eng.memtable_size += key.len() + value.as_ref().map_or(0, |v| v.len());
+            eng.memtable.insert(key, value);
+        }
+        Ok(eng)
+    }
+
+    // 写入 memtable，超过大小上限后生成 SSTable
+    fn write(&mut self, key: Vec<u8>, value: Option<Vec<u8>>) -> Result<()> {
+        self.wal.append(&key, value.as_deref())?;
+        self.memtable_size += key.len() + value.as_ref().map_or(0, |v| v.len());
+        self.memtable.insert(key, value);
+        if self.memtable_size >= self.options.memtable_max_bytes {
+            self.flush_memtable()?;
+            self.maybe_compact()?;
+        }
+        Ok(())
+    }
+
+    // 将 memtable 写入 level 0 的 SSTable，然后清空预写日志
+    fn flush_memtable(&mut self) -> Result<()> {
+        if self.memtable.is_empty() {
+            return Ok(());
+        }
+        let id = self.next_sstable_id();
+        let mut writer = SsTableWriter::new(
+            sstable_path(&self.dir, id),
+            self.options.block_size,
+            self.options.bloom_bits_per_key,
+        )?;
+        for (key, value) in self.memtable.iter() {
+            writer.add(key, value.as_deref())?;
+        }
+        let table = writer.finish(id)?;
+        if self.levels.is_empty() {
+            self.levels.push(Vec::new());
+        }
+        self.levels[0].push(table);
+        self.write_manifest()?;
+
+        self.wal.reset()?;
+        self.memtable.clear();
+        self.memtable_size = 0;
+        Ok(())
+    }
+
+    // 某一层超过大小上限之后，合并到下一层，直到每一层都满足要求
+    fn maybe_compact(&mut self) -> Result<()> {
+        loop {
+            let level = (0..self.levels.len()).find(|level| match level {
+                0 => self.levels[0].len() > self.options.level0_max_files,
+                _ => {
+                    let size: u64 = self.levels[*level].iter().map(|t| t.size()).sum();
+                    size > self.level_max_bytes(*level)
+                }
+            });
+            match level {
+                Some(level) => self.compact_level(level)?,
+                None => return Ok(()),
+            }
+        }
+    }
+
+    fn level_max_bytes(&self, level: usize) -> u64 {
+        self.options.level1_max_bytes
+            * self
+                .options
+                .level_size_multiplier
+                .pow(level.saturating_sub(1) as u32)
+    }
+
+    // 将 level 层的 SSTable 和下一层中 key 范围重叠的 SSTable 合并，写入到下一层
+    // level 0 合并全部的 SSTable，其他层每次合并一个
+    fn compact_level(&mut self, level: usize) -> Result<()> {
+        if self.levels.len() == level + 1 {
+            self.levels.push(Vec::new());
+        }
+        let inputs: Vec<usize> = match level {
+            0 => (0..self.levels[0].len()).collect(),
+            _ => vec![0],
+        };
+        let range = key_range(inputs.iter().map(|i| &self.levels[level][*i]));
+        let overlaps: Vec<usize> = (0..self.levels[level + 1].len())
+            .filter(|i| self.levels[level + 1][*i].overlaps(&range))
+            .collect();
+        // 更下层没有数据时，删除标记不需要保留
+        let drop_tombstones = self.levels[level + 2..].iter().all(|l| l.is_empty());
+
+        // 新的数据排在前面，level 0 中后生成的 SSTable 更新
+        let mut sources: Vec<EntryIterator> = Vec::new();
+        for i in inputs.iter().rev() {
+            sources.push(Box::new(self.levels[level][*i].scan(unbounded())));
+        }
+        for i in overlaps.iter() {
+            sources.push(Box::new(self.levels[level + 1][*i].scan(unbounded())));
+        }
+        let merged = MergeIterator::new(sources);
+
+        let mut outputs = Vec::new();
+        let mut writer: Option<SsTableWriter> = None;
+        let mut next_id = self.next_id;
+        for entry in merged {
+            let (key, value) = entry?;
+            if value.is_none() && drop_tombstones {
+                continue;
+            }
+            let w = match writer.as_mut() {
+                Some(w) => w,
+                None => {
+                    let path = sstable_path(&self.dir, next_id);
+                    writer.insert(SsTableWriter::new(
+                        path,
+                        self.options.block_size,
+                        self.options.bloom_bits_per_key,
+                    )?)
+                }
+            };
+            w.add(&key, value.as_deref())?;
+            if w.size() >= self.options.sstable_max_bytes {
+                outputs.push(writer.take().unwrap().finish(next_id)?);
+                next_id += 1;
+            }
+        }
+        if let Some(w) = writer.take() {
+            outputs.push(w.finish(next_id)?);
+            next_id += 1;
+        }
+        self.next_id = next_id;
+
+        // 替换掉被合并的 SSTable
+        let mut removed = Vec::new();
+        for i in inputs.into_iter().rev() {
+            removed.push(self.levels[level].remove(i));
+        }
+        for i in overlaps.into_iter().rev() {
+            removed.push(self.levels[level + 1].remove(i));
+        }
+        self.levels[level + 1].extend(outputs);
+        self.levels[level + 1].sort_by(|a, b| a.first_key().cmp(b.first_key()));
+        self.write_manifest()?;
+
+        for table in removed {
+            std::fs::remove_file(table.path())?;
+        }
+        Ok(())
+    }
+
+    fn next_sstable_id(&mut self) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        id
+    }
+
+    fn write_manifest(&self) -> Result<()> {
+        let manifest = Manifest {
+            next_id: self.next_id,
+            levels: self
+                .levels
+                .iter()
+                .map(|tables| tables.iter().map(|t| t.id).collect())
+                .collect(),
+        };
+        let tmp_path = self.dir.join(MANIFEST_TMP_FILE);
+        std::fs::write(&tmp_path, bincode::serialize(&manifest)?)?;
+        File::open(&tmp_path)?.sync_all()?;
+        std::fs::rename(tmp_path, self.dir.join(MANIFEST_FILE))?;
+        File::open(&self.dir)?.sync_all()?;
+        Ok(())
+    }
+}
+
+// SSTable 文件的路径
+fn sstable_path(dir: &Path, id: u64) -> PathBuf {
+    dir.join(format!("{:08}.{}", id, SSTABLE_EXT))
+}
+
+fn unbounded() -> (Bound<Vec<u8>>, Bound<Vec<u8>>) {
+    (Bound::Unbounded, Bound::Unbounded)
+}
+
+// 多个 SSTable 合在一起的 key 的范围
+fn key_range<'a>(tables: impl Iterator<Item = &'a SsTable>) -> KeyRange {
+    let mut range: Option<KeyRange> = None;
+    for table in tables {
+        range = Some(match range {
+            None => (table.first_key().to_vec(), table.last_key().to_vec()),
+            Some((start, end)) => (
+                start.min(table.first_key().to_vec()),
+                end.max(table.last_key().to_vec()),
+            ),
+        });
+    }
+    range.unwrap_or_default()
+}
+
+impl Engine for LsmEngine {
+    type EngineIterator<'a> = LsmEngineIterator<'a>;
+
+    fn set(&mut self, key: Vec<u8>, value: Vec<u8>) -> Result<()> {
+        self.write(key, Some(value))
+    }
+
+    fn get(&mut self, key: Vec<u8>) -> Result<Option<Vec<u8>>> {
+        if let Some(value) = self.memtable.get(&key) {
+            return Ok(value.clone());
+        }
+        // level 0 中后生成的 SSTable 更新
+        if let Some(level0) = self.levels.first() {
+            for table in level0.iter().rev() {
+                if let Some(value) = table.get(&key)? {
+                    return Ok(value);
+                }
+            }
+        }
+        for tables in self.levels.iter().skip(1) {
+            let i = tables.partition_point(|t| t.last_key() < key.as_slice());
+            if let Some(table) = tables.get(i) {
+                if table.first_key() <= key.as_slice() {
+                    if let Some(value) = table.get(&key)? {
+                        return Ok(value);
+                    }
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    fn delete(&mut self, key: Vec<u8>) -> Result<()> {
+        self.write(key, None)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.wal.sync()
+    }
+
+    fn scan(&mut self, range: impl RangeBounds<Vec<u8>>) -> Self::EngineIterator<'_> {
+        let range = (range.start_bound().cloned(), range.end_bound().cloned());
+        let mut sources: Vec<EntryIterator> = vec![Box::new(
+            self.memtable
+                .range(range.clone())
+                .map(|(k, v)| Ok((k.clone(), v.clone()))),
+        )];
+        for (level, tables) in self.levels.iter().enumerate() {
+            let tables: Box<dyn Iterator<Item = &SsTable>> = match level {
+                0 => Box::new(tables.iter().rev()),
+                _ => Box::new(tables.iter()),
+            };
+            for table in tables {
+                if table_in_range(table, &range) {
+                    sources.push(Box::new(table.scan(range.clone())));
+                }
+            }
+        }
+        LsmEngineIterator {
+            inner: MergeIterator::new(sources),
+        }
+    }
+}
+
+// SSTable 中是否可能有范围内的数据
+fn table_in_range(table: &SsTable, (start, end): &(Bound<Vec<u8>>, Bound<Vec<u8>>)) -> bool {
+    let after_start = match start {
+        Bound::Included(start) => table.last_key() >= start.as_slice(),
+        Bound::Excluded(start) => table.last_key() > start.as_slice(),
+        Bound::Unbounded => true,
+    };
+    let before_end = match end {
+        Bound::Included(end) => table.first_key() <= end.as_slice(),
+        Bound::Excluded(end) => table.first_key() < end.as_slice(),
+        Bound::Unbounded => true,
+    };
+    after_start && before_end
+}
+
+pub struct LsmEngineIterator<'a> {
+    inner: MergeIterator<'a>,
+}
+
+impl<'a> LsmEngineIterator<'a> {
+    // 跳过删除标记
+    fn map(item: Result<Entry>) -> Option<<Self as Iterator>::Item> {
+        match item {
+            Ok((key, Some(value))) => Some(Ok((key, value))),
+            Ok((_, None)) => None,
+            Err(err) => Some(Err(err)),
+        }
+    }
+}
+
+impl<'a> EngineIterator for LsmEngineIterator<'a> {}
+
+impl<'a> Iterator for LsmEngineIterator<'a> {
+    type Item = Result<(Vec<u8>, Vec<u8>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(item) = Self::map(self.inner.next()?) {
+                return Some(item);
+            }
+        }
+    }
+}
+
+impl<'a> DoubleEndedIterator for LsmEngineIterator<'a> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(item) = Self::map(self.inner.next_back()?) {
+                return Some(item);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LsmEngine, LsmOptions, WAL_FILE};
+    use crate::{
+        error::{Error, Result},
+        storage::engine::Engine,
+    };
+
+    fn small_options() -> LsmOptions {
+        LsmOptions {
+            memtable_max_bytes: 256,
+            block_size: 64,
+            sstable_max_bytes: 512,
+            level0_max_files: 2,
+            level1_max_bytes: 1024,
+            level_size_multiplier: 2,
+            ..Default::default()
+        }
+    }
+
+    fn count_sstables(eng: &LsmEngine) -> Vec<usize> {
+        eng.levels.iter().map(|tables| tables.len()).collect()
+    }
+
+    #[test]
+    fn test_lsm_flush_and_compact() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-lsm");
+        let mut eng = LsmEngine::with_options(p.clone(), small_options())?;
+        for round in 0..5 {
+            for i in 0..100 {
+                let key = format!("key{:03}", i).into_bytes();
+                if i % 7 == round {
+                    eng.delete(key)?;
+                } else {
+                    eng.set(key, format!("value{}-{}", i, round).into_bytes())?;
+                }
+            }
+        }
+        // 数据分布在多层中
+        assert!(count_sstables(&eng).len() > 2);
+
+        let check = |eng: &mut LsmEngine| -> Result<()> {
+            let expected = (0..100)
+                .filter(|i| i % 7 != 4)
+                .map(|i| {
+                    (
+                        format!("key{:03}", i).into_bytes(),
+                        format!("value{}-4", i).into_bytes(),
+                    )
+                })
+                .collect::<Vec<_>>();
+            assert_eq!(eng.scan(..).collect::<Result<Vec<_>>>()?, expected);
+            let mut reversed = eng.scan(..).rev().collect::<Result<Vec<_>>>()?;
+            reversed.reverse();
+            assert_eq!(reversed, expected);
+            assert_eq!(eng.get(b"key004".to_vec())?, None);
+            assert_eq!(eng.get(b"key010".to_vec())?, Some(b"value10-4".to_vec()));
+            assert_eq!(eng.get(b"key100".to_vec())?, None);
+            Ok(())
+        };
+        check(&mut eng)?;
+        drop(eng);
+
+        // 重新打开后从 manifest 和预写日志中恢复
+        let mut eng = LsmEngine::with_options(p.clone(), small_options())?;
+        check(&mut eng)?;
+        // 同一个目录只能被打开一次
+        assert!(LsmEngine::with_options(p.clone(), small_options()).is_err());
+        drop(eng);
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_lsm_wal_recovery() -> Result<()> {
+        let p = tempfile::tempdir()?.into_path().join("sqldb-lsm");
+        let wal = p.join(WAL_FILE);
+        let mut eng = LsmEngine::new(p.clone())?;
+        eng.set(b"key1".to_vec(), b"value1".to_vec())?;
+        eng.set(b"key2".to_vec(), b"value2".to_vec())?;
+        eng.set(b"key3".to_vec(), b"value3".to_vec())?;
+        drop(eng);
+
+        // 最后一条记录只写入了一部分，重新打开时截断
+        let size = std::fs::metadata(&wal)?.len();
+        let file = std::fs::OpenOptions::new().write(true).open(&wal)?;
+        file.set_len(size - 3)?;
+        drop(file);
+        let mut eng = LsmEngine::new(p.clone())?;
+        assert_eq!(eng.get(b"key2".to_vec())?, Some(b"value2".to_vec()));
+        assert_eq!(eng.get(b"key3".to_vec())?, None);
+        drop(eng);
+
+        // 第一条记录的 key 长度被修改，超出了文件末尾，之后的记录不能被截断
+        let mut data = std::fs::read(&wal)?;
+        let size = data.len() as u64;
+        data[4] = 0x7f;
+        std::fs::write(&wal, data)?;
+        assert!(matches!(
+            LsmEngine::new(p.clone()),
+            Err(Error::Corruption(_))
+        ));
+        assert_eq!(std::fs::metadata(&wal)?.len(), size);
+
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+}
@@ -0,0 +1,495 @@
+use std::{
+    collections::VecDeque,
+    fs::{File, OpenOptions},
+    io::{BufWriter, Read, Seek, SeekFrom, Write},
+    ops::{Bound, RangeBounds},
+    path::{Path, PathBuf},
+};
+
+use crate::error::{Error, Result};
+
+use super::{bloom::Bloom, Entry, KeyRange};
+
+const FOOTER_SIZE: u64 = 28;
+const MAGIC: u32 = 0x5353_5442;
+
+// SSTable 文件格式
+// +--------------+-----+--------------+---------+---------+----------+
+// | data block   | ... | data block   | index   | bloom   | footer   |
+// +--------------+-----+--------------+---------+---------+----------+
+// data block: 多条有序的记录，末尾是 crc(4)
+//     记录格式: key len(4) | val len(4) | key | value，val len 为 -1 表示删除标记
+// index: first key len(4) | first key | 每个 block 一条索引，末尾是 crc(4)
+//     索引格式: last key len(4) | last key | offset(8) | size(4)
+// bloom: 布隆过滤器，末尾是 crc(4)
+// footer: index offset(8) | index size(4) | bloom offset(8) | bloom size(4) | magic(4)
+pub struct SsTableWriter {
+    path: PathBuf,
+    writer: BufWriter<File>,
+    block_size: usize,
+    bits_per_key: usize,
+    // 已经写入文件的大小
+    offset: u64,
+    block: Vec<u8>,
+    last_key: Vec<u8>,
+    first_key: Option<Vec<u8>>,
+    index: Vec<(Vec<u8>, u64, u32)>,
+    keys: Vec<Vec<u8>>,
+}
+
+impl SsTableWriter {
+    pub fn new(path: PathBuf, block_size: usize, bits_per_key: usize) -> Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(true)
+            .write(true)
+            .open(&path)?;
+        Ok(Self {
+            path,
+            writer: BufWriter::new(file),
+            block_size,
+            bits_per_key,
+            offset: 0,
+            block: Vec::new(),
+            last_key: Vec::new(),
+            first_key: None,
+            index: Vec::new(),
+            keys: Vec::new(),
+        })
+    }
+
+    // 写入一条记录，key 必须是递增的
+    pub fn add(&mut self, key: &[u8], value: Option<&[u8]>) -> Result<()> {
+        if self.first_key.is_none() {
+            self.first_key = Some(key.to_vec());
+        } else if key <= self.last_key.as_slice() {
+            return Err(Error::Internal(format!(
+                "sstable keys must be increasing, got {:?} after {:?}",
+                key, self.last_key
+            )));
+        }
+
+        self.block
+            .extend_from_slice(&(key.len() as u32).to_be_bytes());
+        self.block
+            .extend_from_slice(&value.map_or(-1, |v| v.len() as i32).to_be_bytes());
+        self.block.extend_from_slice(key);
+        if let Some(v) = value {
+            self.block.extend_from_slice(v);
+        }
+        self.last_key = key.to_vec();
+        self.keys.push(key.to_vec());
+
+        if self.block.len() >= self.block_size {
+            self.finish_block()?;
+        }
+        Ok(())
+    }
+
+    // 当前 SSTable 的大小
+    pub fn size(&self) -> u64 {
+        self.offset + self.block.len() as u64
+    }
+
+    // 写入 index、布隆过滤器和 footer，然后打开这个 SSTable
+    pub fn finish(mut self, id: u64) -> Result<SsTable> {
+        let first_key = match self.first_key.take() {
+            Some(first_key) => first_key,
+            None => return Err(Error::Internal("empty sstable".to_string())),
+        };
+        if !self.block.is_empty() {
+            self.finish_block()?;
+        }
+
+        let mut index = Vec::new();
+        index.extend_from_slice(&(first_key.len() as u32).to_be_bytes());
+        index.extend_from_slice(&first_key);
+        for (last_key, offset, size) in self.index.iter() {
+            index.extend_from_slice(&(last_key.len() as u32).to_be_bytes());
+            index.extend_from_slice(last_key);
+            index.extend_from_slice(&offset.to_be_bytes());
+            index.extend_from_slice(&size.to_be_bytes());
+        }
+        let (index_offset, index_size) = self.write_checked(index)?;
+
+        let bloom = Bloom::build(&self.keys, self.bits_per_key).encode();
+        let (bloom_offset, bloom_size) = self.write_checked(bloom)?;
+
+        self.writer.write_all(&index_offset.to_be_bytes())?;
+        self.writer.write_all(&index_size.to_be_bytes())?;
+        self.writer.write_all(&bloom_offset.to_be_bytes())?;
+        self.writer.write_all(&bloom_size.to_be_bytes())?;
+        self.writer.write_all(&MAGIC.to_be_bytes())?;
+        self.writer.flush()?;
+        self.writer.get_ref().sync_all()?;
+
+        SsTable::open(id, self.path)
+    }
+
+    fn finish_block(&mut self) -> Result<()> {
+        let block = std::mem::take(&mut self.block);
+        let (offset, size) = self.write_checked(block)?;
+        self.index.push((self.last_key.clone(), offset, size));
+        Ok(())
+    }
+
+    // 写入数据和 crc，返回写入的位置和大小
+    fn write_checked(&mut self, data: Vec<u8>) -> Result<(u64, u32)> {
+        let offset = self.offset;
+        self.writer.write_all(&data)?;
+        self.writer
+            .write_all(&crc32fast::hash(&data).to_be_bytes())?;
+        let size = data.len() as u32 + 4;
+        self.offset += size as u64;
+        Ok((offset, size))
+    }
+}
+
+// 只读的 SSTable，index 和布隆过滤器常驻内存，data block 按需从磁盘读取
+pub struct SsTable {
+    pub id: u64,
+    path: PathBuf,
+    file: File,
+    first_key: Vec<u8>,
+    // 每个 block 的最后一个 key、偏移和大小
+    index: Vec<(Vec<u8>, u64, u32)>,
+    bloom: Bloom,
+    size: u64,
+}
+
+impl SsTable {
+    pub fn open(id: u64, path: PathBuf) -> Result<Self> {
+        let file = File::open(&path)?;
+        let size = file.metadata()?.len();
+        if size < FOOTER_SIZE {
+            return Err(Error::Corruption(format!(
+                "sstable {} is too small",
+                path.display()
+            )));
+        }
+
+        let mut footer = [0; FOOTER_SIZE as usize];
+        (&file).seek(SeekFrom::Start(size - FOOTER_SIZE))?;
+        (&file).read_exact(&mut footer)?;
+        if u32::from_be_bytes(footer[24..28].try_into().unwrap()) != MAGIC {
+            return Err(Error::Corruption(format!(
+                "invalid sstable {}",
+                path.display()
+            )));
+        }
+        let index_offset = u64::from_be_bytes(footer[0..8].try_into().unwrap());
+        let index_size = u32::from_be_bytes(footer[8..12].try_into().unwrap());
+        let bloom_offset = u64::from_be_bytes(footer[12..20].try_into().unwrap());
+        let bloom_size = u32::from_be_bytes(footer[20..24].try_into().unwrap());
+
+        let data = read_checked(&file, &path, index_offset, index_size)?;
+        let mut decoder = Decoder::new(&data, &path);
+        let first_key = decoder.bytes()?;
+        let mut index = Vec::new();
+        while !decoder.is_empty() {
+            let last_key = decoder.bytes()?;
+            let offset = decoder.u64()?;
+            let size = decoder.u32()?;
+            index.push((last_key, offset, size));
+        }
+
+        let data = read_checked(&file, &path, bloom_offset, bloom_size)?;
+        let bloom = Bloom::decode(data).ok_or(Error::Corruption(format!(
+            "invalid bloom filter in sstable {}",
+            path.display()
+        )))?;
+
+        Ok(Self {
+            id,
+            path,
+            file,
+            first_key,
+            index,
+            bloom,
+            size,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    pub fn first_key(&self) -> &[u8] {
+        &self.first_key
+    }
+
+    pub fn last_key(&self) -> &[u8] {
+        self.index.last().map_or(&self.first_key, |(key, _, _)| key)
+    }
+
+    // key 的范围是否和 [start, end] 有交集
+    pub fn overlaps(&self, (start, end): &KeyRange) -> bool {
+        self.first_key.as_slice() <= end.as_slice() && self.last_key() >= start.as_slice()
+    }
+
+    // 点查，Some(None) 表示这个 key 已经被删除
+    pub fn get(&self, key: &[u8]) -> Result<Option<Option<Vec<u8>>>> {
+        if !self.bloom.may_contain(key) {
+            return Ok(None);
+        }
+        let i = self
+            .index
+            .partition_point(|(last_key, _, _)| last_key.as_slice() < key);
+        if i == self.index.len() {
+            return Ok(None);
+        }
+        Ok(self
+            .read_block(i)?
+            .into_iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v))
+    }
+
+    // 范围扫描，只读取范围内的 block
+    pub fn scan(&self, range: (Bound<Vec<u8>>, Bound<Vec<u8>>)) -> SsTableIterator<'_> {
+        let lo = match &range.0 {
+            Bound::Included(start) => self.index.partition_point(|(k, _, _)| k < start),
+            Bound::Excluded(start) => self.index.partition_point(|(k, _, _)| k <= start),
+            Bound::Unbounded => 0,
+        };
+        let hi = match &range.1 {
+            Bound::Included(end) => self.index.partition_point(|(k, _, _)| k <= end) + 1,
+            Bound::Excluded(end) => self.index.partition_point(|(k, _, _)| k < end) + 1,
+            Bound::Unbounded => self.index.len(),
+        }
+        .min(self.index.len());
+        SsTableIterator {
+            table: self,
+            range,
+            lo,
+            hi: hi.max(lo),
+            front: VecDeque::new(),
+            back: VecDeque::new(),
+        }
+    }
+
+    fn read_block(&self, i: usize) -> Result<Vec<Entry>> {
+        let (_, offset, size) = &self.index[i];
+        let data = read_checked(&self.file, &self.path, *offset, *size)?;
+        let mut decoder = Decoder::new(&data, &self.path);
+        let mut entries = Vec::new();
+        while !decoder.is_empty() {
+            let key_size = decoder.u32()? as usize;
+            let val_size = decoder.u32()? as i32;
+            let key = decoder.take(key_size)?.to_vec();
+            let value = match val_size {
+                -1 => None,
+                size => Some(decoder.take(size as usize)?.to_vec()),
+            };
+            entries.push((key, value));
+        }
+        Ok(entries)
+    }
+}
+
+// 读取一段带 crc 的数据，返回去掉 crc 的部分
+fn read_checked(file: &File, path: &Path, offset: u64, size: u32) -> Result<Vec<u8>> {
+    let mut file = file;
+    let mut buf = vec![0; size as usize];
+    file.seek(SeekFrom::Start(offset))?;
+    file.read_exact(&mut buf)?;
+    if buf.len() < 4 {
+        return Err(Error::Corruption(format!(
+            "invalid block at offset {} in sstable {}",
+            offset,
+            path.display()
+        )));
+    }
+    let crc = buf.split_off(buf.len() - 4);
+    if crc32fast::hash(&buf).to_be_bytes() != crc.as_slice() {
+        return Err(Error::Corruption(format!(
+            "checksum mismatch at offset {} in sstable {}",
+            offset,
+            path.display()
+        )));
+    }
+    Ok(buf)
+}
+
+// 按顺序解析 block 中的数据
+struct Decoder<'a> {
+    data: &'a [u8],
+    path: &'a Path,
+}
+
+impl<'a> Decoder<'a> {
+    fn new(data: &'a [u8], path: &'a Path) -> Self {
+        Self { data, path }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    fn take(&mut self, n: usize) -> Result<&'a [u8]> {
+        if self.data.len() < n {
+            return Err(Error::Corruption(format!(
+                "unexpected end of block in sstable {}",
+                self.path.display()
+            )));
+        }
+        let (head, tail) = self.data.split_at(n);
+        self.data = tail;
+        Ok(head)
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_be_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64> {
+        Ok(u64::from_be_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn bytes(&mut self) -> Result<Vec<u8>> {
+        let n = self.u32()? as usize;
+        Ok(self.take(n)?.to_vec())
+    }
+}
+
+pub struct SsTableIterator<'a> {
+    table: &'a SsTable,
+    range: (Bound<Vec<u8>>, Bound<Vec<u8>>),
+    // 还没有读取的 block 范围 [lo, hi)
+    lo: usize,
+    hi: usize,
+    // 从前往后和从后往前读取到的 block 中剩余的记录
+    front: VecDeque<Entry>,
+    back: VecDeque<Entry>,
+}
+
+impl<'a> SsTableIterator<'a> {
+    fn load(&self, i: usize) -> Result<VecDeque<Entry>> {
+        Ok(self
+            .table
+            .read_block(i)?
+            .into_iter()
+            .filter(|(key, _)| self.range.contains(key))
+            .collect())
+    }
+}
+
+impl<'a> Iterator for SsTableIterator<'a> {
+    type Item = Result<Entry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(entry) = self.front.pop_front() {
+                return Some(Ok(entry));
+            }
+            if self.lo >= self.hi {
+                return self.back.pop_front().map(Ok);
+            }
+            match self.load(self.lo) {
+                Ok(entries) => {
+                    self.front = entries;
+                    self.lo += 1;
+                }
+                Err(err) => {
+                    self.lo = self.hi;
+                    return Some(Err(err));
+                }
+            }
+        }
+    }
+}
+
+impl<'a> DoubleEndedIterator for SsTableIterator<'a> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(entry) = self.back.pop_back() {
+                return Some(Ok(entry));
+            }
+            if self.lo >= self.hi {
+                return self.front.pop_back().map(Ok);
+            }
+            match self.load(self.hi - 1) {
+                Ok(entries) => {
+                    self.back = entries;
+                    self.hi -= 1;
+                }
+                Err(err) => {
+                    self.hi = self.lo;
+                    return Some(Err(err));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ops::Bound;
+
+    use super::SsTableWriter;
+    use crate::error::{Error, Result};
+
+    #[test]
+    fn test_sstable() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("00000001.sst");
+        let mut writer = SsTableWriter::new(path.clone(), 64, 10)?;
+        for i in 0..100 {
+            let key = format!("key{:03}", i).into_bytes();
+            match i % 10 {
+                0 => writer.add(&key, None)?,
+                _ => writer.add(&key, Some(format!("value{}", i).as_bytes()))?,
+            }
+        }
+        assert!(writer.add(b"key000", None).is_err());
+        let table = writer.finish(1)?;
+        assert_eq!(table.first_key(), b"key000");
+        assert_eq!(table.last_key(), b"key099");
+
+        assert_eq!(table.get(b"key001")?, Some(Some(b"value1".to_vec())));
+        assert_eq!(table.get(b"key050")?, Some(None));
+        assert_eq!(table.get(b"key100")?, None);
+        assert_eq!(table.get(b"aaa")?, None);
+
+        let range = (
+            Bound::Included(b"key015".to_vec()),
+            Bound::Excluded(b"key042".to_vec()),
+        );
+        let keys = table
+            .scan(range.clone())
+            .map(|r| r.map(|(k, _)| k))
+            .collect::<Result<Vec<_>>>()?;
+        let expected = (15..42)
+            .map(|i| format!("key{:03}", i).into_bytes())
+            .collect::<Vec<_>>();
+        assert_eq!(keys, expected);
+
+        // 从两端交替读取
+        let mut iter = table.scan(range);
+        let mut front = Vec::new();
+        let mut back = Vec::new();
+        while let Some(entry) = iter.next() {
+            front.push(entry?.0);
+            match iter.next_back() {
+                Some(entry) => back.push(entry?.0),
+                None => break,
+            }
+        }
+        back.reverse();
+        front.extend(back);
+        assert_eq!(front, expected);
+
+        // 修改 data block 中的数据
+        drop(table);
+        let mut data = std::fs::read(&path)?;
+        data[10] ^= 0x01;
+        std::fs::write(&path, data)?;
+        let table = super::SsTable::open(1, path)?;
+        assert!(matches!(table.get(b"key001"), Err(Error::Corruption(_))));
+        Ok(())
+    }
+}
@@ -0,0 +1,130 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{Read, Seek, SeekFrom, Write},
+    path::PathBuf,
+};
+
+use crate::error::{Error, Result};
+
+use super::Entry;
+
+const WAL_HEADER_SIZE: usize = 12;
+// 一条记录的最大大小，超过文件末尾的记录只有在这个范围内才可能是没有写完整的记录
+const MAX_ENTRY_SIZE: u64 = 64 * 1024 * 1024;
+
+// 预写日志，memtable 中的数据写入 SSTable 之前保存在这里
+// +-------------+-------------+-------------+----------------+----------------+
+// | crc(4)        key len(4)    val len(4)     key              val           |
+// +-------------+-------------+-------------+----------------+----------------+
+// val len 为 -1 表示删除标记，crc 校验的是 crc 之后的全部数据
+pub struct Wal {
+    file: File,
+}
+
+impl Wal {
+    // 打开日志文件，返回日志中的全部记录
+    pub fn open(path: PathBuf) -> Result<(Self, Vec<Entry>)> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .read(true)
+            .write(true)
+            .open(&path)?;
+        let mut data = Vec::new();
+        file.read_to_end(&mut data)?;
+
+        let mut entries = Vec::new();
+        let mut offset = 0;
+        while offset < data.len() {
+            match Self::decode(&data[offset..]) {
+                Some((entry, size)) => {
+                    entries.push(entry);
+                    offset += size;
+                }
+                // 最后一条记录没有写完整，截断这部分数据
+                None if Self::is_tail(&data[offset..]) => {
+                    file.set_len(offset as u64)?;
+                    file.sync_all()?;
+                    break;
+                }
+                None => {
+                    return Err(Error::Corruption(format!(
+                        "checksum mismatch at offset {} in {}",
+                        offset,
+                        path.display()
+                    )))
+                }
+            }
+        }
+        file.seek(SeekFrom::End(0))?;
+        Ok((Self { file }, entries))
+    }
+
+    pub fn append(&mut self, key: &[u8], value: Option<&[u8]>) -> Result<()> {
+        let size = (WAL_HEADER_SIZE + key.len() + value.map_or(0, |v| v.len())) as u64;
+        if size > MAX_ENTRY_SIZE {
+            return Err(Error::Internal(format!(
+                "entry size {} exceeds the limit {}",
+                size, MAX_ENTRY_SIZE
+            )));
+        }
+        let mut buf =
+            Vec::with_capacity(WAL_HEADER_SIZE + key.len() + value.map_or(0, |v| v.len()));
+        buf.extend_from_slice(&[0; 4]);
+        buf.extend_from_slice(&(key.len() as u32).to_be_bytes());
+        buf.extend_from_slice(&value.map_or(-1, |v| v.len() as i32).to_be_bytes());
+        buf.extend_from_slice(key);
+        if let Some(v) = value {
+            buf.extend_from_slice(v);
+        }
+        let crc = crc32fast::hash(&buf[4..]);
+        buf[..4].copy_from_slice(&crc.to_be_bytes());
+        self.file.write_all(&buf)?;
+        Ok(())
+    }
+
+    pub fn sync(&mut self) -> Result<()> {
+        self.file.sync_data()?;
+        Ok(())
+    }
+
+    // memtable 写入 SSTable 之后清空日志
+    pub fn reset(&mut self) -> Result<()> {
+        self.file.set_len(0)?;
+        self.file.seek(SeekFrom::Start(0))?;
+        self.file.sync_all()?;
+        Ok(())
+    }
+
+    // 解析一条记录，返回记录和记录的大小，数据不完整或者校验失败时返回 None
+    fn decode(data: &[u8]) -> Option<(Entry, usize)> {
+        if data.len() < WAL_HEADER_SIZE {
+            return None;
+        }
+        let crc = u32::from_be_bytes(data[0..4].try_into().unwrap());
+        let key_size = u32::from_be_bytes(data[4..8].try_into().unwrap()) as usize;
+        let val_size = i32::from_be_bytes(data[8..12].try_into().unwrap());
+        let size = WAL_HEADER_SIZE + key_size + val_size.max(0) as usize;
+        if val_size < -1 || data.len() < size || crc32fast::hash(&data[4..size]) != crc {
+            return None;
+        }
+        let key = data[WAL_HEADER_SIZE..WAL_HEADER_SIZE + key_size].to_vec();
+        let value = match val_size {
+            -1 => None,
+            _ => Some(data[WAL_HEADER_SIZE + key_size..size].to_vec()),
+        };
+        Some(((key, value), size))
+    }
+
+    // 无法解析的记录是否是文件末尾没有写完整的最后一条记录
+    // 记录需要延伸到文件末尾，并且大小不能超过上限，否则说明长度字段已经损坏
+    fn is_tail(data: &[u8]) -> bool {
+        if data.len() < WAL_HEADER_SIZE {
+            return true;
+        }
+        let key_size = u32::from_be_bytes(data[4..8].try_into().unwrap()) as u64;
+        let val_size = i32::from_be_bytes(data[8..12].try_into().unwrap()).max(0) as u64;
+        let size = WAL_HEADER_SIZE as u64 + key_size + val_size;
+        size <= MAX_ENTRY_SIZE && size >= data.len() as u64
+    }
+}
@@ -1,5 +1,7 @@
+pub mod btree;
 pub mod disk;
 pub mod engine;
+pub mod keycode;
+pub mod lsm;
 pub mod memory;
 pub mod mvcc;
-pub mod keycode;
\ No newline at end of file
@@ -1,5 +1,6 @@
 use std::{
-    collections::{BTreeMap, HashSet},
+    collections::{BTreeMap, HashSet, VecDeque},
+    ops::{Bound, RangeBounds},
     sync::{Arc, Mutex, MutexGuard},
     u64,
 };
@@ -15,14 +16,23 @@ use super::{
 
 pub type Version = u64;
 
+// 扫描时每次从存储引擎中读取的记录数，两次读取之间会释放存储引擎的锁
+const SCAN_BATCH_SIZE: usize = 512;
+
+// 正在运行的只读事务能看到的最小版本号及其数量
+// 只读事务不在活跃事务列表中，GC 时需要保留它们能看到的数据
+type ReadSnapshots = Arc<Mutex<BTreeMap<Version, usize>>>;
+
 pub struct Mvcc<E: Engine> {
     engine: Arc<Mutex<E>>,
+    snapshots: ReadSnapshots,
 }
 
 impl<E: Engine> Clone for Mvcc<E> {
     fn clone(&self) -> Self {
         Self {
             engine: self.engine.clone(),
+            snapshots: self.snapshots.clone(),
         }
     }
 }
@@ -31,23 +41,204 @@ impl<E: Engine> Mvcc<E> {
     pub fn new(eng: E) -> Self {
         Self {
             engine: Arc::new(Mutex::new(eng)),
+            snapshots: Arc::new(Mutex::new(BTreeMap::new())),
         }
     }
 
     pub fn begin(&self) -> Result<MvccTransaction<E>> {
         MvccTransaction::begin(self.engine.clone())
     }
+
+    // 开启只读事务，读取最新的快照
+    pub fn begin_read_only(&self) -> Result<MvccTransaction<E>> {
+        MvccTransaction::begin_read_only(self.engine.clone(), self.snapshots.clone(), None)
+    }
+
+    // 开启只读事务，读取指定版本开始时的快照
+    pub fn begin_as_of(&self, version: Version) -> Result<MvccTransaction<E>> {
+        MvccTransaction::begin_read_only(self.engine.clone(), self.snapshots.clone(), Some(version))
+    }
+
+    // 清理历史版本
+    // 水位线以下的版本对所有正在运行的事务都是可见的，每个 key 只需要保留其中最新的一个版本
+    // 如果最新的版本是删除标记，那么这个 key 在水位线以下的版本可以全部清理掉
+    // 分批扫描和删除，两批之间释放存储引擎的锁，不会长时间阻塞其他事务
+    pub fn gc(&self) -> Result<GcStats> {
+        let watermark = {
+            let mut engine = self.engine.lock()?;
+            let watermark = self.watermark(&mut engine)?;
+            // 先记录 GC 水位线，清理期间开启的事务不能读取水位线以下的历史版本
+            // 新的事务能看到的最小版本号不会低于水位线，水位线以下的数据也不会再被修改
+            engine.set(
+                MvccKey::GcWatermark.encode()?,
+                bincode::serialize(&watermark)?,
+            )?;
+            watermark
+        };
+        let mut stats = GcStats::default();
+
+        // 扫描全部的 Version 数据
+        let mut prefix = MvccKeyPrefix::Version(Vec::new()).encode()?;
+        prefix.truncate(prefix.len() - 2);
+        let mut range = prefix_range(prefix);
+        // 当前 key 在水位线以下的最新版本：原始 key、编码后的 key、数据大小、是否已删除
+        let mut latest: Option<(Vec<u8>, Vec<u8>, usize, bool)> = None;
+        loop {
+            let batch = self.scan_batch(&mut range)?;
+            let done = batch.len() < SCAN_BATCH_SIZE;
+            let mut delete_keys = Vec::new();
+            for (key, value) in batch {
+                let (raw_key, version) = match MvccKey::decode(key.clone())? {
+                    MvccKey::Version(raw_key, version) => (raw_key, version),
+                    _ => {
+                        return Err(Error::Internal(format!(
+                            "unexpected key: {:?}",
+                            String::from_utf8(key)
+                        )))
+                    }
+                };
+                if version >= watermark {
+                    continue;
+                }
+                if let Some((last_raw_key, last_key, size, deleted)) = latest.take() {
+                    // 被更新的版本覆盖，或者是已经删除的数据
+                    if last_raw_key == raw_key || deleted {
+                        delete_keys.push((last_key, size));
+                    }
+                }
+                let deleted = bincode::deserialize::<Option<Vec<u8>>>(&value)?.is_none();
+                let size = key.len() + value.len();
+                latest = Some((raw_key, key, size, deleted));
+            }
+            if done {
+                if let Some((_, key, size, true)) = latest.take() {
+                    delete_keys.push((key, size));
+                }
+            }
+            stats.versions += delete_keys.len();
+            stats.bytes += self.delete_batch(delete_keys)?;
+            if done {
+                break;
+            }
+        }
+
+        // 水位线以下的活跃事务列表快照也不会再被读取
+        let mut range = prefix_range(MvccKeyPrefix::TxnActiveSnapshot.encode()?);
+        loop {
+            let batch = self.scan_batch(&mut range)?;
+            let mut done = batch.len() < SCAN_BATCH_SIZE;
+            let mut delete_keys = Vec::new();
+            for (key, value) in batch {
+                match MvccKey::decode(key.clone())? {
+                    MvccKey::TxnActiveSnapshot(version) if version < watermark => {
+                        let size = key.len() + value.len();
+                        delete_keys.push((key, size));
+                    }
+                    MvccKey::TxnActiveSnapshot(_) => {
+                        done = true;
+                        break;
+                    }
+                    _ => {
+                        return Err(Error::Internal(format!(
+                            "unexpected key: {:?}",
+                            String::from_utf8(key)
+                        )))
+                    }
+                }
+            }
+            stats.snapshots += delete_keys.len();
+            stats.bytes += self.delete_batch(delete_keys)?;
+            if done {
+                break;
+            }
+        }
+        Ok(stats)
+    }
+
+    // 读取范围内的下一批数据，最多 SCAN_BATCH_SIZE 条，并把范围的起点移动到这一批之后
+    fn scan_batch(&self, range: &mut (Bound<Vec<u8>>, Bound<Vec<u8>>)) -> Result<Vec<KvPair>> {
+        let mut engine = self.engine.lock()?;
+        let batch = engine
+            .scan(range.clone())
+            .take(SCAN_BATCH_SIZE)
+            .collect::<Result<Vec<_>>>()?;
+        if let Some((key, _)) = batch.last() {
+            range.0 = Bound::Excluded(key.clone());
+        }
+        Ok(batch)
+    }
+
+    // 删除一批数据，返回删除的数据大小
+    fn delete_batch(&self, keys: Vec<(Vec<u8>, usize)>) -> Result<usize> {
+        if keys.is_empty() {
+            return Ok(0);
+        }
+        let mut engine = self.engine.lock()?;
+        let mut bytes = 0;
+        for (key, size) in keys {
+            engine.delete(key)?;
+            bytes += size;
+        }
+        Ok(bytes)
+    }
+
+    // 计算 GC 水位线，即所有正在运行的事务能看到的最小版本号
+    fn watermark(&self, engine: &mut MutexGuard<E>) -> Result<Version> {
+        let mut watermark = MvccTransaction::next_version(engine)?;
+        for version in MvccTransaction::scan_active(engine)? {
+            let snapshot = MvccTransaction::active_snapshot(engine, version)?;
+            watermark = watermark.min(snapshot.into_iter().fold(version, Version::min));
+        }
+        if let Some(version) = self.snapshots.lock()?.keys().next() {
+            watermark = watermark.min(*version);
+        }
+        Ok(watermark)
+    }
+}
+
+// 一次 GC 清理的数据
+#[derive(Debug, Default, PartialEq)]
+pub struct GcStats {
+    // 清理的历史版本数量
+    pub versions: usize,
+    // 清理的活跃事务列表快照数量
+    pub snapshots: usize,
+    // 清理的数据大小，包括历史版本和活跃事务列表快照
+    pub bytes: usize,
+}
+
+type KvPair = (Vec<u8>, Vec<u8>);
+
+// 前缀对应的 key 范围
+fn prefix_range(prefix: Vec<u8>) -> (Bound<Vec<u8>>, Bound<Vec<u8>>) {
+    let mut end = prefix.clone();
+    while end.last() == Some(&u8::MAX) {
+        end.pop();
+    }
+    let end = match end.last_mut() {
+        Some(last) => {
+            *last += 1;
+            Bound::Excluded(end)
+        }
+        None => Bound::Unbounded,
+    };
+    (Bound::Included(prefix), end)
 }
 
 pub struct MvccTransaction<E: Engine> {
     engine: Arc<Mutex<E>>,
     state: TransactionState,
+    // 只读事务登记的最小可见版本号，事务结束时移除
+    read_snapshot: Option<(ReadSnapshots, Version)>,
 }
 
 // 事务状态
+#[derive(Clone)]
 pub struct TransactionState {
     // 当前事务的版本号
     pub version: Version,
+    // 是否是只读事务
+    pub read_only: bool,
     // 当前活跃事务版本列表
     pub active_versions: HashSet<Version>,
 }
@@ -55,9 +246,12 @@ pub struct TransactionState {
 impl TransactionState {
     fn is_visible(&self, version: Version) -> bool {
         if self.active_versions.contains(&version) {
-            return false;
+            false
+        } else if self.read_only {
+            // 只读事务不占用版本号，只能看到比自己版本号小的数据
+            version < self.version
         } else {
-            return version <= self.version;
+            version <= self.version
         }
     }
 }
@@ -68,6 +262,10 @@ pub enum MvccKey {
     TxnActive(Version),
     TxnWrite(Version, #[serde(with = "serde_bytes")] Vec<u8>),
     Version(#[serde(with = "serde_bytes")] Vec<u8>, Version),
+    // 事务开启时的活跃事务列表，用于恢复历史版本的快照
+    TxnActiveSnapshot(Version),
+    // GC 水位线，比它小的历史版本可能已经被清理
+    GcWatermark,
 }
 
 // NextVersion 0
@@ -90,6 +288,7 @@ pub enum MvccKeyPrefix {
     TxnActive,
     TxnWrite(Version),
     Version(#[serde(with = "serde_bytes")] Vec<u8>),
+    TxnActiveSnapshot,
 }
 
 impl MvccKeyPrefix {
@@ -104,10 +303,7 @@ impl<E: Engine> MvccTransaction<E> {
         // 获取存储引擎
         let mut engine = eng.lock()?;
         // 获取最新的版本号
-        let next_version = match engine.get(MvccKey::NextVersion.encode()?)? {
-            Some(value) => bincode::deserialize(&value)?,
-            None => 1,
-        };
+        let next_version = Self::next_version(&mut engine)?;
         // 保存下一个 version
         engine.set(
             MvccKey::NextVersion.encode()?,
@@ -117,6 +313,14 @@ impl<E: Engine> MvccTransaction<E> {
         // 获取当前活跃的事务列表
         let active_versions = Self::scan_active(&mut engine)?;
 
+        // 保存活跃事务列表的快照，用于之后读取这个版本的历史数据
+        if !active_versions.is_empty() {
+            engine.set(
+                MvccKey::TxnActiveSnapshot(next_version).encode()?,
+                bincode::serialize(&active_versions)?,
+            )?;
+        }
+
         // 当前事务加入到活跃事务列表中
         engine.set(MvccKey::TxnActive(next_version).encode()?, vec![])?;
 
@@ -124,13 +328,79 @@ impl<E: Engine> MvccTransaction<E> {
             engine: eng.clone(),
             state: TransactionState {
                 version: next_version,
+                read_only: false,
                 active_versions,
             },
+            read_snapshot: None,
         })
     }
 
+    // 开启只读事务，不会分配新的版本号，也不会加入到活跃事务列表中
+    // as_of 为空时读取最新的快照，否则读取指定版本开始时的快照
+    pub fn begin_read_only(
+        eng: Arc<Mutex<E>>,
+        snapshots: ReadSnapshots,
+        as_of: Option<Version>,
+    ) -> Result<Self> {
+        // 获取存储引擎
+        let mut engine = eng.lock()?;
+        // 获取最新的版本号
+        let next_version = Self::next_version(&mut engine)?;
+
+        let (version, active_versions) = match as_of {
+            Some(version) => {
+                if version >= next_version {
+                    return Err(Error::Internal(format!(
+                        "version {} does not exist",
+                        version
+                    )));
+                }
+                // 恢复这个版本开始时的活跃事务列表
+                (version, Self::active_snapshot(&mut engine, version)?)
+            }
+            None => (next_version, Self::scan_active(&mut engine)?),
+        };
+
+        // 能看到的最小版本号不能低于 GC 水位线，否则需要读取的数据可能已经被清理
+        let floor = active_versions.iter().copied().fold(version, Version::min);
+        if let Some(value) = engine.get(MvccKey::GcWatermark.encode()?)? {
+            let watermark: Version = bincode::deserialize(&value)?;
+            if floor < watermark {
+                return Err(Error::Internal(format!(
+                    "version {} has been garbage collected",
+                    version
+                )));
+            }
+        }
+        *snapshots.lock()?.entry(floor).or_insert(0) += 1;
+
+        Ok(Self {
+            engine: eng.clone(),
+            state: TransactionState {
+                version,
+                read_only: true,
+                active_versions,
+            },
+            read_snapshot: Some((snapshots, floor)),
+        })
+    }
+
+    // 是否是只读事务
+    pub fn read_only(&self) -> bool {
+        self.state.read_only
+    }
+
+    // 获取事务的版本号
+    pub fn version(&self) -> Version {
+        self.state.version
+    }
+
     // 提交事务
     pub fn commit(&self) -> Result<()> {
+        // 只读事务没有写入任何数据
+        if self.state.read_only {
+            return Ok(());
+        }
         // 获取存储引擎
         let mut engine = self.engine.lock()?;
 
@@ -147,11 +417,17 @@ impl<E: Engine> MvccTransaction<E> {
         }
 
         // 从活跃事务列表中删除
-        engine.delete(MvccKey::TxnActive(self.state.version).encode()?)
+        engine.delete(MvccKey::TxnActive(self.state.version).encode()?)?;
+        // 保证已经提交的事务数据不会丢失
+        engine.flush()
     }
 
     // 回滚事务
     pub fn rollback(&self) -> Result<()> {
+        // 只读事务没有写入任何数据
+        if self.state.read_only {
+            return Ok(());
+        }
         // 获取存储引擎
         let mut engine = self.engine.lock()?;
 
@@ -218,8 +494,42 @@ impl<E: Engine> MvccTransaction<E> {
         Ok(None)
     }
 
-    pub fn scan_prefix(&self, prefix: Vec<u8>) -> Result<Vec<ScanResult>> {
-        let mut eng = self.engine.lock()?;
+    // 范围扫描，可以从两端读取，返回的迭代器按需读取数据
+    pub fn scan(&self, range: impl RangeBounds<Vec<u8>>) -> Result<MvccScanIterator<E>> {
+        // 全部 Version 数据的前缀
+        let mut prefix = MvccKeyPrefix::Version(Vec::new()).encode()?;
+        prefix.truncate(prefix.len() - 2);
+
+        // key 的范围转换为 MvccKey::Version 的范围，包含 key 的全部版本
+        let start = match range.start_bound() {
+            Bound::Included(key) => Bound::Included(MvccKey::Version(key.clone(), 0).encode()?),
+            Bound::Excluded(key) => {
+                Bound::Excluded(MvccKey::Version(key.clone(), u64::MAX).encode()?)
+            }
+            Bound::Unbounded => Bound::Included(prefix.clone()),
+        };
+        let end = match range.end_bound() {
+            Bound::Included(key) => {
+                Bound::Included(MvccKey::Version(key.clone(), u64::MAX).encode()?)
+            }
+            Bound::Excluded(key) => Bound::Excluded(MvccKey::Version(key.clone(), 0).encode()?),
+            Bound::Unbounded => {
+                let mut end = prefix;
+                if let Some(last) = end.iter_mut().last() {
+                    *last += 1;
+                }
+                Bound::Excluded(end)
+            }
+        };
+        Ok(MvccScanIterator::new(
+            self.engine.clone(),
+            self.state.clone(),
+            (start, end),
+        ))
+    }
+
+    // 前缀扫描，返回的迭代器按需读取数据
+    pub fn scan_prefix(&self, prefix: Vec<u8>) -> Result<MvccScanIterator<E>> {
         let mut enc_prefix = MvccKeyPrefix::Version(prefix).encode()?;
         // 原始值           编码后
         // 97 98 99     -> 97 98 99 0 0
@@ -228,35 +538,31 @@ impl<E: Engine> MvccTransaction<E> {
         // 去掉最后的 [0, 0] 后缀
         enc_prefix.truncate(enc_prefix.len() - 2);
 
-        let mut iter = eng.scan_prefix(enc_prefix);
-        let mut results = BTreeMap::new();
-        while let Some((key, value)) = iter.next().transpose()? {
-            match MvccKey::decode(key.clone())? {
-                MvccKey::Version(raw_key, version) => {
-                    if self.state.is_visible(version) {
-                        match bincode::deserialize(&value)? {
-                            Some(raw_value) => results.insert(raw_key, raw_value),
-                            None => results.remove(&raw_key),
-                        };
-                    }
-                }
-                _ => {
-                    return Err(Error::Internal(format!(
-                        "Unexepected key {:?}",
-                        String::from_utf8(key)
-                    )))
-                }
-            }
+        // 转义后的前缀可能以 255 结尾，去掉末尾的 255 之后再把最后一个字节加一
+        let mut end = enc_prefix.clone();
+        while end.last() == Some(&u8::MAX) {
+            end.pop();
         }
-
-        Ok(results
-            .into_iter()
-            .map(|(key, value)| ScanResult { key, value })
-            .collect())
+        let end = match end.last_mut() {
+            Some(last) => {
+                *last += 1;
+                Bound::Excluded(end)
+            }
+            None => Bound::Unbounded,
+        };
+        Ok(MvccScanIterator::new(
+            self.engine.clone(),
+            self.state.clone(),
+            (Bound::Included(enc_prefix), end),
+        ))
     }
 
     // 更新/删除数据
     fn write_inner(&self, key: Vec<u8>, value: Option<Vec<u8>>) -> Result<()> {
+        // 只读事务不能写入
+        if self.state.read_only {
+            return Err(Error::ReadOnly);
+        }
         // 获取存储引擎
         let mut engine = self.engine.lock()?;
 
@@ -312,6 +618,24 @@ impl<E: Engine> MvccTransaction<E> {
         Ok(())
     }
 
+    // 获取下一个事务的版本号
+    fn next_version(engine: &mut MutexGuard<E>) -> Result<Version> {
+        Ok(match engine.get(MvccKey::NextVersion.encode()?)? {
+            Some(value) => bincode::deserialize(&value)?,
+            None => 1,
+        })
+    }
+
+    // 获取事务开启时的活跃事务列表
+    fn active_snapshot(engine: &mut MutexGuard<E>, version: Version) -> Result<HashSet<Version>> {
+        Ok(
+            match engine.get(MvccKey::TxnActiveSnapshot(version).encode()?)? {
+                Some(value) => bincode::deserialize(&value)?,
+                None => HashSet::new(),
+            },
+        )
+    }
+
     // 扫描获取当前活跃事务列表
     fn scan_active(engine: &mut MutexGuard<E>) -> Result<HashSet<Version>> {
         let mut active_versions = HashSet::new();
@@ -333,20 +657,191 @@ impl<E: Engine> MvccTransaction<E> {
     }
 }
 
+impl<E: Engine> Drop for MvccTransaction<E> {
+    // 只读事务结束后，GC 不再需要保留它能看到的数据
+    fn drop(&mut self) {
+        if let Some((snapshots, floor)) = self.read_snapshot.take() {
+            if let Ok(mut snapshots) = snapshots.lock() {
+                if let Some(count) = snapshots.get_mut(&floor) {
+                    *count -= 1;
+                    if *count == 0 {
+                        snapshots.remove(&floor);
+                    }
+                }
+            }
+        }
+    }
+}
+
 #[derive(Debug, PartialEq)]
 pub struct ScanResult {
     pub key: Vec<u8>,
     pub value: Vec<u8>,
 }
 
+// 扫描时读取到的一个版本：原始 key、版本号、value
+type VersionEntry = (Vec<u8>, Version, Vec<u8>);
+
+// MVCC 扫描迭代器，每次从存储引擎中读取一批数据，逐个 key 找到对事务可见的最新版本
+// 迭代器持有事务状态的副本，需要在事务结束之前读取完
+pub struct MvccScanIterator<E: Engine> {
+    engine: Arc<Mutex<E>>,
+    state: TransactionState,
+    // 还没有从存储引擎中读取的范围
+    range: (Bound<Vec<u8>>, Bound<Vec<u8>>),
+    // 从前向后和从后向前读取到还没有处理的版本，都按照从小到大排列
+    front: VecDeque<VersionEntry>,
+    back: VecDeque<VersionEntry>,
+    // 范围内的数据已经全部读取
+    exhausted: bool,
+}
+
+impl<E: Engine> MvccScanIterator<E> {
+    fn new(
+        engine: Arc<Mutex<E>>,
+        state: TransactionState,
+        range: (Bound<Vec<u8>>, Bound<Vec<u8>>),
+    ) -> Self {
+        // 范围为空时不需要读取存储引擎，例如 a > 5 and a < 3
+        // 存储引擎使用的 BTreeMap 在起始位置大于结束位置时会 panic
+        let exhausted = match (&range.0, &range.1) {
+            (Bound::Included(start), Bound::Included(end)) => start > end,
+            (
+                Bound::Included(start) | Bound::Excluded(start),
+                Bound::Included(end) | Bound::Excluded(end),
+            ) => start >= end,
+            _ => false,
+        };
+        Self {
+            engine,
+            state,
+            range,
+            front: VecDeque::new(),
+            back: VecDeque::new(),
+            exhausted,
+        }
+    }
+
+    // 从存储引擎中读取下一批数据，front 为 true 时从前向后读取，否则从后向前读取
+    fn fill(&mut self, front: bool) -> Result<()> {
+        let mut engine = self.engine.lock()?;
+        let mut iter = engine.scan(self.range.clone());
+        let mut count = 0;
+        loop {
+            let item = match front {
+                true => iter.next(),
+                false => iter.next_back(),
+            };
+            let (key, value) = match item.transpose()? {
+                Some(item) => item,
+                None => break,
+            };
+            let entry = match MvccKey::decode(key.clone())? {
+                MvccKey::Version(raw_key, version) => (raw_key, version, value),
+                _ => {
+                    return Err(Error::Internal(format!(
+                        "unexpected key: {:?}",
+                        String::from_utf8(key)
+                    )))
+                }
+            };
+            match front {
+                true => self.front.push_back(entry),
+                false => self.back.push_front(entry),
+            }
+            count += 1;
+            if count >= SCAN_BATCH_SIZE {
+                // 下一批从这个 key 之后开始读取
+                match front {
+                    true => self.range.0 = Bound::Excluded(key),
+                    false => self.range.1 = Bound::Excluded(key),
+                }
+                return Ok(());
+            }
+        }
+        self.exhausted = true;
+        Ok(())
+    }
+
+    // 这一端的数据处理完之后从存储引擎中读取，范围内的数据都读取完之后，再读取另一端剩下的数据
+    fn load(&mut self, front: bool) -> Result<()> {
+        let buffer = match front {
+            true => &self.front,
+            false => &self.back,
+        };
+        if buffer.is_empty() && !self.exhausted {
+            self.fill(front)?;
+        }
+        Ok(())
+    }
+
+    // 读取一端的下一个版本
+    fn next_version(&mut self, front: bool) -> Result<Option<VersionEntry>> {
+        self.load(front)?;
+        Ok(match front {
+            true => self.front.pop_front().or_else(|| self.back.pop_front()),
+            false => self.back.pop_back().or_else(|| self.front.pop_back()),
+        })
+    }
+
+    // 查看一端下一个版本的原始 key
+    fn peek_key(&mut self, front: bool) -> Result<Option<&Vec<u8>>> {
+        self.load(front)?;
+        let entry = match front {
+            true => self.front.front().or(self.back.front()),
+            false => self.back.back().or(self.front.back()),
+        };
+        Ok(entry.map(|(key, _, _)| key))
+    }
+
+    fn try_next(&mut self, front: bool) -> Result<Option<ScanResult>> {
+        while let Some((key, version, value)) = self.next_version(front)? {
+            // 同一个 key 的版本从小到大排列，从前向后读取时最后一个可见的版本是最新的数据，
+            // 从后向前读取时是第一个可见的版本，这个 key 的其他版本都需要跳过
+            let mut latest = self.state.is_visible(version).then_some(value);
+            while self.peek_key(front)? == Some(&key) {
+                let (_, version, value) = self.next_version(front)?.unwrap();
+                if self.state.is_visible(version) && (front || latest.is_none()) {
+                    latest = Some(value);
+                }
+            }
+            // 最新的版本是删除标记时跳过这个 key
+            if let Some(value) = latest {
+                if let Some(value) = bincode::deserialize(&value)? {
+                    return Ok(Some(ScanResult { key, value }));
+                }
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl<E: Engine> Iterator for MvccScanIterator<E> {
+    type Item = Result<ScanResult>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.try_next(true).transpose()
+    }
+}
+
+impl<E: Engine> DoubleEndedIterator for MvccScanIterator<E> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.try_next(false).transpose()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::{
-        error::Result,
-        storage::{disk::DiskEngine, engine::Engine, memory::MemoryEngine},
+        error::{Error, Result},
+        storage::{
+            btree::BTreeEngine, disk::DiskEngine, engine::Engine, lsm::LsmEngine,
+            memory::MemoryEngine,
+        },
     };
 
-    use super::Mvcc;
+    use super::{GcStats, Mvcc};
+    use std::ops::Bound;
 
     // 1. Get
     fn get(eng: impl Engine) -> Result<()> {
@@ -374,6 +869,14 @@ mod tests {
         let p = tempfile::tempdir()?.into_path().join("sqldb-log");
         get(DiskEngine::new(p.clone())?)?;
         std::fs::remove_dir_all(p.parent().unwrap())?;
+
+        let p = tempfile::tempdir()?.into_path().join("sqldb-lsm");
+        get(LsmEngine::new(p.clone())?)?;
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+
+        let p = tempfile::tempdir()?.into_path().join("sqldb-btree");
+        get(BTreeEngine::new(p.clone())?)?;
+        std::fs::remove_dir_all(p.parent().unwrap())?;
         Ok(())
     }
 
@@ -410,6 +913,14 @@ mod tests {
         let p = tempfile::tempdir()?.into_path().join("sqldb-log");
         get_isolation(DiskEngine::new(p.clone())?)?;
         std::fs::remove_dir_all(p.parent().unwrap())?;
+
+        let p = tempfile::tempdir()?.into_path().join("sqldb-lsm");
+        get_isolation(LsmEngine::new(p.clone())?)?;
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+
+        let p = tempfile::tempdir()?.into_path().join("sqldb-btree");
+        get_isolation(BTreeEngine::new(p.clone())?)?;
+        std::fs::remove_dir_all(p.parent().unwrap())?;
         Ok(())
     }
 
@@ -426,7 +937,9 @@ mod tests {
         tx.commit()?;
 
         let tx1 = mvcc.begin()?;
-        let iter1 = tx1.scan_prefix(b"aa".to_vec())?;
+        let iter1 = tx1
+            .scan_prefix(b"aa".to_vec())?
+            .collect::<Result<Vec<_>>>()?;
         assert_eq!(
             iter1,
             vec![
@@ -441,7 +954,9 @@ mod tests {
             ]
         );
 
-        let iter2 = tx1.scan_prefix(b"a".to_vec())?;
+        let iter2 = tx1
+            .scan_prefix(b"a".to_vec())?
+            .collect::<Result<Vec<_>>>()?;
         assert_eq!(
             iter2,
             vec![
@@ -464,7 +979,9 @@ mod tests {
             ]
         );
 
-        let iter3 = tx1.scan_prefix(b"bcca".to_vec())?;
+        let iter3 = tx1
+            .scan_prefix(b"bcca".to_vec())?
+            .collect::<Result<Vec<_>>>()?;
         assert_eq!(
             iter3,
             vec![super::ScanResult {
@@ -482,6 +999,14 @@ mod tests {
         let p = tempfile::tempdir()?.into_path().join("sqldb-log");
         scan_prefix(DiskEngine::new(p.clone())?)?;
         std::fs::remove_dir_all(p.parent().unwrap())?;
+
+        let p = tempfile::tempdir()?.into_path().join("sqldb-lsm");
+        scan_prefix(LsmEngine::new(p.clone())?)?;
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+
+        let p = tempfile::tempdir()?.into_path().join("sqldb-btree");
+        scan_prefix(BTreeEngine::new(p.clone())?)?;
+        std::fs::remove_dir_all(p.parent().unwrap())?;
         Ok(())
     }
 
@@ -507,7 +1032,9 @@ mod tests {
         tx3.delete(b"bcca".to_vec())?;
         tx3.commit()?;
 
-        let iter1 = tx1.scan_prefix(b"aa".to_vec())?;
+        let iter1 = tx1
+            .scan_prefix(b"aa".to_vec())?
+            .collect::<Result<Vec<_>>>()?;
         assert_eq!(
             iter1,
             vec![
@@ -522,7 +1049,9 @@ mod tests {
             ]
         );
 
-        let iter2 = tx1.scan_prefix(b"a".to_vec())?;
+        let iter2 = tx1
+            .scan_prefix(b"a".to_vec())?
+            .collect::<Result<Vec<_>>>()?;
         assert_eq!(
             iter2,
             vec![
@@ -545,7 +1074,9 @@ mod tests {
             ]
         );
 
-        let iter3 = tx1.scan_prefix(b"bcca".to_vec())?;
+        let iter3 = tx1
+            .scan_prefix(b"bcca".to_vec())?
+            .collect::<Result<Vec<_>>>()?;
         assert_eq!(
             iter3,
             vec![super::ScanResult {
@@ -563,6 +1094,170 @@ mod tests {
         let p = tempfile::tempdir()?.into_path().join("sqldb-log");
         scan_isolation(DiskEngine::new(p.clone())?)?;
         std::fs::remove_dir_all(p.parent().unwrap())?;
+
+        let p = tempfile::tempdir()?.into_path().join("sqldb-lsm");
+        scan_isolation(LsmEngine::new(p.clone())?)?;
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+
+        let p = tempfile::tempdir()?.into_path().join("sqldb-btree");
+        scan_isolation(BTreeEngine::new(p.clone())?)?;
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    // 4.1 scan batches
+    fn scan_batches(eng: impl Engine) -> Result<()> {
+        let mvcc = Mvcc::new(eng);
+        // 数据量超过一批，同一个 key 的多个版本可能分布在两批中
+        for round in 0..3 {
+            let tx = mvcc.begin()?;
+            for i in 0..400 {
+                let key = format!("key{:03}", i).into_bytes();
+                if i % 5 == round {
+                    tx.delete(key)?;
+                } else {
+                    tx.set(key, format!("val{}-{}", i, round).into_bytes())?;
+                }
+            }
+            tx.commit()?;
+        }
+        let expected = (0..400)
+            .filter(|i| i % 5 != 2)
+            .map(|i| super::ScanResult {
+                key: format!("key{:03}", i).into_bytes(),
+                value: format!("val{}-2", i).into_bytes(),
+            })
+            .collect::<Vec<_>>();
+
+        let tx1 = mvcc.begin()?;
+        let mut iter = tx1.scan_prefix(b"key".to_vec())?;
+        let mut results = iter.by_ref().take(100).collect::<Result<Vec<_>>>()?;
+
+        // 读取的过程中其他事务修改了数据，对 tx1 不可见
+        let tx2 = mvcc.begin()?;
+        tx2.set(b"key000".to_vec(), b"val-tx2".to_vec())?;
+        tx2.set(b"key399".to_vec(), b"val-tx2".to_vec())?;
+        tx2.delete(b"key398".to_vec())?;
+        tx2.commit()?;
+
+        results.extend(iter.collect::<Result<Vec<_>>>()?);
+        assert_eq!(results, expected);
+
+        // 从后向前读取
+        let mut results = tx1.scan(..)?.rev().collect::<Result<Vec<_>>>()?;
+        results.reverse();
+        assert_eq!(results, expected);
+
+        // 从两端交替读取，在中间相遇
+        let mut iter = tx1.scan(..)?;
+        let (mut front, mut back) = (Vec::new(), Vec::new());
+        while let Some(result) = iter.next().transpose()? {
+            front.push(result);
+            match iter.next_back().transpose()? {
+                Some(result) => back.push(result),
+                None => break,
+            }
+        }
+        back.reverse();
+        front.extend(back);
+        assert_eq!(front, expected);
+        Ok(())
+    }
+
+    // 4.2 scan range
+    fn scan_range(eng: impl Engine) -> Result<()> {
+        let mvcc = Mvcc::new(eng);
+        let tx = mvcc.begin()?;
+        tx.set(b"a".to_vec(), b"val1".to_vec())?;
+        tx.set(b"b".to_vec(), b"val2".to_vec())?;
+        tx.set(b"bb".to_vec(), b"val3".to_vec())?;
+        tx.set(b"c".to_vec(), b"val4".to_vec())?;
+        tx.set(b"d".to_vec(), b"val5".to_vec())?;
+        tx.commit()?;
+
+        let tx1 = mvcc.begin()?;
+        let tx2 = mvcc.begin()?;
+        tx2.set(b"b".to_vec(), b"val2-2".to_vec())?;
+        tx2.delete(b"c".to_vec())?;
+        tx2.set(b"cc".to_vec(), b"val6".to_vec())?;
+
+        fn keys(iter: impl Iterator<Item = Result<super::ScanResult>>) -> Result<Vec<Vec<u8>>> {
+            iter.map(|r| r.map(|r| r.key)).collect()
+        }
+        // tx1 看不到 tx2 的修改
+        assert_eq!(
+            keys(tx1.scan(b"b".to_vec()..b"d".to_vec())?)?,
+            vec![b"b".to_vec(), b"bb".to_vec(), b"c".to_vec()]
+        );
+        assert_eq!(
+            keys(tx1.scan((Bound::Excluded(b"b".to_vec()), Bound::Unbounded))?)?,
+            vec![b"bb".to_vec(), b"c".to_vec(), b"d".to_vec()]
+        );
+        assert_eq!(
+            keys(tx1.scan(..=b"bb".to_vec())?.rev())?,
+            vec![b"bb".to_vec(), b"b".to_vec(), b"a".to_vec()]
+        );
+        // tx2 能看到自己的修改
+        assert_eq!(
+            tx2.scan(b"b".to_vec()..=b"cc".to_vec())?
+                .rev()
+                .collect::<Result<Vec<_>>>()?,
+            vec![
+                super::ScanResult {
+                    key: b"cc".to_vec(),
+                    value: b"val6".to_vec()
+                },
+                super::ScanResult {
+                    key: b"bb".to_vec(),
+                    value: b"val3".to_vec()
+                },
+                super::ScanResult {
+                    key: b"b".to_vec(),
+                    value: b"val2-2".to_vec()
+                },
+            ]
+        );
+        // 空的范围
+        assert!(keys(tx1.scan(b"c".to_vec()..b"b".to_vec())?)?.is_empty());
+        assert!(keys(tx1.scan((
+            Bound::Excluded(b"b".to_vec()),
+            Bound::Excluded(b"b".to_vec())
+        ))?)?
+        .is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_scan_range() -> Result<()> {
+        scan_range(MemoryEngine::new())?;
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        scan_range(DiskEngine::new(p.clone())?)?;
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+
+        let p = tempfile::tempdir()?.into_path().join("sqldb-lsm");
+        scan_range(LsmEngine::new(p.clone())?)?;
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+
+        let p = tempfile::tempdir()?.into_path().join("sqldb-btree");
+        scan_range(BTreeEngine::new(p.clone())?)?;
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_scan_batches() -> Result<()> {
+        scan_batches(MemoryEngine::new())?;
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        scan_batches(DiskEngine::new(p.clone())?)?;
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+
+        let p = tempfile::tempdir()?.into_path().join("sqldb-lsm");
+        scan_batches(LsmEngine::new(p.clone())?)?;
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+
+        let p = tempfile::tempdir()?.into_path().join("sqldb-btree");
+        scan_batches(BTreeEngine::new(p.clone())?)?;
+        std::fs::remove_dir_all(p.parent().unwrap())?;
         Ok(())
     }
 
@@ -604,6 +1299,14 @@ mod tests {
         let p = tempfile::tempdir()?.into_path().join("sqldb-log");
         set(DiskEngine::new(p.clone())?)?;
         std::fs::remove_dir_all(p.parent().unwrap())?;
+
+        let p = tempfile::tempdir()?.into_path().join("sqldb-lsm");
+        set(LsmEngine::new(p.clone())?)?;
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+
+        let p = tempfile::tempdir()?.into_path().join("sqldb-btree");
+        set(BTreeEngine::new(p.clone())?)?;
+        std::fs::remove_dir_all(p.parent().unwrap())?;
         Ok(())
     }
 
@@ -648,6 +1351,14 @@ mod tests {
         let p = tempfile::tempdir()?.into_path().join("sqldb-log");
         set_conflict(DiskEngine::new(p.clone())?)?;
         std::fs::remove_dir_all(p.parent().unwrap())?;
+
+        let p = tempfile::tempdir()?.into_path().join("sqldb-lsm");
+        set_conflict(LsmEngine::new(p.clone())?)?;
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+
+        let p = tempfile::tempdir()?.into_path().join("sqldb-btree");
+        set_conflict(BTreeEngine::new(p.clone())?)?;
+        std::fs::remove_dir_all(p.parent().unwrap())?;
         Ok(())
     }
 
@@ -666,7 +1377,9 @@ mod tests {
         let tx1 = mvcc.begin()?;
         assert_eq!(tx1.get(b"key2".to_vec())?, None);
 
-        let iter = tx1.scan_prefix(b"ke".to_vec())?;
+        let iter = tx1
+            .scan_prefix(b"ke".to_vec())?
+            .collect::<Result<Vec<_>>>()?;
         assert_eq!(
             iter,
             vec![
@@ -689,6 +1402,14 @@ mod tests {
         let p = tempfile::tempdir()?.into_path().join("sqldb-log");
         delete(DiskEngine::new(p.clone())?)?;
         std::fs::remove_dir_all(p.parent().unwrap())?;
+
+        let p = tempfile::tempdir()?.into_path().join("sqldb-lsm");
+        delete(LsmEngine::new(p.clone())?)?;
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+
+        let p = tempfile::tempdir()?.into_path().join("sqldb-btree");
+        delete(BTreeEngine::new(p.clone())?)?;
+        std::fs::remove_dir_all(p.parent().unwrap())?;
         Ok(())
     }
 
@@ -723,6 +1444,14 @@ mod tests {
         let p = tempfile::tempdir()?.into_path().join("sqldb-log");
         delete_conflict(DiskEngine::new(p.clone())?)?;
         std::fs::remove_dir_all(p.parent().unwrap())?;
+
+        let p = tempfile::tempdir()?.into_path().join("sqldb-lsm");
+        delete_conflict(LsmEngine::new(p.clone())?)?;
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+
+        let p = tempfile::tempdir()?.into_path().join("sqldb-btree");
+        delete_conflict(BTreeEngine::new(p.clone())?)?;
+        std::fs::remove_dir_all(p.parent().unwrap())?;
         Ok(())
     }
 
@@ -750,6 +1479,14 @@ mod tests {
         let p = tempfile::tempdir()?.into_path().join("sqldb-log");
         dirty_read(DiskEngine::new(p.clone())?)?;
         std::fs::remove_dir_all(p.parent().unwrap())?;
+
+        let p = tempfile::tempdir()?.into_path().join("sqldb-lsm");
+        dirty_read(LsmEngine::new(p.clone())?)?;
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+
+        let p = tempfile::tempdir()?.into_path().join("sqldb-btree");
+        dirty_read(BTreeEngine::new(p.clone())?)?;
+        std::fs::remove_dir_all(p.parent().unwrap())?;
         Ok(())
     }
 
@@ -779,6 +1516,14 @@ mod tests {
         let p = tempfile::tempdir()?.into_path().join("sqldb-log");
         unrepeatable_read(DiskEngine::new(p.clone())?)?;
         std::fs::remove_dir_all(p.parent().unwrap())?;
+
+        let p = tempfile::tempdir()?.into_path().join("sqldb-lsm");
+        unrepeatable_read(LsmEngine::new(p.clone())?)?;
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+
+        let p = tempfile::tempdir()?.into_path().join("sqldb-btree");
+        unrepeatable_read(BTreeEngine::new(p.clone())?)?;
+        std::fs::remove_dir_all(p.parent().unwrap())?;
         Ok(())
     }
 
@@ -794,7 +1539,9 @@ mod tests {
         let tx1 = mvcc.begin()?;
         let tx2 = mvcc.begin()?;
 
-        let iter1 = tx1.scan_prefix(b"key".to_vec())?;
+        let iter1 = tx1
+            .scan_prefix(b"key".to_vec())?
+            .collect::<Result<Vec<_>>>()?;
         assert_eq!(
             iter1,
             vec![
@@ -817,7 +1564,9 @@ mod tests {
         tx2.set(b"key4".to_vec(), b"val4".to_vec())?;
         tx2.commit()?;
 
-        let iter1 = tx1.scan_prefix(b"key".to_vec())?;
+        let iter1 = tx1
+            .scan_prefix(b"key".to_vec())?
+            .collect::<Result<Vec<_>>>()?;
         assert_eq!(
             iter1,
             vec![
@@ -844,6 +1593,14 @@ mod tests {
         let p = tempfile::tempdir()?.into_path().join("sqldb-log");
         phantom_read(DiskEngine::new(p.clone())?)?;
         std::fs::remove_dir_all(p.parent().unwrap())?;
+
+        let p = tempfile::tempdir()?.into_path().join("sqldb-lsm");
+        phantom_read(LsmEngine::new(p.clone())?)?;
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+
+        let p = tempfile::tempdir()?.into_path().join("sqldb-btree");
+        phantom_read(BTreeEngine::new(p.clone())?)?;
+        std::fs::remove_dir_all(p.parent().unwrap())?;
         Ok(())
     }
 
@@ -876,6 +1633,237 @@ mod tests {
         let p = tempfile::tempdir()?.into_path().join("sqldb-log");
         rollback(DiskEngine::new(p.clone())?)?;
         std::fs::remove_dir_all(p.parent().unwrap())?;
+
+        let p = tempfile::tempdir()?.into_path().join("sqldb-lsm");
+        rollback(LsmEngine::new(p.clone())?)?;
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+
+        let p = tempfile::tempdir()?.into_path().join("sqldb-btree");
+        rollback(BTreeEngine::new(p.clone())?)?;
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    // 13. read only
+    fn read_only(eng: impl Engine) -> Result<()> {
+        let mvcc = Mvcc::new(eng);
+        let tx = mvcc.begin()?;
+        tx.set(b"key1".to_vec(), b"val1".to_vec())?;
+        tx.set(b"key2".to_vec(), b"val2".to_vec())?;
+        tx.commit()?;
+
+        let tx1 = mvcc.begin()?;
+        tx1.set(b"key1".to_vec(), b"val1-1".to_vec())?;
+
+        // 只读事务看不到未提交的事务
+        let ro = mvcc.begin_read_only()?;
+        assert!(ro.read_only());
+        assert_eq!(ro.get(b"key1".to_vec())?, Some(b"val1".to_vec()));
+        assert_eq!(ro.set(b"key3".to_vec(), vec![]), Err(Error::ReadOnly));
+        assert_eq!(ro.delete(b"key1".to_vec()), Err(Error::ReadOnly));
+        tx1.commit()?;
+        assert_eq!(ro.get(b"key1".to_vec())?, Some(b"val1".to_vec()));
+
+        // 只读事务不会分配新的版本号
+        let tx2 = mvcc.begin()?;
+        assert_eq!(tx2.version(), ro.version());
+        tx2.set(b"key2".to_vec(), b"val2-1".to_vec())?;
+        assert_eq!(ro.get(b"key2".to_vec())?, Some(b"val2".to_vec()));
+        // 只读事务提交不会影响其他事务
+        ro.commit()?;
+        tx2.commit()?;
+
+        let tx3 = mvcc.begin_read_only()?;
+        assert_eq!(tx3.get(b"key1".to_vec())?, Some(b"val1-1".to_vec()));
+        assert_eq!(tx3.get(b"key2".to_vec())?, Some(b"val2-1".to_vec()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_only() -> Result<()> {
+        read_only(MemoryEngine::new())?;
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        read_only(DiskEngine::new(p.clone())?)?;
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+
+        let p = tempfile::tempdir()?.into_path().join("sqldb-lsm");
+        read_only(LsmEngine::new(p.clone())?)?;
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+
+        let p = tempfile::tempdir()?.into_path().join("sqldb-btree");
+        read_only(BTreeEngine::new(p.clone())?)?;
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    // 14. as of
+    fn as_of(eng: impl Engine) -> Result<()> {
+        let mvcc = Mvcc::new(eng);
+        let tx = mvcc.begin()?;
+        tx.set(b"key1".to_vec(), b"val1".to_vec())?;
+        tx.set(b"key2".to_vec(), b"val2".to_vec())?;
+        tx.commit()?;
+
+        let tx1 = mvcc.begin()?;
+        tx1.set(b"key1".to_vec(), b"val1-1".to_vec())?;
+
+        // tx2 开始时 tx1 还没有提交
+        let tx2 = mvcc.begin()?;
+        tx2.delete(b"key2".to_vec())?;
+        tx1.commit()?;
+        tx2.commit()?;
+
+        let tx3 = mvcc.begin()?;
+        tx3.set(b"key3".to_vec(), b"val3".to_vec())?;
+        tx3.commit()?;
+
+        // 读取 tx1 开始时的数据
+        let ro = mvcc.begin_as_of(tx1.version())?;
+        assert_eq!(ro.get(b"key1".to_vec())?, Some(b"val1".to_vec()));
+        assert_eq!(ro.get(b"key2".to_vec())?, Some(b"val2".to_vec()));
+        assert_eq!(ro.set(b"key1".to_vec(), vec![]), Err(Error::ReadOnly));
+
+        // 读取 tx2 开始时的数据，tx1 的修改不可见
+        let ro = mvcc.begin_as_of(tx2.version())?;
+        assert_eq!(ro.get(b"key1".to_vec())?, Some(b"val1".to_vec()));
+        assert_eq!(ro.get(b"key2".to_vec())?, Some(b"val2".to_vec()));
+
+        // 读取 tx3 开始时的数据
+        let ro = mvcc.begin_as_of(tx3.version())?;
+        assert_eq!(ro.get(b"key1".to_vec())?, Some(b"val1-1".to_vec()));
+        assert_eq!(ro.get(b"key2".to_vec())?, None);
+        assert_eq!(
+            ro.scan_prefix(b"key".to_vec())?
+                .collect::<Result<Vec<_>>>()?,
+            vec![super::ScanResult {
+                key: b"key1".to_vec(),
+                value: b"val1-1".to_vec()
+            }]
+        );
+
+        // 不存在的版本
+        assert!(mvcc.begin_as_of(100).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_as_of() -> Result<()> {
+        as_of(MemoryEngine::new())?;
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        as_of(DiskEngine::new(p.clone())?)?;
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+
+        let p = tempfile::tempdir()?.into_path().join("sqldb-lsm");
+        as_of(LsmEngine::new(p.clone())?)?;
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+
+        let p = tempfile::tempdir()?.into_path().join("sqldb-btree");
+        as_of(BTreeEngine::new(p.clone())?)?;
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+        Ok(())
+    }
+
+    // 15. gc
+    fn gc(eng: impl Engine) -> Result<()> {
+        let mvcc = Mvcc::new(eng);
+        assert_eq!(mvcc.gc()?.versions, 0);
+
+        let tx = mvcc.begin()?;
+        tx.set(b"key1".to_vec(), b"val1".to_vec())?;
+        tx.set(b"key2".to_vec(), b"val2".to_vec())?;
+        tx.set(b"key3".to_vec(), b"val3".to_vec())?;
+        tx.commit()?;
+
+        let tx1 = mvcc.begin()?;
+        tx1.set(b"key1".to_vec(), b"val1-1".to_vec())?;
+        tx1.delete(b"key2".to_vec())?;
+        tx1.commit()?;
+
+        // 只读事务能看到 tx1 提交的数据
+        let ro = mvcc.begin_read_only()?;
+
+        let tx2 = mvcc.begin()?;
+        tx2.set(b"key1".to_vec(), b"val1-2".to_vec())?;
+        tx2.commit()?;
+
+        // tx3 还没有提交
+        let tx3 = mvcc.begin()?;
+        tx3.set(b"key3".to_vec(), b"val3-3".to_vec())?;
+
+        // 只有 key1、key2 的第一个版本和 key2 的删除标记会被清理
+        let stats = mvcc.gc()?;
+        assert_eq!(stats.versions, 3);
+        assert!(stats.bytes > 0);
+        assert_eq!(ro.get(b"key1".to_vec())?, Some(b"val1-1".to_vec()));
+        assert_eq!(ro.get(b"key2".to_vec())?, None);
+        assert_eq!(ro.get(b"key3".to_vec())?, Some(b"val3".to_vec()));
+        assert_eq!(mvcc.gc()?.versions, 0);
+
+        drop(ro);
+        tx3.commit()?;
+
+        // key1、key3 被覆盖的版本会被清理
+        assert_eq!(mvcc.gc()?.versions, 2);
+        let tx4 = mvcc.begin()?;
+        assert_eq!(
+            tx4.scan_prefix(b"key".to_vec())?
+                .collect::<Result<Vec<_>>>()?,
+            vec![
+                super::ScanResult {
+                    key: b"key1".to_vec(),
+                    value: b"val1-2".to_vec()
+                },
+                super::ScanResult {
+                    key: b"key3".to_vec(),
+                    value: b"val3-3".to_vec()
+                },
+            ]
+        );
+        tx4.commit()?;
+
+        // 历史版本已经被清理，无法读取
+        assert!(mvcc.begin_as_of(tx1.version()).is_err());
+        assert!(mvcc.begin_as_of(tx4.version()).is_ok());
+
+        // 数据超过一批时分批清理，活跃事务列表的快照单独统计
+        let tx5 = mvcc.begin()?;
+        for i in 0..1200 {
+            tx5.set(format!("batch{:04}", i).into_bytes(), b"v5".to_vec())?;
+        }
+        let tx6 = mvcc.begin()?;
+        tx5.commit()?;
+        tx6.commit()?;
+        let tx7 = mvcc.begin()?;
+        for i in 0..1200 {
+            tx7.set(format!("batch{:04}", i).into_bytes(), b"v7".to_vec())?;
+        }
+        tx7.commit()?;
+        let stats = mvcc.gc()?;
+        assert_eq!(stats.versions, 1200);
+        assert_eq!(stats.snapshots, 1);
+        assert_eq!(mvcc.gc()?, GcStats::default());
+        let tx8 = mvcc.begin()?;
+        assert_eq!(tx8.scan_prefix(b"batch".to_vec())?.count(), 1200);
+        assert_eq!(tx8.get(b"batch0999".to_vec())?, Some(b"v7".to_vec()));
+        tx8.commit()?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_gc() -> Result<()> {
+        gc(MemoryEngine::new())?;
+        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        gc(DiskEngine::new(p.clone())?)?;
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+
+        let p = tempfile::tempdir()?.into_path().join("sqldb-lsm");
+        gc(LsmEngine::new(p.clone())?)?;
+        std::fs::remove_dir_all(p.parent().unwrap())?;
+
+        let p = tempfile::tempdir()?.into_path().join("sqldb-btree");
+        gc(BTreeEngine::new(p.clone())?)?;
+        std::fs::remove_dir_all(p.parent().unwrap())?;
         Ok(())
     }
 }